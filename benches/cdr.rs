@@ -0,0 +1,90 @@
+//! Cost of turning a CompressedVideo sample into an `EncodedFrame`, copying
+//! the frame data out of the payload versus sharing it in place.
+//!
+//! ```sh
+//! cargo bench --bench cdr
+//! ```
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use zenoh::bytes::ZBytes;
+
+use video_zenoh_player::cdr::{self, DecodeErrors, Schema};
+use video_zenoh_player::frame::FrameData;
+use video_zenoh_player::zenoh_sub;
+
+/// How long each case runs.
+const RUN_TIME: Duration = Duration::from_millis(500);
+
+/// Frame sizes from a P-frame at a low bitrate to a 4K keyframe at a high
+/// one.
+const SIZES: [(&str, usize); 4] = [
+    ("16 KiB", 16 << 10),
+    ("256 KiB", 256 << 10),
+    ("1 MiB", 1 << 20),
+    ("4 MiB", 4 << 20),
+];
+
+fn main() {
+    println!(
+        "{:>8}  {:>12} {:>10}  {:>12} {:>10}  {:>7}",
+        "frame", "copy", "GB/s", "in place", "GB/s", "speedup"
+    );
+    for (label, size) in SIZES {
+        let payload = ZBytes::from(compressed_video(size));
+        let errors = DecodeErrors::default();
+
+        // What the subscriber did before: an owned message per sample.
+        let copy = time(|| {
+            let bytes = payload.to_bytes();
+            let message = cdr::decode_compressed_video(&bytes).expect("valid message");
+            FrameData::from(message.data)
+        });
+        let in_place = time(|| {
+            zenoh_sub::decode_payload("bench", 0, "bench", &payload, None, Schema::Auto, &errors)
+                .expect("a frame")
+        });
+
+        let rate = |t: Duration| size as f64 / t.as_secs_f64() / 1e9;
+        println!(
+            "{label:>8}  {:>12?} {:>10.1}  {:>12?} {:>10.1}  {:>6.1}x",
+            copy,
+            rate(copy),
+            in_place,
+            rate(in_place),
+            copy.as_secs_f64() / in_place.as_secs_f64()
+        );
+    }
+}
+
+/// Mean time of `f` over `RUN_TIME`.
+fn time<T>(mut f: impl FnMut() -> T) -> Duration {
+    let start = Instant::now();
+    let mut runs = 0;
+    while start.elapsed() < RUN_TIME {
+        black_box(f());
+        runs += 1;
+    }
+    start.elapsed() / runs
+}
+
+/// A little-endian CDR CompressedVideo message with `size` bytes of H.264.
+fn compressed_video(size: usize) -> Vec<u8> {
+    let mut data = vec![0x5a; size];
+    data[..5].copy_from_slice(&[0, 0, 0, 1, 0x65]);
+
+    let mut buf = vec![0, 1, 0, 0];
+    let align = |buf: &mut Vec<u8>| buf.resize(4 + (buf.len() - 4).next_multiple_of(4), 0);
+    let sequence = |buf: &mut Vec<u8>, bytes: &[u8]| {
+        align(buf);
+        buf.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+        buf.extend_from_slice(bytes);
+    };
+    buf.extend_from_slice(&1_700_000_000u32.to_le_bytes());
+    buf.extend_from_slice(&0u32.to_le_bytes());
+    sequence(&mut buf, b"camera\0");
+    sequence(&mut buf, &data);
+    sequence(&mut buf, b"h264\0");
+    buf
+}
@@ -1,9 +1,12 @@
-use serde::Deserialize;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::codec::Codec;
+use crate::images::{RawEncoding, RawLayout};
 
 /// A timestamp, represented as an offset from a user-defined epoch.
 /// <https://docs.foxglove.dev/docs/visualization/message-schemas/built-in-types#time>
-#[derive(Debug, Deserialize)]
-#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Timestamp {
     /// Seconds since epoch.
     pub sec: u32,
@@ -11,10 +14,21 @@ pub struct Timestamp {
     pub nsec: u32,
 }
 
+impl Timestamp {
+    /// Total nanoseconds since epoch.
+    pub fn as_nanos(&self) -> u64 {
+        self.sec as u64 * 1_000_000_000 + self.nsec as u64
+    }
+
+    /// Publishers that don't fill in the timestamp leave it at zero.
+    pub fn is_zero(&self) -> bool {
+        self.sec == 0 && self.nsec == 0
+    }
+}
+
 /// A single frame of a compressed video bitstream.
 /// <https://docs.foxglove.dev/docs/visualization/message-schemas/compressed-video>
-#[derive(Debug, Deserialize)]
-#[allow(dead_code)]
+#[derive(Debug)]
 pub struct CompressedVideo {
     /// Timestamp of video frame.
     pub timestamp: Timestamp,
@@ -26,19 +40,1135 @@ pub struct CompressedVideo {
     pub format: String,
 }
 
+/// A [`CompressedVideo`] (or [`CompressedImage`]) read in place: the
+/// strings and the frame data borrow from the payload, so parsing costs the
+/// same for a 4K keyframe as for a small P-frame.
+#[derive(Debug, Clone, Copy)]
+pub struct CompressedVideoView<'a> {
+    pub timestamp: Timestamp,
+    pub frame_id: &'a str,
+    pub data: &'a [u8],
+    pub format: &'a str,
+}
+
+impl<'a> CompressedVideoView<'a> {
+    /// Read a CDR-encoded message, with the same requirements as
+    /// [`decode_compressed_video`].
+    pub fn parse(buf: &'a [u8]) -> Result<Self, DecodeError> {
+        Self::read(&mut Reader::new(buf)?)
+    }
+
+    fn read(r: &mut Reader<'a>) -> Result<Self, DecodeError> {
+        Ok(Self {
+            timestamp: Timestamp::decode(r)?,
+            frame_id: r.str()?,
+            data: r.bytes()?,
+            format: r.str()?,
+        })
+    }
+
+    /// CompressedImage for image formats, CompressedVideo otherwise.
+    pub fn schema(&self) -> Schema {
+        if Codec::from_format(self.format).is_some_and(Codec::is_image) {
+            Schema::CompressedImage
+        } else {
+            Schema::CompressedVideo
+        }
+    }
+}
+
+impl From<CompressedVideoView<'_>> for CompressedVideo {
+    fn from(view: CompressedVideoView<'_>) -> Self {
+        Self {
+            timestamp: view.timestamp,
+            frame_id: view.frame_id.to_string(),
+            data: view.data.to_vec(),
+            format: view.format.to_string(),
+        }
+    }
+}
+
+/// A compressed image. Same layout as [`CompressedVideo`]; the `format`
+/// tells them apart.
+/// <https://docs.foxglove.dev/docs/visualization/message-schemas/compressed-image>
+#[derive(Debug)]
+pub struct CompressedImage {
+    /// Timestamp of image.
+    pub timestamp: Timestamp,
+    /// Frame of reference for the image.
+    pub frame_id: String,
+    /// Compressed image data.
+    pub data: Vec<u8>,
+    /// Image format (e.g. "jpeg", "png", "webp").
+    pub format: String,
+}
+
+/// A raw image.
+/// <https://docs.foxglove.dev/docs/visualization/message-schemas/raw-image>
+#[derive(Debug)]
+pub struct RawImage {
+    /// Timestamp of image.
+    pub timestamp: Timestamp,
+    /// Frame of reference for the image.
+    pub frame_id: String,
+    /// Image width in pixels.
+    pub width: u32,
+    /// Image height in pixels.
+    pub height: u32,
+    /// Encoding of the raw image data (e.g. "rgb8", "mono16", "bayer_rggb8").
+    pub encoding: String,
+    /// Byte length of a single row.
+    pub step: u32,
+    /// Raw image data.
+    pub data: Vec<u8>,
+}
+
+impl RawImage {
+    /// Whether the header agrees with the data, whatever the encoding: a
+    /// message of another schema that happens to deserialize rarely does.
+    pub fn is_consistent(&self) -> bool {
+        raw_is_consistent(self.width, self.height, self.step, self.data.len())
+    }
+}
+
+/// ROS 2 `builtin_interfaces/Time`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RosTime {
+    pub sec: i32,
+    pub nanosec: u32,
+}
+
+impl From<RosTime> for Timestamp {
+    /// Times before the epoch become zero, i.e. unset.
+    fn from(time: RosTime) -> Self {
+        if time.sec < 0 {
+            return Self { sec: 0, nsec: 0 };
+        }
+        Self {
+            sec: time.sec as u32,
+            nsec: time.nanosec,
+        }
+    }
+}
+
+/// ROS 2 `std_msgs/Header`.
+#[derive(Debug)]
+pub struct RosHeader {
+    pub stamp: RosTime,
+    pub frame_id: String,
+}
+
+/// ROS 2 `sensor_msgs/CompressedImage`, as zenoh-bridge-ros2dds forwards it.
+/// <https://docs.ros2.org/latest/api/sensor_msgs/msg/CompressedImage.html>
+#[derive(Debug)]
+pub struct RosCompressedImage {
+    pub header: RosHeader,
+    /// "jpeg" or "png", or as image_transport writes it, e.g.
+    /// "bgr8; jpeg compressed bgr8" or "16UC1; compressedDepth png".
+    pub format: String,
+    pub data: Vec<u8>,
+}
+
+impl RosCompressedImage {
+    /// Image codec named in `format`.
+    pub fn codec(&self) -> Option<Codec> {
+        let format = self.format.to_ascii_lowercase();
+        if format.contains("jpeg") || format.contains("jpg") {
+            Some(Codec::Jpeg)
+        } else if format.contains("png") {
+            Some(Codec::Png)
+        } else {
+            Codec::from_format(&format)
+        }
+    }
+
+    /// Where the image starts in `data`: compressedDepth images carry a
+    /// 12-byte depth quantization header before the PNG.
+    fn image_offset(&self) -> usize {
+        if self.format.contains("compressedDepth") {
+            COMPRESSED_DEPTH_HEADER.min(self.data.len())
+        } else {
+            0
+        }
+    }
+}
+
+/// Size of the `ConfigHeader` of image_transport's compressedDepth format.
+const COMPRESSED_DEPTH_HEADER: usize = 12;
+
+/// ROS 2 `sensor_msgs/Image`.
+/// <https://docs.ros2.org/latest/api/sensor_msgs/msg/Image.html>
+#[derive(Debug)]
+pub struct RosImage {
+    pub header: RosHeader,
+    pub height: u32,
+    pub width: u32,
+    /// Pixel encoding, e.g. "rgb8", "mono16", "bayer_rggb8".
+    pub encoding: String,
+    /// Non-zero if 16-bit samples are big-endian.
+    pub is_bigendian: u8,
+    /// Full row length in bytes.
+    pub step: u32,
+    pub data: Vec<u8>,
+}
+
+impl RosImage {
+    /// See [`RawImage::is_consistent`].
+    pub fn is_consistent(&self) -> bool {
+        self.is_bigendian <= 1
+            && raw_is_consistent(self.width, self.height, self.step, self.data.len())
+    }
+}
+
+/// Whether `len` bytes can hold `height` rows of `step` bytes with at least
+/// one byte per pixel.
+fn raw_is_consistent(width: u32, height: u32, step: u32, len: usize) -> bool {
+    let (width, height, step) = (width as u64, height as u64, step as u64);
+    width > 0 && height > 0 && step >= width && len as u64 >= step * (height - 1) + width
+}
+
+/// Message layouts the player decodes, for `--schema`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, clap::ValueEnum)]
+pub enum Schema {
+    /// Detect from the message layout, per message.
+    #[default]
+    Auto,
+    /// foxglove.CompressedVideo
+    CompressedVideo,
+    /// foxglove.CompressedImage
+    CompressedImage,
+    /// foxglove.RawImage
+    RawImage,
+    /// sensor_msgs/msg/CompressedImage (ROS 2)
+    RosCompressedImage,
+    /// sensor_msgs/msg/Image (ROS 2)
+    RosImage,
+    /// Bare H.264 Annex B without a CDR message around it; never detected
+    AnnexB,
+}
+
+impl std::fmt::Display for Schema {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Auto => "auto",
+            Self::CompressedVideo => "CompressedVideo",
+            Self::CompressedImage => "CompressedImage",
+            Self::RawImage => "RawImage",
+            Self::RosCompressedImage => "sensor_msgs/CompressedImage",
+            Self::RosImage => "sensor_msgs/Image",
+            Self::AnnexB => "H.264 Annex B",
+        })
+    }
+}
+
+/// A message of any schema the player shows.
+#[derive(Debug)]
+pub enum Message {
+    Video(CompressedVideo),
+    Image(CompressedImage),
+    Raw(RawImage),
+    RosCompressedImage(RosCompressedImage),
+    RosImage(RosImage),
+    /// A bare H.264 access unit, see [`Schema::AnnexB`].
+    AnnexB(Vec<u8>),
+}
+
+impl Message {
+    /// A CompressedVideo-layout message: video or image by its `format`.
+    fn compressed(video: CompressedVideo) -> Self {
+        if Codec::from_format(&video.format).is_some_and(Codec::is_image) {
+            Self::Image(CompressedImage {
+                timestamp: video.timestamp,
+                frame_id: video.frame_id,
+                data: video.data,
+                format: video.format,
+            })
+        } else {
+            Self::Video(video)
+        }
+    }
+
+    pub fn schema(&self) -> Schema {
+        match self {
+            Self::Video(_) => Schema::CompressedVideo,
+            Self::Image(_) => Schema::CompressedImage,
+            Self::Raw(_) => Schema::RawImage,
+            Self::RosCompressedImage(_) => Schema::RosCompressedImage,
+            Self::RosImage(_) => Schema::RosImage,
+            Self::AnnexB(_) => Schema::AnnexB,
+        }
+    }
+
+    pub fn timestamp(&self) -> Timestamp {
+        match self {
+            Self::Video(m) => m.timestamp,
+            Self::Image(m) => m.timestamp,
+            Self::Raw(m) => m.timestamp,
+            Self::RosCompressedImage(m) => m.header.stamp.into(),
+            Self::RosImage(m) => m.header.stamp.into(),
+            Self::AnnexB(_) => Timestamp { sec: 0, nsec: 0 },
+        }
+    }
+
+    /// `format` of compressed messages, `encoding` of raw images.
+    pub fn format(&self) -> &str {
+        match self {
+            Self::Video(m) => &m.format,
+            Self::Image(m) => &m.format,
+            Self::Raw(m) => &m.encoding,
+            Self::RosCompressedImage(m) => &m.format,
+            Self::RosImage(m) => &m.encoding,
+            Self::AnnexB(_) => "h264",
+        }
+    }
+
+    /// Codec of the payload, `None` for formats the player cannot decode.
+    pub fn codec(&self) -> Option<Codec> {
+        match self {
+            Self::Video(m) => Codec::from_format(&m.format),
+            Self::Image(m) => Codec::from_format(&m.format),
+            Self::RosCompressedImage(m) => m.codec(),
+            Self::Raw(_) | Self::RosImage(_) => Some(Codec::Raw),
+            Self::AnnexB(_) => Some(Codec::H264),
+        }
+    }
+
+    /// Pixel layout of raw images with a known encoding.
+    pub fn raw_layout(&self) -> Option<RawLayout> {
+        match self {
+            Self::Raw(m) => Some(RawLayout {
+                encoding: RawEncoding::from_encoding(&m.encoding)?,
+                width: m.width,
+                height: m.height,
+                step: m.step,
+                big_endian: false,
+            }),
+            Self::RosImage(m) => Some(RawLayout {
+                encoding: RawEncoding::from_encoding(&m.encoding)?,
+                width: m.width,
+                height: m.height,
+                step: m.step,
+                big_endian: m.is_bigendian != 0,
+            }),
+            _ => None,
+        }
+    }
+
+    /// The encoded frame or image.
+    pub fn data(&self) -> &[u8] {
+        match self {
+            Self::Video(m) => &m.data,
+            Self::Image(m) => &m.data,
+            Self::Raw(m) => &m.data,
+            Self::RosCompressedImage(m) => &m.data[m.image_offset()..],
+            Self::RosImage(m) => &m.data,
+            Self::AnnexB(data) => data,
+        }
+    }
+
+    /// `frame_id` and [`data`](Self::data), taken out of the message.
+    pub fn into_parts(self) -> (String, Vec<u8>) {
+        match self {
+            Self::Video(m) => (m.frame_id, m.data),
+            Self::Image(m) => (m.frame_id, m.data),
+            Self::Raw(m) => (m.frame_id, m.data),
+            Self::RosCompressedImage(mut m) => {
+                m.data.drain(..m.image_offset());
+                (m.header.frame_id, m.data)
+            }
+            Self::RosImage(m) => (m.header.frame_id, m.data),
+            Self::AnnexB(data) => (String::new(), data),
+        }
+    }
+}
+
+/// CDR representation of a message: the representation identifier in the
+/// first two bytes of its encapsulation header (DDS-XTypes 1.3, 7.6.3.1.2,
+/// where XCDR2 is 0x0006–0x000b). The 0x0010–0x0015 identifiers of
+/// XTypes 1.2 are still accepted.
+///
+/// Plain CDR (XCDR1), PLAIN_CDR2 and DELIMITED_CDR2 are read in either byte
+/// order. The parameter-list forms only serve mutable types, which no
+/// supported schema is, and are rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Encapsulation {
+    CdrBe,
+    CdrLe,
+    PlCdrBe,
+    PlCdrLe,
+    Cdr2Be,
+    Cdr2Le,
+    PlCdr2Be,
+    PlCdr2Le,
+    DCdr2Be,
+    DCdr2Le,
+}
+
+impl Encapsulation {
+    pub fn from_id(id: [u8; 2]) -> Option<Self> {
+        match u16::from_be_bytes(id) {
+            0x0000 => Some(Self::CdrBe),
+            0x0001 => Some(Self::CdrLe),
+            0x0002 => Some(Self::PlCdrBe),
+            0x0003 => Some(Self::PlCdrLe),
+            0x0006 | 0x0010 => Some(Self::Cdr2Be),
+            0x0007 | 0x0011 => Some(Self::Cdr2Le),
+            0x0008 | 0x0014 => Some(Self::DCdr2Be),
+            0x0009 | 0x0015 => Some(Self::DCdr2Le),
+            0x000a | 0x0012 => Some(Self::PlCdr2Be),
+            0x000b | 0x0013 => Some(Self::PlCdr2Le),
+            _ => None,
+        }
+    }
+
+    /// Representation named by the header at the start of `buf`.
+    pub fn from_header(buf: &[u8]) -> Option<Self> {
+        Self::from_id(*buf.first_chunk()?)
+    }
+
+    pub fn is_little_endian(self) -> bool {
+        matches!(
+            self,
+            Self::CdrLe | Self::PlCdrLe | Self::Cdr2Le | Self::PlCdr2Le | Self::DCdr2Le
+        )
+    }
+
+    /// Members are sent as a parameter list, each with its id and length.
+    pub fn is_parameter_list(self) -> bool {
+        matches!(
+            self,
+            Self::PlCdrBe | Self::PlCdrLe | Self::PlCdr2Be | Self::PlCdr2Le
+        )
+    }
+
+    /// The top-level struct starts with a DHEADER holding its size.
+    pub fn is_delimited(self) -> bool {
+        matches!(self, Self::DCdr2Be | Self::DCdr2Le)
+    }
+
+    /// Name from the XTypes specification, e.g. "CDR_LE" or "D_CDR2_BE".
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::CdrBe => "CDR_BE",
+            Self::CdrLe => "CDR_LE",
+            Self::PlCdrBe => "PL_CDR_BE",
+            Self::PlCdrLe => "PL_CDR_LE",
+            Self::Cdr2Be => "CDR2_BE",
+            Self::Cdr2Le => "CDR2_LE",
+            Self::PlCdr2Be => "PL_CDR2_BE",
+            Self::PlCdr2Le => "PL_CDR2_LE",
+            Self::DCdr2Be => "D_CDR2_BE",
+            Self::DCdr2Le => "D_CDR2_LE",
+        }
+    }
+}
+
+impl std::fmt::Display for Encapsulation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Why a payload is not a message the player can show.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The payload ends inside a field: `len` bytes, `needed` to read it.
+    Truncated { len: usize, needed: usize },
+    /// The encapsulation header names no CDR representation, or one the
+    /// player cannot read (see [`Encapsulation`]).
+    BadEncapsulation([u8; 2]),
+    /// A string or sequence is longer than the bytes left.
+    LengthOverflow { length: u32, remaining: usize },
+    /// The message decoded, but names a format or encoding the player has
+    /// no decoder for.
+    UnknownFormat(String),
+    /// The bytes decode, but not to a sensible message of the schema.
+    SchemaMismatch(String),
+}
+
+impl DecodeError {
+    /// Labels of the error classes, see [`class`](Self::class).
+    pub const CLASSES: [&str; 5] = ["truncated", "encapsulation", "length", "format", "schema"];
+
+    /// Short label of the error's class, for metrics and counters.
+    pub fn class(&self) -> &'static str {
+        Self::CLASSES[self.class_index()]
+    }
+
+    fn class_index(&self) -> usize {
+        match self {
+            Self::Truncated { .. } => 0,
+            Self::BadEncapsulation(_) => 1,
+            Self::LengthOverflow { .. } => 2,
+            Self::UnknownFormat(_) => 3,
+            Self::SchemaMismatch(_) => 4,
+        }
+    }
+
+    fn inconsistent(width: u32, height: u32, step: u32, len: usize) -> Self {
+        Self::SchemaMismatch(format!(
+            "{width}×{height} image with step {step} does not match {len} bytes of data"
+        ))
+    }
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Truncated { len, needed } => {
+                write!(f, "truncated: {len} bytes, needed {needed}")
+            }
+            Self::BadEncapsulation(id) => match Encapsulation::from_id(*id) {
+                Some(encapsulation) => write!(f, "unsupported encapsulation {encapsulation}"),
+                None => write!(f, "unknown encapsulation 0x{:02x}{:02x}", id[0], id[1]),
+            },
+            Self::LengthOverflow { length, remaining } => {
+                write!(f, "length {length} overflows the {remaining} bytes left")
+            }
+            Self::UnknownFormat(format) => write!(f, "unsupported format '{format}'"),
+            Self::SchemaMismatch(reason) => write!(f, "schema mismatch: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Payloads of one stream that failed to decode, by error class; readable
+/// from anywhere, like [`Drops`](crate::queue::Drops).
+#[derive(Debug, Clone, Default)]
+pub struct DecodeErrors(Arc<[AtomicU64; DecodeError::CLASSES.len()]>);
+
+impl DecodeErrors {
+    pub fn count(&self, error: &DecodeError) {
+        self.0[error.class_index()].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Failures of each class in [`DecodeError::CLASSES`] order.
+    pub fn get(&self) -> [(&'static str, u64); DecodeError::CLASSES.len()] {
+        std::array::from_fn(|i| (DecodeError::CLASSES[i], self.0[i].load(Ordering::Relaxed)))
+    }
+
+    pub fn total(&self) -> u64 {
+        self.0.iter().map(|n| n.load(Ordering::Relaxed)).sum()
+    }
+}
+
+/// Decode a message of the given `schema`, or with [`Schema::Auto`]
+/// whichever CDR layout it turns out to have.
+///
+/// Foxglove and ROS messages all start with a time and a frame id, so
+/// detection tries each layout and keeps the first that makes sense:
+/// compressed messages must name a known format, raw images must have rows
+/// that fit their data. A compressed message with an unknown format is
+/// returned as CompressedVideo, for the caller to report. Bare Annex B is
+/// never detected, only taken when asked for.
+pub fn decode_message(buf: &[u8], schema: Schema) -> Result<Message, DecodeError> {
+    match schema {
+        Schema::Auto => detect(buf),
+        Schema::CompressedVideo => decode(buf).map(Message::Video),
+        Schema::CompressedImage => decode(buf).map(Message::Image),
+        Schema::RawImage => match decode::<RawImage>(buf)? {
+            image if image.is_consistent() => Ok(Message::Raw(image)),
+            image => Err(DecodeError::inconsistent(
+                image.width,
+                image.height,
+                image.step,
+                image.data.len(),
+            )),
+        },
+        Schema::RosCompressedImage => decode(buf).map(Message::RosCompressedImage),
+        Schema::RosImage => match decode::<RosImage>(buf)? {
+            image if image.is_consistent() => Ok(Message::RosImage(image)),
+            image => Err(DecodeError::inconsistent(
+                image.width,
+                image.height,
+                image.step,
+                image.data.len(),
+            )),
+        },
+        Schema::AnnexB => {
+            if !(buf.starts_with(&[0, 0, 1]) || buf.starts_with(&[0, 0, 0, 1])) {
+                return Err(DecodeError::SchemaMismatch(
+                    "no Annex B start code".to_string(),
+                ));
+            }
+            Ok(Message::AnnexB(buf.to_vec()))
+        }
+    }
+}
+
+fn detect(buf: &[u8]) -> Result<Message, DecodeError> {
+    let video = match decode_compressed_video(buf) {
+        Ok(video) if Codec::from_format(&video.format).is_some() => {
+            return Ok(Message::compressed(video));
+        }
+        other => other,
+    };
+    if let Ok(image) = decode::<RosCompressedImage>(buf)
+        && image.codec().is_some()
+    {
+        return Ok(Message::RosCompressedImage(image));
+    }
+    if let Ok(image) = decode_raw_image(buf)
+        && image.is_consistent()
+    {
+        return Ok(Message::Raw(image));
+    }
+    if let Ok(image) = decode::<RosImage>(buf)
+        && image.is_consistent()
+    {
+        return Ok(Message::RosImage(image));
+    }
+    video.map(Message::Video)
+}
+
 /// Decode a CDR-encoded foxglove CompressedVideo message.
 ///
-/// The buffer must include the 4-byte CDR encapsulation header produced by
-/// `cdr::serialize` — `cdr::deserialize` consumes it automatically.
+/// The buffer must start with the 4-byte CDR encapsulation header, which
+/// selects the representation and byte order (see [`Encapsulation`]).
+///
+/// Returns the full message on success.
+pub fn decode_compressed_video(buf: &[u8]) -> Result<CompressedVideo, DecodeError> {
+    decode(buf)
+}
+
+/// Decode a CDR-encoded foxglove RawImage message, with the same
+/// requirements as [`decode_compressed_video`].
+pub fn decode_raw_image(buf: &[u8]) -> Result<RawImage, DecodeError> {
+    decode(buf)
+}
+
+fn decode<T: Decode>(buf: &[u8]) -> Result<T, DecodeError> {
+    T::decode(&mut Reader::new(buf)?)
+}
+
+/// Size of the encapsulation header before the message body.
+const ENCAPSULATION_HEADER: usize = 4;
+
+/// Reads the fields of a CDR message body in order. Primitives are aligned
+/// to their size from the start of the body, and every length is checked
+/// against the bytes left before anything is copied.
 ///
-/// Returns (video_data, format_string) on success.
-pub fn decode_compressed_video(buf: &[u8]) -> Result<(Vec<u8>, String), String> {
-    if buf.len() < 4 {
-        return Err(format!("payload too short ({} bytes, need ≥4)", buf.len()));
+/// XCDR1 and XCDR2 differ only in aligning 8-byte primitives, which no
+/// supported schema has.
+struct Reader<'a> {
+    body: &'a [u8],
+    pos: usize,
+    little_endian: bool,
+}
+
+impl<'a> Reader<'a> {
+    /// Check the encapsulation header of `buf` and start at the body.
+    fn new(buf: &'a [u8]) -> Result<Self, DecodeError> {
+        let Some((header, body)) = buf.split_first_chunk::<{ ENCAPSULATION_HEADER }>() else {
+            return Err(DecodeError::Truncated {
+                len: buf.len(),
+                needed: ENCAPSULATION_HEADER,
+            });
+        };
+        // The options that follow the identifier are ignored.
+        let id = [header[0], header[1]];
+        let encapsulation = Encapsulation::from_id(id)
+            .filter(|e| !e.is_parameter_list())
+            .ok_or(DecodeError::BadEncapsulation(id))?;
+        let mut reader = Self {
+            body,
+            pos: 0,
+            little_endian: encapsulation.is_little_endian(),
+        };
+        if encapsulation.is_delimited() {
+            // Members that a newer version of the type appends after the
+            // ones known here are skipped. Nested structs are taken as
+            // final, without a DHEADER of their own.
+            let size = reader.u32()?;
+            let remaining = reader.body.len() - reader.pos;
+            if size as usize > remaining {
+                return Err(DecodeError::LengthOverflow {
+                    length: size,
+                    remaining,
+                });
+            }
+            reader.body = &reader.body[..reader.pos + size as usize];
+        }
+        Ok(reader)
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], DecodeError> {
+        let Some(bytes) = self.body.get(self.pos..).and_then(|rest| rest.get(..n)) else {
+            return Err(DecodeError::Truncated {
+                len: ENCAPSULATION_HEADER + self.body.len(),
+                needed: ENCAPSULATION_HEADER + self.pos + n,
+            });
+        };
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, DecodeError> {
+        self.pos = self.pos.next_multiple_of(4);
+        let bytes = self.take(4)?.try_into().expect("4 bytes");
+        Ok(if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    }
+
+    fn i32(&mut self) -> Result<i32, DecodeError> {
+        self.u32().map(|v| v as i32)
+    }
+
+    /// A length-prefixed `sequence<uint8>`.
+    fn bytes(&mut self) -> Result<&'a [u8], DecodeError> {
+        let length = self.u32()?;
+        let remaining = self.body.len() - self.pos;
+        if length as usize > remaining {
+            return Err(DecodeError::LengthOverflow { length, remaining });
+        }
+        self.take(length as usize)
+    }
+
+    fn str(&mut self) -> Result<&'a str, DecodeError> {
+        let bytes = self.bytes()?;
+        // The length counts the terminating NUL.
+        let bytes = bytes.strip_suffix(&[0]).unwrap_or(bytes);
+        std::str::from_utf8(bytes)
+            .map_err(|_| DecodeError::SchemaMismatch("string is not UTF-8".to_string()))
+    }
+
+    fn string(&mut self) -> Result<String, DecodeError> {
+        self.str().map(str::to_string)
+    }
+}
+
+/// A message type read field by field from a [`Reader`].
+trait Decode: Sized {
+    fn decode(r: &mut Reader) -> Result<Self, DecodeError>;
+}
+
+impl Decode for Timestamp {
+    fn decode(r: &mut Reader) -> Result<Self, DecodeError> {
+        Ok(Self {
+            sec: r.u32()?,
+            nsec: r.u32()?,
+        })
+    }
+}
+
+impl Decode for CompressedVideo {
+    fn decode(r: &mut Reader) -> Result<Self, DecodeError> {
+        CompressedVideoView::read(r).map(Self::from)
+    }
+}
+
+impl Decode for CompressedImage {
+    fn decode(r: &mut Reader) -> Result<Self, DecodeError> {
+        Ok(Self {
+            timestamp: Timestamp::decode(r)?,
+            frame_id: r.string()?,
+            data: r.bytes()?.to_vec(),
+            format: r.string()?,
+        })
+    }
+}
+
+impl Decode for RawImage {
+    fn decode(r: &mut Reader) -> Result<Self, DecodeError> {
+        Ok(Self {
+            timestamp: Timestamp::decode(r)?,
+            frame_id: r.string()?,
+            width: r.u32()?,
+            height: r.u32()?,
+            encoding: r.string()?,
+            step: r.u32()?,
+            data: r.bytes()?.to_vec(),
+        })
+    }
+}
+
+impl Decode for RosTime {
+    fn decode(r: &mut Reader) -> Result<Self, DecodeError> {
+        Ok(Self {
+            sec: r.i32()?,
+            nanosec: r.u32()?,
+        })
+    }
+}
+
+impl Decode for RosHeader {
+    fn decode(r: &mut Reader) -> Result<Self, DecodeError> {
+        Ok(Self {
+            stamp: RosTime::decode(r)?,
+            frame_id: r.string()?,
+        })
+    }
+}
+
+impl Decode for RosCompressedImage {
+    fn decode(r: &mut Reader) -> Result<Self, DecodeError> {
+        Ok(Self {
+            header: RosHeader::decode(r)?,
+            format: r.string()?,
+            data: r.bytes()?.to_vec(),
+        })
+    }
+}
+
+impl Decode for RosImage {
+    fn decode(r: &mut Reader) -> Result<Self, DecodeError> {
+        Ok(Self {
+            header: RosHeader::decode(r)?,
+            height: r.u32()?,
+            width: r.u32()?,
+            encoding: r.string()?,
+            is_bigendian: r.u8()?,
+            step: r.u32()?,
+            data: r.bytes()?.to_vec(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const H264: &[u8] = &[0, 0, 0, 1, 0x65, 0x88, 0x84];
+
+    /// Builds a CDR message with the representation identifier `id`.
+    struct Writer {
+        buf: Vec<u8>,
+        little_endian: bool,
+    }
+
+    impl Writer {
+        fn new(id: u16) -> Self {
+            let little_endian = Encapsulation::from_id(id.to_be_bytes())
+                .is_none_or(Encapsulation::is_little_endian);
+            let mut buf = id.to_be_bytes().to_vec();
+            buf.extend_from_slice(&[0, 0]);
+            Self { buf, little_endian }
+        }
+
+        fn u8(mut self, v: u8) -> Self {
+            self.buf.push(v);
+            self
+        }
+
+        fn u32(mut self, v: u32) -> Self {
+            let body = self.buf.len() - ENCAPSULATION_HEADER;
+            self.buf
+                .resize(ENCAPSULATION_HEADER + body.next_multiple_of(4), 0);
+            let bytes = if self.little_endian {
+                v.to_le_bytes()
+            } else {
+                v.to_be_bytes()
+            };
+            self.buf.extend_from_slice(&bytes);
+            self
+        }
+
+        fn bytes(self, bytes: &[u8]) -> Self {
+            let mut w = self.u32(bytes.len() as u32);
+            w.buf.extend_from_slice(bytes);
+            w
+        }
+
+        fn string(self, s: &str) -> Self {
+            self.bytes(format!("{s}\0").as_bytes())
+        }
+
+        fn finish(self) -> Vec<u8> {
+            self.buf
+        }
+
+        /// The message with a DHEADER holding the size of the body.
+        fn delimited(self) -> Vec<u8> {
+            let size = (self.buf.len() - ENCAPSULATION_HEADER) as u32;
+            let size = if self.little_endian {
+                size.to_le_bytes()
+            } else {
+                size.to_be_bytes()
+            };
+            let mut buf = self.buf;
+            buf.splice(ENCAPSULATION_HEADER..ENCAPSULATION_HEADER, size);
+            buf
+        }
+    }
+
+    fn compressed_video(id: u16, format: &str) -> Writer {
+        Writer::new(id)
+            .u32(7)
+            .u32(9)
+            .string("cam")
+            .bytes(H264)
+            .string(format)
+    }
+
+    fn is_delimited(id: u16) -> bool {
+        Encapsulation::from_id(id.to_be_bytes()).is_some_and(Encapsulation::is_delimited)
+    }
+
+    /// CDR, CDR2 (XTypes 1.3 and 1.2 identifiers) and D_CDR2, both byte
+    /// orders.
+    const READABLE: [u16; 10] = [
+        0x0000, 0x0001, 0x0006, 0x0007, 0x0008, 0x0009, 0x0010, 0x0011, 0x0014, 0x0015,
+    ];
+
+    #[test]
+    fn reads_every_representation() {
+        for id in READABLE {
+            let w = compressed_video(id, "h264");
+            let buf = if is_delimited(id) {
+                w.delimited()
+            } else {
+                w.finish()
+            };
+            let video = decode_compressed_video(&buf).unwrap_or_else(|e| panic!("{id:#06x}: {e}"));
+            assert_eq!(video.timestamp, Timestamp { sec: 7, nsec: 9 }, "{id:#06x}");
+            assert_eq!(video.frame_id, "cam", "{id:#06x}");
+            assert_eq!(video.data, H264, "{id:#06x}");
+            assert_eq!(video.format, "h264", "{id:#06x}");
+
+            let view = CompressedVideoView::parse(&buf).unwrap();
+            assert_eq!(view.data, H264, "{id:#06x}");
+            assert!(Encapsulation::from_header(&buf).is_some());
+        }
+    }
+
+    #[test]
+    fn byte_order_follows_the_identifier() {
+        let ids = [(0x0000, 0x0001), (0x0006, 0x0007), (0x0010, 0x0011)];
+        for (be, le) in ids {
+            let be = Encapsulation::from_id(u16::to_be_bytes(be)).unwrap();
+            let le = Encapsulation::from_id(u16::to_be_bytes(le)).unwrap();
+            assert!(!be.is_little_endian() && le.is_little_endian());
+        }
+        // A big-endian body read as little-endian has a huge string length.
+        let mut buf = compressed_video(0x0000, "h264").finish();
+        buf[1] = 0x01;
+        assert!(matches!(
+            decode_compressed_video(&buf),
+            Err(DecodeError::LengthOverflow { .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_parameter_lists() {
+        for id in [0x0002, 0x0003, 0x000a, 0x000b, 0x0012, 0x0013] {
+            let buf = compressed_video(id, "h264").finish();
+            assert_eq!(
+                decode_compressed_video(&buf).unwrap_err(),
+                DecodeError::BadEncapsulation(u16::to_be_bytes(id)),
+                "{id:#06x}"
+            );
+        }
+    }
+
+    #[test]
+    fn delimited_skips_appended_members() {
+        let buf = compressed_video(0x0009, "h264").u32(42).delimited();
+        assert_eq!(decode_compressed_video(&buf).unwrap().data, H264);
     }
 
-    let msg: CompressedVideo =
-        cdr::deserialize(buf).map_err(|e| format!("CDR deserialize: {e}"))?;
+    #[test]
+    fn rejects_a_dheader_larger_than_the_body() {
+        let mut buf = compressed_video(0x0009, "h264").delimited();
+        let body = (buf.len() - ENCAPSULATION_HEADER - 4) as u32;
+        buf[4..8].copy_from_slice(&(body + 1).to_le_bytes());
+        assert_eq!(
+            decode_compressed_video(&buf).unwrap_err(),
+            DecodeError::LengthOverflow {
+                length: body + 1,
+                remaining: body as usize
+            }
+        );
+    }
+
+    #[test]
+    fn truncated_inside_a_sequence_length() {
+        // Header, timestamp and "cam\0" take 20 bytes; `data`'s length
+        // follows.
+        let buf = compressed_video(0x0001, "h264").finish();
+        assert_eq!(
+            decode_compressed_video(&buf[..22]).unwrap_err(),
+            DecodeError::Truncated {
+                len: 22,
+                needed: 24
+            }
+        );
+    }
+
+    const JPEG: &[u8] = &[0xff, 0xd8, 0xff, 0xe0, 0, 0x10];
+
+    fn raw_image() -> Writer {
+        Writer::new(0x0001)
+            .u32(7)
+            .u32(9)
+            .string("cam")
+            .u32(2)
+            .u32(2)
+            .string("rgb8")
+            .u32(6)
+            .bytes(&[0x80; 12])
+    }
+
+    fn ros_header(w: Writer) -> Writer {
+        w.u32(7).u32(9).string("cam")
+    }
+
+    #[test]
+    fn detects_every_layout() {
+        let detect = |buf: Vec<u8>| decode_message(&buf, Schema::Auto).unwrap();
+
+        let video = detect(compressed_video(0x0001, "h264").finish());
+        assert_eq!(video.schema(), Schema::CompressedVideo);
+        assert_eq!(video.data(), H264);
+
+        let image = detect(
+            Writer::new(0x0001)
+                .u32(7)
+                .u32(9)
+                .string("cam")
+                .bytes(JPEG)
+                .string("jpeg")
+                .finish(),
+        );
+        assert_eq!(image.schema(), Schema::CompressedImage);
+        assert_eq!(image.codec(), Some(Codec::Jpeg));
+
+        let raw = detect(raw_image().finish());
+        assert_eq!(raw.schema(), Schema::RawImage);
+        assert_eq!(raw.raw_layout().unwrap().encoding, RawEncoding::Rgb8);
+
+        let ros_compressed = detect(
+            ros_header(Writer::new(0x0001))
+                .string("bgr8; jpeg compressed bgr8")
+                .bytes(JPEG)
+                .finish(),
+        );
+        assert_eq!(ros_compressed.schema(), Schema::RosCompressedImage);
+        assert_eq!(ros_compressed.codec(), Some(Codec::Jpeg));
+        assert_eq!(ros_compressed.data(), JPEG);
+
+        let ros_image = detect(
+            ros_header(Writer::new(0x0001))
+                .u32(2)
+                .u32(3)
+                .string("mono16")
+                .u8(1)
+                .u32(6)
+                .bytes(&[0; 12])
+                .finish(),
+        );
+        assert_eq!(ros_image.schema(), Schema::RosImage);
+        let layout = ros_image.raw_layout().unwrap();
+        assert_eq!((layout.width, layout.height), (3, 2));
+        assert!(layout.big_endian);
+    }
 
-    Ok((msg.data, msg.format))
+    #[test]
+    fn only_annex_b_accepts_bare_h264() {
+        for schema in [Schema::Auto, Schema::CompressedVideo, Schema::RawImage] {
+            assert!(decode_message(H264, schema).is_err(), "{schema}");
+        }
+        let message = decode_message(H264, Schema::AnnexB).unwrap();
+        assert_eq!(message.codec(), Some(Codec::H264));
+        assert_eq!(message.data(), H264);
+
+        let cdr = compressed_video(0x0001, "h264").finish();
+        assert!(matches!(
+            decode_message(&cdr, Schema::AnnexB),
+            Err(DecodeError::SchemaMismatch(_))
+        ));
+    }
+
+    #[test]
+    fn error_truncated() {
+        let e = decode_message(&[0, 1, 0], Schema::Auto).unwrap_err();
+        assert_eq!(e, DecodeError::Truncated { len: 3, needed: 4 });
+        assert_eq!(e.class(), "truncated");
+    }
+
+    #[test]
+    fn error_bad_encapsulation() {
+        // An MPEG-TS packet is not CDR.
+        let e = decode_message(&[0x47, 0x40, 0x11, 0x10, 0, 0, 0, 0], Schema::Auto).unwrap_err();
+        assert_eq!(e, DecodeError::BadEncapsulation([0x47, 0x40]));
+        assert_eq!(e.class(), "encapsulation");
+    }
+
+    #[test]
+    fn error_length_overflow() {
+        let buf = Writer::new(0x0001).u32(7).u32(9).u32(u32::MAX).finish();
+        let e = decode_message(&buf, Schema::CompressedVideo).unwrap_err();
+        assert_eq!(
+            e,
+            DecodeError::LengthOverflow {
+                length: u32::MAX,
+                remaining: 0
+            }
+        );
+        assert_eq!(e.class(), "length");
+    }
+
+    #[test]
+    fn error_unknown_format() {
+        // Decodes, but the format is the caller's to reject.
+        let buf = compressed_video(0x0001, "theora").finish();
+        let message = decode_message(&buf, Schema::Auto).unwrap();
+        assert_eq!(message.codec(), None);
+        assert_eq!(
+            DecodeError::UnknownFormat("theora".into()).class(),
+            "format"
+        );
+    }
+
+    #[test]
+    fn error_schema_mismatch() {
+        // Two rows 6 bytes apart cannot fit in 4 bytes.
+        let buf = Writer::new(0x0001)
+            .u32(7)
+            .u32(9)
+            .string("cam")
+            .u32(2)
+            .u32(2)
+            .string("rgb8")
+            .u32(6)
+            .bytes(&[0; 4])
+            .finish();
+        let e = decode_message(&buf, Schema::RawImage).unwrap_err();
+        assert!(matches!(e, DecodeError::SchemaMismatch(_)), "{e}");
+        assert_eq!(e.class(), "schema");
+
+        let buf = Writer::new(0x0001)
+            .u32(7)
+            .u32(9)
+            .bytes(&[0xff, 0xfe, 0])
+            .finish();
+        let e = decode_message(&buf, Schema::CompressedVideo).unwrap_err();
+        assert!(matches!(e, DecodeError::SchemaMismatch(_)), "{e}");
+    }
+
+    #[test]
+    fn counts_errors_by_class() {
+        let errors = DecodeErrors::default();
+        errors.count(&DecodeError::BadEncapsulation([1, 2]));
+        errors.count(&DecodeError::BadEncapsulation([1, 2]));
+        errors.count(&DecodeError::UnknownFormat("theora".into()));
+        assert_eq!(errors.total(), 3);
+        assert_eq!(
+            errors.get(),
+            [
+                ("truncated", 0),
+                ("encapsulation", 2),
+                ("length", 0),
+                ("format", 1),
+                ("schema", 0)
+            ]
+        );
+    }
 }
@@ -1,13 +1,223 @@
-use clap::Parser;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+
+use video_zenoh_player::cdr::Schema;
+use video_zenoh_player::decoder::Backend;
+use video_zenoh_player::keyframe::{self, RequestMode};
+use video_zenoh_player::queue::DropPolicy;
+use video_zenoh_player::recorder::Container;
+use video_zenoh_player::scaling::Scaling;
+use video_zenoh_player::session::Mode;
+use video_zenoh_player::{DecoderChoice, SessionConfig};
+
+/// Endpoint connected to when neither `--connect` nor `--zenoh-config` is
+/// given.
+const DEFAULT_ENDPOINT: &str = "tcp/192.168.31.113:7447";
 
 #[derive(Parser)]
 #[command(name = "player", about = "Zenoh video stream player")]
 pub struct Args {
-    /// Zenoh endpoint address (e.g. tcp/192.168.31.113:7447)
-    #[arg(short = 'e', long, default_value = "tcp/192.168.31.113:7447")]
-    pub endpoint: String,
+    /// Without a subcommand, play the Zenoh topics live
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Zenoh configuration file (JSON5); the Zenoh options below override it
+    #[arg(long, global = true)]
+    pub zenoh_config: Option<PathBuf>,
+
+    /// Zenoh session mode
+    #[arg(long, value_enum, global = true)]
+    pub mode: Option<Mode>,
+
+    /// Zenoh endpoint to connect to (repeatable) [default without
+    /// --zenoh-config: tcp/192.168.31.113:7447]
+    #[arg(
+        short = 'e',
+        long = "connect",
+        visible_alias = "endpoint",
+        global = true
+    )]
+    pub connect: Vec<String>,
+
+    /// Zenoh endpoint to listen on (repeatable, e.g. tcp/0.0.0.0:7447)
+    #[arg(long = "listen", global = true)]
+    pub listen: Vec<String>,
+
+    /// Enable or disable Zenoh multicast scouting
+    #[arg(long, global = true)]
+    pub multicast_scouting: Option<bool>,
+
+    /// CA certificate (PEM) to verify tls/ and quic/ endpoints against
+    #[arg(long, global = true)]
+    pub tls_root_ca: Option<PathBuf>,
+
+    /// Client certificate (PEM) for mutual TLS, together with --tls-key
+    #[arg(long, global = true, requires = "tls_key")]
+    pub tls_cert: Option<PathBuf>,
+
+    /// Client private key (PEM) for mutual TLS, together with --tls-cert
+    #[arg(long, global = true, requires = "tls_cert")]
+    pub tls_key: Option<PathBuf>,
+
+    /// User for Zenoh user/password authentication
+    #[arg(long, global = true, requires = "zenoh_password")]
+    pub zenoh_user: Option<String>,
+
+    /// Password for Zenoh user/password authentication
+    #[arg(
+        long,
+        global = true,
+        env = "ZENOH_PASSWORD",
+        hide_env_values = true,
+        requires = "zenoh_user"
+    )]
+    pub zenoh_password: Option<String>,
+
+    /// Zenoh topic to subscribe to (repeat to show several streams in a grid)
+    #[arg(
+        short,
+        long = "topic",
+        global = true,
+        default_value = "video/RadCam19216831100/stream"
+    )]
+    pub topics: Vec<String>,
+
+    /// Run without a window and print stream metrics as JSON lines
+    #[arg(long, global = true)]
+    pub headless: bool,
+
+    /// Seconds between JSON stats lines in headless mode
+    #[arg(long, global = true, default_value_t = 1.0)]
+    pub stats_interval: f64,
+
+    /// Exit after this many seconds in headless mode; the exit code is
+    /// non-zero if a stream received no frames by then
+    #[arg(long, global = true)]
+    pub exit_after: Option<f64>,
+
+    /// Serve Prometheus metrics on this address (e.g. 0.0.0.0:9090)
+    #[arg(long, global = true)]
+    pub metrics_listen: Option<SocketAddr>,
+
+    /// How to ask publishers for a keyframe when decoding starts or recovers
+    #[arg(long, value_enum, default_value_t = RequestMode::Query)]
+    pub keyframe_request: RequestMode,
+
+    /// Key that keyframe requests are sent to; {topic} is replaced by the
+    /// stream's topic
+    #[arg(long, default_value = keyframe::DEFAULT_KEY)]
+    pub keyframe_request_key: String,
+
+    /// Don't treat timestamp jumps as lost data (for publishers that skip
+    /// frames on purpose and send no frame counter)
+    #[arg(long, global = true)]
+    pub no_timestamp_gaps: bool,
+
+    /// Frames buffered per stream between the subscriber and the decoder
+    #[arg(long, global = true, default_value_t = 8)]
+    pub queue_len: usize,
+
+    /// What a full queue does when decoding falls behind (playback always
+    /// blocks)
+    #[arg(long, global = true, value_enum, default_value_t = DropPolicy::DropOldest)]
+    pub queue_policy: DropPolicy,
+
+    /// Message layout of the samples; auto detects Foxglove and ROS 2
+    /// (zenoh-bridge-ros2dds) images and video per message. Payloads that
+    /// don't decode are dropped, bare H.264 needs annex-b
+    #[arg(long, global = true, value_enum, default_value_t = Schema::Auto)]
+    pub schema: Schema,
+
+    /// Decoding library; openh264 handles H.264 only, without GStreamer
+    #[arg(long, global = true, value_enum, default_value_t = Backend::default())]
+    pub backend: Backend,
+
+    /// GStreamer decoder to use: auto (hardware first), software, or an
+    /// element name (see `probe-decoders`)
+    #[arg(long, global = true, default_value = "auto")]
+    pub decoder: DecoderChoice,
+
+    /// Resolution streams are decoded to (can also be changed in the GUI)
+    #[arg(long, global = true, value_enum, default_value_t = Scaling::Window)]
+    pub scaling: Scaling,
+
+    /// Largest decoded size for --scaling max, as WIDTHxHEIGHT
+    #[arg(long, global = true, default_value = "1920x1080", value_parser = parse_size)]
+    pub max_size: (u32, u32),
+
+    /// Key expression scanned by the topic discovery panel
+    #[arg(long, default_value = "video/**")]
+    pub discover: String,
+
+    /// Start recording as soon as video arrives (can also be toggled in the GUI)
+    #[arg(long)]
+    pub record: bool,
+
+    /// Directory that recordings are written to
+    #[arg(long, default_value = "recordings")]
+    pub record_dir: PathBuf,
+
+    /// Container format for recordings
+    #[arg(long, value_enum, default_value_t = Container::Mp4)]
+    pub record_container: Container,
+
+    /// Start a new recording file after this many megabytes (0 = no limit)
+    #[arg(long, default_value_t = 0)]
+    pub record_max_size_mb: u64,
+
+    /// Start a new recording file after this many seconds (0 = no limit)
+    #[arg(long, default_value_t = 0)]
+    pub record_max_duration: u64,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Write every sample of the topics to an MCAP file until Ctrl+C
+    Record {
+        /// MCAP file to create
+        output: PathBuf,
+    },
+    /// Play an MCAP file through the decoder and GUI
+    Play {
+        /// MCAP file written by `record` (or any MCAP of CDR video or image messages)
+        input: PathBuf,
+    },
+    /// List the decoders of each codec and whether they work here
+    ProbeDecoders,
+}
+
+impl Args {
+    /// Zenoh session settings from the config file and the command line.
+    pub fn session_config(&self) -> SessionConfig {
+        let mut connect = self.connect.clone();
+        if connect.is_empty() && self.zenoh_config.is_none() {
+            connect.push(DEFAULT_ENDPOINT.to_string());
+        }
+        SessionConfig {
+            file: self.zenoh_config.clone(),
+            mode: self.mode,
+            connect,
+            listen: self.listen.clone(),
+            multicast_scouting: self.multicast_scouting,
+            tls_root_ca: self.tls_root_ca.clone(),
+            tls_cert: self.tls_cert.clone(),
+            tls_key: self.tls_key.clone(),
+            user: self.zenoh_user.clone(),
+            password: self.zenoh_password.clone(),
+        }
+    }
+}
 
-    /// Zenoh topic to subscribe to
-    #[arg(short, long, default_value = "video/RadCam19216831100/stream")]
-    pub topic: String,
+/// `WIDTHxHEIGHT`, e.g. `1280x720`.
+fn parse_size(s: &str) -> Result<(u32, u32), String> {
+    let (width, height) = s
+        .split_once(['x', 'X'])
+        .ok_or_else(|| format!("expected WIDTHxHEIGHT, got '{s}'"))?;
+    let parse = |v: &str| match v.trim().parse::<u32>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!("invalid size '{s}'")),
+    };
+    Ok((parse(width)?, parse(height)?))
 }
@@ -0,0 +1,66 @@
+use std::fmt;
+
+/// Video codecs that can appear in the `format` field of a foxglove
+/// CompressedVideo message, plus the still image formats of CompressedImage
+/// and RawImage.
+/// <https://docs.foxglove.dev/docs/visualization/message-schemas/compressed-video>
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Codec {
+    H264,
+    H265,
+    Vp9,
+    Av1,
+    /// CompressedImage, JPEG.
+    Jpeg,
+    /// CompressedImage, PNG.
+    Png,
+    /// RawImage; the pixel layout travels with each frame.
+    Raw,
+}
+
+impl Codec {
+    /// Every supported video codec.
+    pub const VIDEO: [Self; 4] = [Self::H264, Self::H265, Self::Vp9, Self::Av1];
+
+    /// Parse a CompressedVideo or CompressedImage `format` string
+    /// (case-insensitive).
+    ///
+    /// Accepts the Foxglove names plus the common aliases "avc", "hevc" and
+    /// "jpg".
+    pub fn from_format(format: &str) -> Option<Self> {
+        match format.trim().to_ascii_lowercase().as_str() {
+            "h264" | "avc" => Some(Self::H264),
+            "h265" | "hevc" => Some(Self::H265),
+            "vp9" => Some(Self::Vp9),
+            "av1" => Some(Self::Av1),
+            "jpeg" | "jpg" => Some(Self::Jpeg),
+            "png" => Some(Self::Png),
+            _ => None,
+        }
+    }
+
+    /// Still images: every frame decodes on its own, without a video
+    /// decoder.
+    pub fn is_image(self) -> bool {
+        matches!(self, Self::Jpeg | Self::Png | Self::Raw)
+    }
+
+    /// Canonical Foxglove format name.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::H264 => "h264",
+            Self::H265 => "h265",
+            Self::Vp9 => "vp9",
+            Self::Av1 => "av1",
+            Self::Jpeg => "jpeg",
+            Self::Png => "png",
+            Self::Raw => "raw",
+        }
+    }
+}
+
+impl fmt::Display for Codec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
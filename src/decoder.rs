@@ -1,249 +1,323 @@
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 use std::sync::mpsc;
-use std::sync::{Arc, Mutex};
-use std::time::Duration;
+use std::time::Instant;
 
-use gstreamer::prelude::*;
+use image::RgbaImage;
 
-/// Build, run, and auto-restart the GStreamer decode pipeline.
+use crate::codec::Codec;
+use crate::frame::{DecodedFrame, EncodedFrame, PixelFormat};
+use crate::gate::{Admit, KeyframeGate};
+use crate::images;
+use crate::keyframe::KeyframeRequester;
+use crate::latency::FrameTiming;
+use crate::metrics::{METRICS, StreamName};
+use crate::queue::FrameReceiver;
+use crate::recorder::Recording;
+use crate::scaling::OutputSize;
+
+#[cfg(feature = "gstreamer")]
+mod gst;
+#[cfg(feature = "openh264")]
+mod openh264;
+
+#[cfg(feature = "gstreamer")]
+pub use gst::{make_parser, src_caps};
+
+/// Library that decodes the frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Backend {
+    /// GStreamer pipelines, with hardware decoders when available; every
+    /// codec (cargo feature `gstreamer`, on by default).
+    Gstreamer,
+    /// Cisco's OpenH264 software decoder, C++ compiled into the binary;
+    /// H.264 only, but needs no GStreamer plugins (cargo feature `openh264`).
+    Openh264,
+}
+
+impl Default for Backend {
+    /// GStreamer if it is built in.
+    fn default() -> Self {
+        if cfg!(feature = "gstreamer") {
+            Self::Gstreamer
+        } else {
+            Self::Openh264
+        }
+    }
+}
+
+/// Which decoder element a [`DecoderBuilder`] uses.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum DecoderChoice {
+    /// Hardware decoders first, then software.
+    #[default]
+    Auto,
+    /// Software decoders only.
+    Software,
+    /// This GStreamer element (e.g. `nvh264dec`) for the codecs it accepts,
+    /// with the automatic order as fallback.
+    Element(String),
+}
+
+impl std::str::FromStr for DecoderChoice {
+    type Err = std::convert::Infallible;
+
+    /// `auto`, `software`, or an element name.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "auto" => Self::Auto,
+            "software" => Self::Software,
+            name => Self::Element(name.to_string()),
+        })
+    }
+}
+
+impl std::fmt::Display for DecoderChoice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Auto => f.write_str("auto"),
+            Self::Software => f.write_str("software"),
+            Self::Element(name) => f.write_str(name),
+        }
+    }
+}
+
+/// What `probe-decoders` found out about one decoder element.
+#[derive(Debug, Clone)]
+pub struct DecoderProbe {
+    pub name: String,
+    /// Factory long name, empty if not installed.
+    pub description: String,
+    pub hardware: bool,
+    pub installed: bool,
+    /// Could be created and brought to the READY state, i.e. the device
+    /// (for hardware decoders) is present.
+    pub usable: bool,
+}
+
+/// Decoders that can handle `codec`, for `probe-decoders`: GStreamer's in
+/// the order `auto` tries them, then the built-in ones. Initializes
+/// GStreamer.
+pub fn probe_decoders(codec: Codec) -> anyhow::Result<Vec<DecoderProbe>> {
+    let mut probes = Vec::new();
+    #[cfg(feature = "gstreamer")]
+    probes.extend(gst::probe(codec)?);
+    #[cfg(feature = "openh264")]
+    if codec == Codec::H264 {
+        probes.push(openh264::probe());
+    }
+    Ok(probes)
+}
+
+/// Builds the decoder stage: a thread that turns [`EncodedFrame`]s into
+/// RGBA [`DecodedFrame`]s, with GStreamer (preferring hardware decoders) or
+/// the built-in OpenH264 decoder. Still images skip both and go through
+/// [`images`] on the same thread.
 ///
-/// Each iteration creates a fresh pipeline. On error it tears down and rebuilds.
-/// Decoded RGBA frames are sent through `rgba_tx`.
-/// The current pipeline reference is stored in `pipeline_holder` so the GUI can
-/// shut it down cleanly on exit.
-pub fn run_loop(
-    h264_rx: mpsc::Receiver<Vec<u8>>,
-    rgba_tx: mpsc::SyncSender<(Vec<u8>, u32, u32, usize)>,
-    pipeline_holder: Arc<Mutex<Option<gstreamer::Element>>>,
-) {
+/// ```ignore
+/// let (decoded_tx, decoded_rx) = std::sync::mpsc::sync_channel(2);
+/// let decoder = DecoderBuilder::new("video/front/stream").spawn(frame_rx, decoded_tx);
+/// ```
+pub struct DecoderBuilder {
+    name: StreamName,
+    recording: Arc<Recording>,
+    keyframe_requests: Option<KeyframeRequester>,
+    timestamp_gaps: bool,
+    output_size: OutputSize,
+    backend: Backend,
+    decoder: DecoderChoice,
+}
 
-    loop {
-        println!("Starting GStreamer decode pipeline...");
-
-        let rgba_tx = rgba_tx.clone();
-
-        // Build the pipeline manually to avoid gst_base_src_loop issues.
-        // Use avdec_h264 (software) as primary -- it's more error-tolerant than vtdec.
-        // Fall back to vtdec if avdec_h264 is unavailable.
-        let pipeline = gstreamer::Pipeline::new();
-
-        let appsrc = gstreamer_app::AppSrc::builder()
-            .name("src")
-            .is_live(true)
-            .format(gstreamer::Format::Time)
-            .build();
-
-        let caps = gstreamer::Caps::builder("video/x-h264")
-            .field("stream-format", "byte-stream")
-            .build();
-        appsrc.set_caps(Some(&caps));
-
-        let h264parse = gstreamer::ElementFactory::make("h264parse")
-            .property_from_str("config-interval", "-1")
-            .build()
-            .expect("h264parse");
-
-        // Try hardware decoders first, fall back to software.
-        // Order: VA (modern) → VA-API (legacy) → NVIDIA → VideoToolbox (macOS) → software
-        let hw_decoders = [
-            ("vah264dec", "VA H.264 (Intel/AMD)"),
-            ("vaapih264dec", "VA-API H.264 (Intel/AMD)"),
-            ("nvh264dec", "NVIDIA NVDEC H.264"),
-            ("vtdec", "VideoToolbox (macOS)"),
-        ];
-        let (decoder, decoder_name) = hw_decoders
-            .iter()
-            .find_map(|(name, label)| {
-                gstreamer::ElementFactory::make(name)
-                    .build()
-                    .ok()
-                    .map(|d| {
-                        println!("  Using hardware decoder: {label} ({name})");
-                        (d, *name)
-                    })
-            })
-            .unwrap_or_else(|| {
-                let d = gstreamer::ElementFactory::make("avdec_h264")
-                    .build()
-                    .expect("No H.264 decoder available (tried hw + avdec_h264)");
-                println!("  Using software decoder: avdec_h264");
-                (d, "avdec_h264")
-            });
-
-        let videoscale =
-            gstreamer::ElementFactory::make("videoscale").build().expect("videoscale");
-        let videoconvert =
-            gstreamer::ElementFactory::make("videoconvert").build().expect("videoconvert");
-
-        let capsfilter = gstreamer::ElementFactory::make("capsfilter")
-            .property(
-                "caps",
-                gstreamer::Caps::builder("video/x-raw")
-                    .field("format", "RGBA")
-                    .field("width", gstreamer::IntRange::new(1, 960))
-                    .field("height", gstreamer::IntRange::new(1, 540))
-                    .build(),
-            )
-            .build()
-            .expect("capsfilter");
-
-        let appsink = gstreamer_app::AppSink::builder()
-            .name("sink")
-            .max_buffers(1)
-            .drop(true)
-            .sync(false)
-            .build();
-
-        // Add and link all elements
-        pipeline
-            .add_many([
-                appsrc.upcast_ref(),
-                &h264parse,
-                &decoder,
-                &videoscale,
-                &videoconvert,
-                &capsfilter,
-                appsink.upcast_ref(),
-            ])
-            .expect("Failed to add elements");
-
-        gstreamer::Element::link_many([
-            appsrc.upcast_ref(),
-            &h264parse,
-            &decoder,
-            &videoscale,
-            &videoconvert,
-            &capsfilter,
-            appsink.upcast_ref(),
-        ])
-        .expect("Failed to link elements");
-
-        // Store pipeline reference for clean shutdown
-        {
-            let mut holder = pipeline_holder.lock().unwrap();
-            *holder = Some(pipeline.clone().upcast());
+impl DecoderBuilder {
+    /// `name` (usually the topic) labels logs, metrics and recordings; a
+    /// shared [`StreamName`] can be changed while the decoder runs.
+    pub fn new(name: impl Into<StreamName>) -> Self {
+        Self {
+            name: name.into(),
+            recording: Arc::new(Recording::default()),
+            keyframe_requests: None,
+            timestamp_gaps: true,
+            output_size: OutputSize::default(),
+            backend: Backend::default(),
+            decoder: DecoderChoice::Auto,
         }
+    }
 
-        // Track the compressed H.264 frame size so the appsink callback can
-        // forward it alongside the decoded RGBA data.
-        let last_h264_size = Arc::new(AtomicUsize::new(0));
-        let last_h264_size_cb = last_h264_size.clone();
-
-        // Forward decoded RGBA frames from appsink
-        appsink.set_callbacks(
-            gstreamer_app::AppSinkCallbacks::builder()
-                .new_sample(move |appsink| {
-                    let sample = appsink
-                        .pull_sample()
-                        .map_err(|_| gstreamer::FlowError::Error)?;
-                    let caps = sample.caps().ok_or(gstreamer::FlowError::Error)?;
-                    let info = gstreamer_video::VideoInfo::from_caps(caps)
-                        .map_err(|_| gstreamer::FlowError::Error)?;
-                    let buffer = sample.buffer().ok_or(gstreamer::FlowError::Error)?;
-                    let map = buffer
-                        .map_readable()
-                        .map_err(|_| gstreamer::FlowError::Error)?;
-
-                    let h264_size = last_h264_size_cb.load(Ordering::Relaxed);
-                    let _ = rgba_tx.try_send((map.as_slice().to_vec(), info.width(), info.height(), h264_size));
-                    Ok(gstreamer::FlowSuccess::Ok)
-                })
-                .build(),
-        );
-
-        pipeline
-            .set_state(gstreamer::State::Playing)
-            .expect("Failed to start pipeline");
-
-        println!("  Pipeline playing (decoder: {decoder_name})");
-
-        // Watch the bus for errors on a background thread.
-        // Sets got_error flag so the main pump loop knows to restart.
-        let got_error = Arc::new(Mutex::new(false));
-        let got_error_bus = got_error.clone();
-        let bus = pipeline.bus().expect("Pipeline has no bus");
-        let bus_thread = std::thread::spawn(move || {
-            for msg in bus.iter_timed(gstreamer::ClockTime::NONE) {
-                use gstreamer::MessageView;
-                match msg.view() {
-                    MessageView::Error(err) => {
-                        eprintln!(
-                            "GStreamer ERROR from {:?}: {}",
-                            err.src().map(|s| s.path_string()),
-                            err.error(),
-                        );
-                        if let Some(debug) = err.debug() {
-                            eprintln!("  debug: {debug}");
-                        }
-                        *got_error_bus.lock().unwrap() = true;
-                        break;
-                    }
-                    MessageView::Warning(warn) => {
-                        eprintln!("GStreamer WARNING: {}", warn.error());
-                        if let Some(debug) = warn.debug() {
-                            eprintln!("  debug: {debug}");
-                        }
-                    }
-                    MessageView::Eos(..) => {
-                        eprintln!("GStreamer: unexpected EOS, restarting...");
-                        *got_error_bus.lock().unwrap() = true;
-                        break;
-                    }
-                    _ => {}
-                }
-            }
-        });
+    /// Also write the compressed stream to disk while `recording` is enabled
+    /// (GStreamer backend only).
+    pub fn recording(mut self, recording: Arc<Recording>) -> Self {
+        self.recording = recording;
+        self
+    }
 
-        // Push H.264 data directly into appsrc with proper PTS.
-        let frame_duration_ns: u64 = 33_333_333; // ~30fps
-        let mut pts_ns: u64 = 0;
-        let mut pump_count: u64 = 0;
+    /// Ask the publisher for a keyframe when decoding starts, after a
+    /// pipeline restart, and when the decoder reports corrupt data.
+    pub fn keyframe_requests(mut self, requester: KeyframeRequester) -> Self {
+        self.keyframe_requests = Some(requester);
+        self
+    }
 
-        loop {
-            // Check if the bus thread detected an error
-            if *got_error.lock().unwrap() {
-                break;
-            }
+    /// Treat jumps in the source timestamps as lost data when the publisher
+    /// sends no frame counter (on by default). Turn off for publishers that
+    /// skip frames on purpose, e.g. on static scenes.
+    pub fn timestamp_gaps(mut self, enabled: bool) -> Self {
+        self.timestamp_gaps = enabled;
+        self
+    }
 
-            let data = match h264_rx.recv_timeout(Duration::from_millis(100)) {
-                Ok(d) => d,
-                Err(mpsc::RecvTimeoutError::Timeout) => continue,
-                Err(mpsc::RecvTimeoutError::Disconnected) => break,
-            };
+    /// Scale decoded frames as `output_size` says, renegotiating whenever
+    /// it changes (default: source resolution).
+    pub fn output_size(mut self, output_size: OutputSize) -> Self {
+        self.output_size = output_size;
+        self
+    }
 
-            pump_count += 1;
-            if pump_count <= 3 || pump_count % 500 == 0 {
-                println!("  Pump #{pump_count}: {} bytes", data.len());
-            }
+    /// Decoding library (default: GStreamer if built in).
+    pub fn backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
 
-            last_h264_size.store(data.len(), Ordering::Relaxed);
-            let mut buffer = gstreamer::Buffer::from_slice(data);
-            {
-                let buf_ref = buffer.get_mut().unwrap();
-                buf_ref.set_pts(gstreamer::ClockTime::from_nseconds(pts_ns));
-                buf_ref.set_duration(gstreamer::ClockTime::from_nseconds(frame_duration_ns));
-            }
-            pts_ns += frame_duration_ns;
+    /// Which GStreamer decoder element to prefer (default: hardware, then
+    /// software).
+    pub fn decoder(mut self, choice: DecoderChoice) -> Self {
+        self.decoder = choice;
+        self
+    }
 
-            if appsrc.push_buffer(buffer).is_err() {
-                eprintln!("  appsrc push failed at #{pump_count}");
-                break;
+    /// Start decoding `frame_rx` into `decoded_tx` on a background thread,
+    /// initializing the backend if needed. Fails if the backend is not
+    /// built in or cannot start.
+    ///
+    /// The thread runs until `frame_rx` disconnects. Frames are dropped
+    /// rather than queued when `decoded_tx` is full; how `frame_rx` itself
+    /// sheds load is its [`DropPolicy`](crate::queue::DropPolicy).
+    pub fn spawn(
+        self,
+        frame_rx: FrameReceiver,
+        decoded_tx: mpsc::SyncSender<DecodedFrame>,
+    ) -> anyhow::Result<Decoder> {
+        match self.backend {
+            #[cfg(feature = "gstreamer")]
+            Backend::Gstreamer => gst::spawn(self, frame_rx, decoded_tx),
+            #[cfg(feature = "openh264")]
+            Backend::Openh264 => openh264::spawn(self, frame_rx, decoded_tx),
+            #[allow(unreachable_patterns)]
+            backend => {
+                anyhow::bail!("This build has no {backend:?} backend; enable its cargo feature")
             }
         }
+    }
+}
 
-        // Tear down
-        let _ = pipeline.set_state(gstreamer::State::Null);
-        let _ = appsrc.end_of_stream();
-        let _ = bus_thread.join();
+/// Handle to a decoder thread. Dropping it stops the current pipeline or
+/// decoder.
+pub struct Decoder {
+    stop: Option<Box<dyn FnOnce() + Send>>,
+}
 
-        // Drain stale H.264 data
-        while h264_rx.try_recv().is_ok() {}
+impl Decoder {
+    fn new(stop: impl FnOnce() + Send + 'static) -> Self {
+        Self {
+            stop: Some(Box::new(stop)),
+        }
+    }
+}
 
-        if !*got_error.lock().unwrap() {
-            break; // clean shutdown (channel disconnected)
+impl Drop for Decoder {
+    fn drop(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            stop();
         }
+    }
+}
 
-        println!("Restarting pipeline in 500ms...");
-        std::thread::sleep(Duration::from_millis(500));
+/// Run `frame` through `gate`, counting and logging what it holds back and
+/// asking for a keyframe after a gap. `false` if the frame must be dropped.
+fn admit(
+    gate: &mut KeyframeGate,
+    frame: &mut EncodedFrame,
+    name: &str,
+    keyframe_requests: Option<&KeyframeRequester>,
+) -> bool {
+    match gate.admit(frame) {
+        Admit::Decode => true,
+        Admit::Wait => {
+            METRICS.dropped.with_label_values(&[name, "sync"]).inc();
+            false
+        }
+        Admit::Gap { missing } => {
+            match missing {
+                Some(n) => println!("  Gap of {n} frames, waiting for a keyframe"),
+                None => println!("  Stream discontinuity, waiting for a keyframe"),
+            }
+            METRICS.gaps.with_label_values(&[name]).inc();
+            METRICS.dropped.with_label_values(&[name, "gap"]).inc();
+            if let Some(requester) = keyframe_requests {
+                requester.request("gap");
+            }
+            false
+        }
+    }
+}
+
+/// Decode a still image frame and send it on like a decoded video frame.
+/// Images are not recorded.
+fn show_image(
+    frame: &EncodedFrame,
+    name: &str,
+    output_size: &OutputSize,
+    decoded_tx: &mpsc::SyncSender<DecodedFrame>,
+) {
+    match images::decode(frame) {
+        Ok(image) => {
+            let mut timing = frame.timing;
+            timing.decoded = Some(Instant::now());
+            send_rgba(
+                image,
+                Some(timing),
+                frame.data.len(),
+                name,
+                output_size,
+                decoded_tx,
+            );
+        }
+        Err(e) => {
+            eprintln!("  {} image failed to decode: {e:#}", frame.codec);
+            METRICS.dropped.with_label_values(&[name, "decoder"]).inc();
+        }
+    }
+}
+
+/// Scale a frame decoded in software down to `output_size` and send it to
+/// the display, counting it as decoded.
+fn send_rgba(
+    image: RgbaImage,
+    timing: Option<FrameTiming>,
+    compressed_size: usize,
+    name: &str,
+    output_size: &OutputSize,
+    decoded_tx: &mpsc::SyncSender<DecodedFrame>,
+) {
+    METRICS.decoded.with_label_values(&[name]).inc();
+    let (source_width, source_height) = image.dimensions();
+    let (width, height) = output_size.fit(source_width, source_height);
+    let image = if (width, height) == (source_width, source_height) {
+        image
+    } else {
+        image::imageops::resize(&image, width, height, image::imageops::FilterType::Triangle)
+    };
+    let sent = decoded_tx.try_send(DecodedFrame {
+        pixels: image.into_raw(),
+        format: PixelFormat::Rgba,
+        width,
+        height,
+        source_width,
+        source_height,
+        timing,
+        compressed_size,
+    });
+    if sent.is_err() {
+        // The display is not keeping up.
+        METRICS.dropped.with_label_values(&[name, "display"]).inc();
     }
 }
@@ -0,0 +1,888 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use gstreamer::prelude::*;
+
+use crate::codec::Codec;
+use crate::frame::{DecodedFrame, EncodedFrame, PixelFormat};
+use crate::gate::KeyframeGate;
+use crate::latency::FrameTiming;
+use crate::metrics::{METRICS, StreamName};
+use crate::queue::FrameReceiver;
+use crate::recorder::Recorder;
+
+use super::{Decoder, DecoderBuilder, DecoderChoice, DecoderProbe};
+
+/// (pts_ns, compressed_size, timing) of frames pushed into appsrc.
+type InFlight = VecDeque<(u64, usize, FrameTiming)>;
+
+/// Duration assumed for a frame until two timestamps have been seen (~30 fps).
+const DEFAULT_FRAME_DURATION_NS: u64 = 33_333_333;
+
+/// Frames pushed but not yet decoded that we keep timing for. Anything older
+/// has been dropped or merged by the decoder.
+const MAX_IN_FLIGHT: usize = 64;
+
+/// Forward jumps larger than this are treated as a discontinuity
+/// (publisher restart, clock step, `sec` wraparound) rather than real time.
+const MAX_TIMESTAMP_JUMP_NS: u64 = 5_000_000_000;
+
+/// Maps source timestamps onto buffer PTS relative to the first frame.
+///
+/// PTS follows the source clock as long as it moves forward by a plausible
+/// amount. Backwards, repeated, or wildly large steps are absorbed by
+/// advancing one frame duration instead, so the PTS seen by GStreamer stays
+/// strictly increasing. Each such step is counted; only the first of a run
+/// is logged.
+struct PtsClock {
+    /// Stream name, for the metrics.
+    name: StreamName,
+    /// Source time (ns) of the previous frame.
+    last_src_ns: Option<u64>,
+    /// PTS (ns) handed out for the previous frame.
+    last_pts_ns: u64,
+    /// Most recent plausible inter-frame interval.
+    frame_duration_ns: u64,
+    /// The previous step was a discontinuity too.
+    discontinuous: bool,
+}
+
+impl PtsClock {
+    fn new(name: StreamName) -> Self {
+        Self {
+            name,
+            last_src_ns: None,
+            last_pts_ns: 0,
+            frame_duration_ns: DEFAULT_FRAME_DURATION_NS,
+            discontinuous: false,
+        }
+    }
+
+    /// Return `(pts, duration)` in nanoseconds for a frame captured at `src_ns`.
+    fn next(&mut self, src_ns: u64) -> (u64, u64) {
+        let Some(last_src_ns) = self.last_src_ns.replace(src_ns) else {
+            return (0, self.frame_duration_ns);
+        };
+
+        let delta = src_ns.wrapping_sub(last_src_ns);
+        if src_ns > last_src_ns && delta <= MAX_TIMESTAMP_JUMP_NS {
+            self.frame_duration_ns = delta;
+            self.last_pts_ns += delta;
+            self.discontinuous = false;
+        } else {
+            if !self.discontinuous {
+                eprintln!(
+                    "  Timestamp discontinuity ({} → {} ns), continuing at nominal rate",
+                    last_src_ns, src_ns
+                );
+                self.discontinuous = true;
+            }
+            METRICS
+                .timestamp_discontinuities
+                .with_label_values(&[&self.name.get()])
+                .inc();
+            self.last_pts_ns += self.frame_duration_ns;
+        }
+        (self.last_pts_ns, self.frame_duration_ns)
+    }
+}
+
+/// Hardware decoders to try for each codec, in order of preference.
+/// Order: VA (modern) → VA-API (legacy) → NVIDIA → VideoToolbox (macOS)
+fn hw_decoders(codec: Codec) -> &'static [(&'static str, &'static str)] {
+    match codec {
+        Codec::H264 => &[
+            ("vah264dec", "VA H.264 (Intel/AMD)"),
+            ("vaapih264dec", "VA-API H.264 (Intel/AMD)"),
+            ("nvh264dec", "NVIDIA NVDEC H.264"),
+            ("vtdec", "VideoToolbox (macOS)"),
+        ],
+        Codec::H265 => &[
+            ("vah265dec", "VA H.265 (Intel/AMD)"),
+            ("vaapih265dec", "VA-API H.265 (Intel/AMD)"),
+            ("nvh265dec", "NVIDIA NVDEC H.265"),
+            ("vtdec", "VideoToolbox (macOS)"),
+        ],
+        Codec::Vp9 => &[
+            ("vavp9dec", "VA VP9 (Intel/AMD)"),
+            ("vaapivp9dec", "VA-API VP9 (Intel/AMD)"),
+            ("nvvp9dec", "NVIDIA NVDEC VP9"),
+        ],
+        Codec::Av1 => &[
+            ("vaav1dec", "VA AV1 (Intel/AMD)"),
+            ("nvav1dec", "NVIDIA NVDEC AV1"),
+        ],
+        // Decoded by `crate::images`, never by a pipeline.
+        Codec::Jpeg | Codec::Png | Codec::Raw => &[],
+    }
+}
+
+/// Software decoders used when no hardware decoder is available.
+fn sw_decoders(codec: Codec) -> &'static [&'static str] {
+    match codec {
+        Codec::H264 => &["avdec_h264"],
+        Codec::H265 => &["avdec_h265"],
+        Codec::Vp9 => &["vp9dec", "avdec_vp9"],
+        Codec::Av1 => &["dav1ddec", "av1dec"],
+        Codec::Jpeg | Codec::Png | Codec::Raw => &[],
+    }
+}
+
+/// Caps that any decoder of `codec` accepts, whatever stream format.
+fn codec_caps(codec: Codec) -> gstreamer::Caps {
+    gstreamer::Caps::new_empty_simple(format!("video/x-{}", codec.as_str()))
+}
+
+/// Whether `name` is an installed element that decodes `codec`.
+fn decodes(name: &str, codec: Codec) -> bool {
+    gstreamer::ElementFactory::find(name)
+        .is_some_and(|factory| factory.can_sink_any_caps(&codec_caps(codec)))
+}
+
+/// Decoder elements to try for `codec`, best first: the chosen element if
+/// it decodes `codec`, then hardware (unless software only), then software.
+fn candidates(codec: Codec, choice: &DecoderChoice) -> Vec<String> {
+    let mut names = Vec::new();
+    if let DecoderChoice::Element(name) = choice
+        && decodes(name, codec)
+    {
+        names.push(name.clone());
+    }
+    if *choice != DecoderChoice::Software {
+        names.extend(hw_decoders(codec).iter().map(|(name, _)| name.to_string()));
+    }
+    names.extend(sw_decoders(codec).iter().map(|name| name.to_string()));
+
+    let mut seen = std::collections::HashSet::new();
+    names.retain(|name| seen.insert(name.clone()));
+    names
+}
+
+/// Create the first candidate decoder that is not in `failed`.
+fn select_decoder(
+    codec: Codec,
+    choice: &DecoderChoice,
+    failed: &[String],
+) -> Option<(gstreamer::Element, String)> {
+    candidates(codec, choice)
+        .into_iter()
+        .filter(|name| !failed.contains(name))
+        .find_map(|name| {
+            let decoder = gstreamer::ElementFactory::make(&name)
+                .name("decoder")
+                .build()
+                .ok()?;
+            match hw_decoders(codec).iter().find(|(hw, _)| *hw == name) {
+                Some((_, label)) => println!("  Using hardware decoder: {label} ({name})"),
+                None if sw_decoders(codec).contains(&name.as_str()) => {
+                    println!("  Using software decoder: {name}")
+                }
+                None => println!("  Using decoder: {name}"),
+            }
+            Some((decoder, name))
+        })
+}
+
+/// GStreamer decoders for `codec`: the ones `auto` tries, in its order,
+/// then any other installed element that accepts the bitstream.
+/// Initializes GStreamer.
+pub(super) fn probe(codec: Codec) -> Result<Vec<DecoderProbe>, gstreamer::glib::Error> {
+    gstreamer::init()?;
+    let mut names = candidates(codec, &DecoderChoice::Auto);
+    let caps = codec_caps(codec);
+    let mut others: Vec<String> = gstreamer::ElementFactory::factories_with_type(
+        gstreamer::ElementFactoryType::DECODER,
+        gstreamer::Rank::NONE,
+    )
+    .into_iter()
+    .filter(|factory| factory.can_sink_any_caps(&caps))
+    .map(|factory| factory.name().to_string())
+    .filter(|name| !names.contains(name))
+    .collect();
+    others.sort();
+    names.extend(others);
+
+    Ok(names
+        .into_iter()
+        .map(|name| {
+            let factory = gstreamer::ElementFactory::find(&name);
+            let usable = factory
+                .as_ref()
+                .and_then(|factory| factory.create().build().ok())
+                .is_some_and(|element| {
+                    let ready = element.set_state(gstreamer::State::Ready).is_ok();
+                    let _ = element.set_state(gstreamer::State::Null);
+                    ready
+                });
+            DecoderProbe {
+                description: factory
+                    .as_ref()
+                    .map_or_else(String::new, |f| f.longname().to_string()),
+                hardware: factory.as_ref().map_or_else(
+                    || hw_decoders(codec).iter().any(|(hw, _)| *hw == name),
+                    |f| f.klass().contains("Hardware"),
+                ),
+                installed: factory.is_some(),
+                usable,
+                name,
+            }
+        })
+        .collect())
+}
+
+/// Parser element placed between appsrc and the decoder.
+fn parser(codec: Codec) -> &'static str {
+    match codec {
+        Codec::H264 => "h264parse",
+        Codec::H265 => "h265parse",
+        Codec::Vp9 => "vp9parse",
+        Codec::Av1 => "av1parse",
+        Codec::Jpeg => "jpegparse",
+        Codec::Png => "pngparse",
+        Codec::Raw => "rawvideoparse",
+    }
+}
+
+/// Create the parser for `codec`. H.264/H.265 parsers re-insert SPS/PPS
+/// before every keyframe so decoding can start from any IDR.
+pub fn make_parser(codec: Codec) -> gstreamer::Element {
+    let mut builder = gstreamer::ElementFactory::make(parser(codec));
+    if matches!(codec, Codec::H264 | Codec::H265) {
+        builder = builder.property_from_str("config-interval", "-1");
+    }
+    builder.build().expect(parser(codec))
+}
+
+/// Caps describing the bitstream pushed into appsrc.
+///
+/// H.264/H.265 arrive as Annex B byte-stream, AV1 as a low-overhead OBU
+/// stream with one temporal unit per message.
+pub fn src_caps(codec: Codec) -> gstreamer::Caps {
+    match codec {
+        Codec::H264 => gstreamer::Caps::builder("video/x-h264")
+            .field("stream-format", "byte-stream")
+            .build(),
+        Codec::H265 => gstreamer::Caps::builder("video/x-h265")
+            .field("stream-format", "byte-stream")
+            .build(),
+        Codec::Vp9 => gstreamer::Caps::builder("video/x-vp9").build(),
+        Codec::Av1 => gstreamer::Caps::builder("video/x-av1")
+            .field("stream-format", "obu-stream")
+            .field("alignment", "tu")
+            .build(),
+        Codec::Jpeg => gstreamer::Caps::builder("image/jpeg").build(),
+        Codec::Png => gstreamer::Caps::builder("image/png").build(),
+        Codec::Raw => gstreamer::Caps::builder("video/x-raw").build(),
+    }
+}
+
+/// Caps of the frames handed to the display: RGBA, and with `limit` scaled
+/// down to fit it with square pixels. `videoscale` keeps the source size
+/// when it already fits, so frames are never scaled up.
+fn output_caps(limit: Option<(u32, u32)>) -> gstreamer::Caps {
+    let mut caps = gstreamer::Caps::builder("video/x-raw").field("format", "RGBA");
+    if let Some((width, height)) = limit {
+        let max = |v: u32| v.clamp(2, i32::MAX as u32) as i32;
+        caps = caps
+            .field("width", gstreamer::IntRange::new(1, max(width)))
+            .field("height", gstreamer::IntRange::new(1, max(height)))
+            .field("pixel-aspect-ratio", gstreamer::Fraction::new(1, 1));
+    }
+    caps.build()
+}
+
+/// Start [`run_loop`] on a background thread, initializing GStreamer.
+pub(super) fn spawn(
+    config: DecoderBuilder,
+    frame_rx: FrameReceiver,
+    decoded_tx: mpsc::SyncSender<DecodedFrame>,
+) -> anyhow::Result<Decoder> {
+    gstreamer::init()?;
+    let pipeline: Arc<Mutex<Option<gstreamer::Element>>> = Arc::new(Mutex::new(None));
+    let pipeline_holder = pipeline.clone();
+    std::thread::spawn(move || {
+        run_loop(config, frame_rx, decoded_tx, pipeline_holder);
+    });
+    Ok(Decoder::new(move || {
+        if let Some(pipeline) = pipeline.lock().unwrap().take() {
+            let _ = pipeline.set_state(gstreamer::State::Null);
+        }
+    }))
+}
+
+/// Build, run, and auto-restart the GStreamer decode pipeline configured by
+/// `config`.
+///
+/// Each iteration creates a fresh pipeline for the codec of the first frame
+/// it receives. On error it tears down and rebuilds; when the codec changes
+/// mid-stream the pipeline is rebuilt for the new codec immediately.
+/// Decoded RGBA frames are sent through `decoded_tx`.
+/// The current pipeline reference is stored in `pipeline_holder` so the
+/// [`Decoder`] handle can shut it down cleanly on exit.
+///
+/// Decoders are tried in the order of the builder's [`DecoderChoice`]. One
+/// that fails to start, or fails before decoding anything, is skipped in
+/// later pipelines until every candidate has failed.
+///
+/// Frames are scaled to the builder's
+/// [`OutputSize`](crate::scaling::OutputSize); a change of its limit
+/// renegotiates the running pipeline instead of rebuilding it.
+///
+/// While `recording` is enabled, the compressed frames are also written to
+/// disk under `name`; the file is finalized whenever the pipeline stops. If
+/// this stream's recording fails, only this stream stops recording, until
+/// the toggle is switched off and on again.
+///
+/// Still images need no pipeline and are decoded in software as they come.
+///
+/// Each pipeline is only fed from a keyframe on, and resyncs to the next
+/// keyframe after a gap in the stream (see [`KeyframeGate`]).
+///
+/// With `keyframe_requests`, a keyframe is requested whenever a pipeline
+/// starts, after a gap and when the decoder warns about the data, so decoding
+/// does not wait for the next scheduled keyframe.
+fn run_loop(
+    config: DecoderBuilder,
+    frame_rx: FrameReceiver,
+    decoded_tx: mpsc::SyncSender<DecodedFrame>,
+    pipeline_holder: Arc<Mutex<Option<gstreamer::Element>>>,
+) {
+    let DecoderBuilder {
+        name,
+        recording,
+        keyframe_requests,
+        timestamp_gaps,
+        output_size,
+        backend: _,
+        decoder: choice,
+    } = config;
+
+    // Frame that triggered a codec switch, fed first into the next pipeline.
+    let mut pending: Option<EncodedFrame> = None;
+    // Why the next pipeline starts, for the keyframe request.
+    let mut start_reason = "start";
+
+    // Stand-in source clock for frames that carry no timestamp.
+    let arrival_epoch = Instant::now();
+
+    // Outlives the pipelines so parameter sets and counters carry over.
+    let mut gate: Option<KeyframeGate> = None;
+
+    // Decoders that failed at runtime, skipped until all candidates have.
+    let mut failed: Vec<String> = Vec::new();
+
+    // Whether the stream was told it shows still images that are not recorded.
+    let mut image_not_recorded = false;
+
+    // This stream's recording failed; the shared toggle stays as it is.
+    let mut recording_failed = false;
+
+    loop {
+        // The pipeline layout depends on the codec, so wait for a frame first.
+        let first = match pending.take() {
+            Some(frame) => frame,
+            None => match frame_rx.recv() {
+                Ok(frame) => frame,
+                Err(_) => break,
+            },
+        };
+        let codec = first.codec;
+        if codec.is_image() {
+            // The toggle is shared with the video streams; only this one
+            // goes unrecorded.
+            if recording.is_enabled() && !image_not_recorded {
+                eprintln!("  [{name}] Still images are not recorded");
+                image_not_recorded = true;
+            }
+            super::show_image(&first, &name.get(), &output_size, &decoded_tx);
+            continue;
+        }
+        let gate = gate.get_or_insert_with(|| KeyframeGate::new(codec, timestamp_gaps));
+        gate.reset(codec);
+
+        println!("Starting GStreamer decode pipeline ({codec})...");
+
+        let mut selected = select_decoder(codec, &choice, &failed);
+        if selected.is_none() && !failed.is_empty() {
+            println!("  Every {codec} decoder failed, trying them all again");
+            failed.clear();
+            selected = select_decoder(codec, &choice, &failed);
+        }
+        let Some((decoder, decoder_name)) = selected else {
+            eprintln!("No {codec} decoder available; see `player probe-decoders`");
+            // Drop this codec until the stream switches to another one.
+            METRICS
+                .dropped
+                .with_label_values(&[&name.get(), "no decoder"])
+                .inc();
+            pending = loop {
+                match frame_rx.recv() {
+                    Ok(frame) if frame.codec == codec => {
+                        METRICS
+                            .dropped
+                            .with_label_values(&[&name.get(), "no decoder"])
+                            .inc();
+                    }
+                    Ok(frame) => break Some(frame),
+                    Err(_) => break None,
+                }
+            };
+            if pending.is_none() {
+                break;
+            }
+            continue;
+        };
+
+        let decoded_tx = decoded_tx.clone();
+
+        // Build the pipeline manually to avoid gst_base_src_loop issues.
+        let pipeline = gstreamer::Pipeline::new();
+
+        let appsrc = gstreamer_app::AppSrc::builder()
+            .name("src")
+            .is_live(true)
+            .format(gstreamer::Format::Time)
+            .build();
+        appsrc.set_caps(Some(&src_caps(codec)));
+
+        let parse = make_parser(codec);
+
+        let videoscale =
+            gstreamer::ElementFactory::make("videoscale").build().expect("videoscale");
+        let videoconvert =
+            gstreamer::ElementFactory::make("videoconvert").build().expect("videoconvert");
+
+        let mut output_limit = output_size.limit();
+        let capsfilter = gstreamer::ElementFactory::make("capsfilter")
+            .property("caps", output_caps(output_limit))
+            .build()
+            .expect("capsfilter");
+
+        let appsink = gstreamer_app::AppSink::builder()
+            .name("sink")
+            .max_buffers(1)
+            .drop(true)
+            .sync(false)
+            .build();
+
+        // Add and link all elements
+        pipeline
+            .add_many([
+                appsrc.upcast_ref(),
+                &parse,
+                &decoder,
+                &videoscale,
+                &videoconvert,
+                &capsfilter,
+                appsink.upcast_ref(),
+            ])
+            .expect("Failed to add elements");
+
+        gstreamer::Element::link_many([
+            appsrc.upcast_ref(),
+            &parse,
+            &decoder,
+            &videoscale,
+            &videoconvert,
+            &capsfilter,
+            appsink.upcast_ref(),
+        ])
+        .expect("Failed to link elements");
+
+        // Store pipeline reference for clean shutdown
+        {
+            let mut holder = pipeline_holder.lock().unwrap();
+            *holder = Some(pipeline.clone().upcast());
+        }
+
+        // Frames pushed into appsrc, so the appsink callback can match
+        // decoded output back to its input.
+        let in_flight: Arc<Mutex<InFlight>> =
+            Arc::new(Mutex::new(VecDeque::with_capacity(MAX_IN_FLIGHT)));
+        let in_flight_cb = in_flight.clone();
+        let name_cb = name.clone();
+        // Whether this pipeline has produced a frame yet.
+        let decoded_any = Arc::new(AtomicBool::new(false));
+        let decoded_any_cb = decoded_any.clone();
+        // Decoder output before scaling, for the source resolution.
+        let scaler_input = videoscale.static_pad("sink").expect("videoscale sink pad");
+
+        // Forward decoded RGBA frames from appsink
+        appsink.set_callbacks(
+            gstreamer_app::AppSinkCallbacks::builder()
+                .new_sample(move |appsink| {
+                    let sample = appsink
+                        .pull_sample()
+                        .map_err(|_| gstreamer::FlowError::Error)?;
+                    let caps = sample.caps().ok_or(gstreamer::FlowError::Error)?;
+                    let info = gstreamer_video::VideoInfo::from_caps(caps)
+                        .map_err(|_| gstreamer::FlowError::Error)?;
+                    let (source_width, source_height) = scaler_input
+                        .current_caps()
+                        .and_then(|caps| gstreamer_video::VideoInfo::from_caps(&caps).ok())
+                        .map_or((info.width(), info.height()), |source| {
+                            (source.width(), source.height())
+                        });
+                    let buffer = sample.buffer().ok_or(gstreamer::FlowError::Error)?;
+                    let map = buffer
+                        .map_readable()
+                        .map_err(|_| gstreamer::FlowError::Error)?;
+
+                    // Decoders keep the input PTS, so everything queued up to
+                    // this PTS has either produced this frame or been dropped.
+                    // Dropped frames still count towards the bitrate.
+                    let pts_ns = buffer.pts().map(|t| t.nseconds());
+                    let mut compressed_size = 0;
+                    let mut frames = 0;
+                    let mut timing = None;
+                    {
+                        let mut in_flight = in_flight_cb.lock().unwrap();
+                        while let Some(&(queued_pts, size, queued_timing)) = in_flight.front() {
+                            if pts_ns.is_some_and(|pts| queued_pts > pts) {
+                                break;
+                            }
+                            in_flight.pop_front();
+                            compressed_size += size;
+                            frames += 1;
+                            timing = Some(queued_timing);
+                            if pts_ns.is_none() {
+                                break;
+                            }
+                        }
+                    }
+                    if let Some(timing) = &mut timing {
+                        timing.decoded = Some(Instant::now());
+                    }
+
+                    decoded_any_cb.store(true, Ordering::Relaxed);
+                    METRICS.decoded.with_label_values(&[&name_cb.get()]).inc();
+                    if frames > 1 {
+                        METRICS
+                            .dropped
+                            .with_label_values(&[&name_cb.get(), "decoder"])
+                            .inc_by(frames - 1);
+                    }
+
+                    let sent = decoded_tx.try_send(DecodedFrame {
+                        pixels: map.as_slice().to_vec(),
+                        format: PixelFormat::Rgba,
+                        width: info.width(),
+                        height: info.height(),
+                        source_width,
+                        source_height,
+                        timing,
+                        compressed_size,
+                    });
+                    if sent.is_err() {
+                        // The display is not keeping up.
+                        METRICS
+                            .dropped
+                            .with_label_values(&[&name_cb.get(), "display"])
+                            .inc();
+                    }
+                    Ok(gstreamer::FlowSuccess::Ok)
+                })
+                .build(),
+        );
+
+        // Hardware decoders often only find out here that the device is
+        // missing or cannot handle the stream.
+        if let Err(e) = pipeline.set_state(gstreamer::State::Playing) {
+            eprintln!("  Decoder {decoder_name} failed to start: {e}");
+            let _ = pipeline.set_state(gstreamer::State::Null);
+            failed.push(decoder_name);
+            pending = Some(first);
+            continue;
+        }
+
+        println!("  Pipeline playing (decoder: {decoder_name})");
+        if let Some(requester) = &keyframe_requests {
+            requester.request(start_reason);
+        }
+
+        // Watch the bus for errors on a background thread.
+        // Sets got_error flag so the main pump loop knows to restart, and
+        // decoder_failed if the decoder itself is to blame: the error came
+        // from it, or nothing was decoded before it.
+        let got_error = Arc::new(Mutex::new(false));
+        let got_error_bus = got_error.clone();
+        let decoder_failed = Arc::new(Mutex::new(false));
+        let decoder_failed_bus = decoder_failed.clone();
+        let keyframe_requests_bus = keyframe_requests.clone();
+        let bus = pipeline.bus().expect("Pipeline has no bus");
+        let bus_watch = bus.clone();
+        let bus_thread = std::thread::spawn(move || {
+            for msg in bus_watch.iter_timed(gstreamer::ClockTime::NONE) {
+                use gstreamer::MessageView;
+                match msg.view() {
+                    MessageView::Error(err) => {
+                        eprintln!(
+                            "GStreamer ERROR from {:?}: {}",
+                            err.src().map(|s| s.path_string()),
+                            err.error(),
+                        );
+                        if let Some(debug) = err.debug() {
+                            eprintln!("  debug: {debug}");
+                        }
+                        if err.src().is_some_and(|s| s.name() == "decoder")
+                            || !decoded_any.load(Ordering::Relaxed)
+                        {
+                            *decoder_failed_bus.lock().unwrap() = true;
+                        }
+                        *got_error_bus.lock().unwrap() = true;
+                        break;
+                    }
+                    MessageView::Warning(warn) => {
+                        eprintln!("GStreamer WARNING: {}", warn.error());
+                        if let Some(debug) = warn.debug() {
+                            eprintln!("  debug: {debug}");
+                        }
+                        // Decoders warn about data they could not decode.
+                        if warn.error().matches(gstreamer::StreamError::Decode)
+                            && let Some(requester) = &keyframe_requests_bus
+                        {
+                            requester.request("corruption");
+                        }
+                    }
+                    MessageView::Eos(..) => {
+                        eprintln!("GStreamer: unexpected EOS, restarting...");
+                        *got_error_bus.lock().unwrap() = true;
+                        break;
+                    }
+                    MessageView::Application(..) => break, // teardown request
+                    _ => {}
+                }
+            }
+        });
+
+        // Push compressed data directly into appsrc, with PTS derived from
+        // the source timestamps.
+        let mut pts_clock = PtsClock::new(name.clone());
+        let mut pump_count: u64 = 0;
+        let mut next = Some(first);
+        let mut recorder: Option<Recorder> = None;
+
+        loop {
+            // Check if the bus thread detected an error
+            if *got_error.lock().unwrap() {
+                break;
+            }
+
+            // Follow scaling mode and window size changes.
+            let limit = output_size.limit();
+            if limit != output_limit {
+                output_limit = limit;
+                capsfilter.set_property("caps", output_caps(limit));
+            }
+
+            // Start or finalize the recording to follow the toggle.
+            let enabled = recording.is_enabled();
+            if !enabled {
+                recording_failed = false;
+            }
+            match (&recorder, enabled && !recording_failed) {
+                (None, true) => match Recorder::start(recording.clone(), codec, &name.get()) {
+                    Ok(r) => recorder = Some(r),
+                    Err(e) => {
+                        eprintln!("  [{name}] Recording failed to start: {e:#}");
+                        recording_failed = true;
+                    }
+                },
+                (Some(_), false) => recorder = None,
+                _ => {}
+            }
+
+            let mut frame = match next.take() {
+                Some(frame) => frame,
+                None => match frame_rx.recv_timeout(Duration::from_millis(100)) {
+                    Ok(frame) if frame.codec == codec => frame,
+                    Ok(frame) => {
+                        pending = Some(frame);
+                        break;
+                    }
+                    Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                    Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                },
+            };
+
+            if !super::admit(gate, &mut frame, &name.get(), keyframe_requests.as_ref()) {
+                continue;
+            }
+            let EncodedFrame { data, timing, .. } = frame;
+
+            pump_count += 1;
+            if pump_count <= 3 || pump_count % 500 == 0 {
+                println!("  Pump #{pump_count}: {} bytes", data.len());
+            }
+
+            let src_ns = timing.timestamp.map_or_else(
+                || timing.received.duration_since(arrival_epoch).as_nanos() as u64,
+                |ts| ts.as_nanos(),
+            );
+            let (pts_ns, duration_ns) = pts_clock.next(src_ns);
+
+            {
+                let mut in_flight = in_flight.lock().unwrap();
+                if in_flight.len() >= MAX_IN_FLIGHT {
+                    in_flight.pop_front();
+                }
+                in_flight.push_back((pts_ns, data.len(), timing));
+            }
+            // Wraps the frame data, often still the received Zenoh payload,
+            // without copying it.
+            let mut buffer = gstreamer::Buffer::from_slice(data);
+            {
+                let buf_ref = buffer.get_mut().unwrap();
+                buf_ref.set_pts(gstreamer::ClockTime::from_nseconds(pts_ns));
+                buf_ref.set_duration(gstreamer::ClockTime::from_nseconds(duration_ns));
+            }
+
+            if let Some(r) = &mut recorder
+                && let Err(e) = r.push(&buffer)
+            {
+                eprintln!("  [{name}] Recording stopped: {e:#}");
+                recording_failed = true;
+                recorder = None;
+            }
+
+            if appsrc.push_buffer(buffer).is_err() {
+                eprintln!("  appsrc push failed at #{pump_count}");
+                break;
+            }
+        }
+
+        // Tear down. The bus thread only stops by itself on error/EOS, so
+        // wake it with an application message before joining.
+        let _ = bus.post(gstreamer::message::Application::new(
+            gstreamer::Structure::new_empty("teardown"),
+        ));
+        let _ = bus_thread.join();
+        let _ = pipeline.set_state(gstreamer::State::Null);
+        let _ = appsrc.end_of_stream();
+        drop(recorder);
+
+        if let Some(frame) = &pending {
+            println!(
+                "Video format changed {codec} → {}, rebuilding pipeline...",
+                frame.codec
+            );
+            start_reason = "codec change";
+            continue;
+        }
+
+        // Drain stale compressed data
+        let mut stale = 0;
+        while frame_rx.try_recv().is_ok() {
+            stale += 1;
+        }
+        METRICS
+            .dropped
+            .with_label_values(&[&name.get(), "restart"])
+            .inc_by(stale);
+
+        if !*got_error.lock().unwrap() {
+            break; // clean shutdown (channel disconnected)
+        }
+
+        if *decoder_failed.lock().unwrap() {
+            println!("  Decoder {decoder_name} failed, trying the next one");
+            failed.push(decoder_name);
+        }
+
+        METRICS
+            .pipeline_restarts
+            .with_label_values(&[&name.get()])
+            .inc();
+        start_reason = "restart";
+        println!("Restarting pipeline in 500ms...");
+        std::thread::sleep(Duration::from_millis(500));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEC: u64 = 1_000_000_000;
+
+    /// PTS handed out for frames captured at `src`.
+    fn pts(clock: &mut PtsClock, src: &[u64]) -> Vec<u64> {
+        src.iter().map(|&ns| clock.next(ns).0).collect()
+    }
+
+    fn discontinuities(name: &str) -> u64 {
+        METRICS
+            .timestamp_discontinuities
+            .with_label_values(&[name])
+            .get()
+    }
+
+    #[test]
+    fn steady_rates_follow_the_source() {
+        for fps in [15, 25, 60] {
+            let name = format!("steady-{fps}");
+            let interval = SEC / fps;
+            let mut clock = PtsClock::new(name.as_str().into());
+            // Starts far from zero, as a wall clock stamp would.
+            let src: Vec<u64> = (0..10)
+                .map(|n| 1_700_000_000 * SEC + n * interval)
+                .collect();
+            let expected: Vec<u64> = (0..10).map(|n| n * interval).collect();
+            assert_eq!(pts(&mut clock, &src), expected);
+            assert_eq!(clock.next(src[9] + interval), (10 * interval, interval));
+            assert_eq!(discontinuities(&name), 0);
+        }
+    }
+
+    #[test]
+    fn dropped_frame_keeps_source_time() {
+        let interval = SEC / 30;
+        let mut clock = PtsClock::new("dropped-frame".into());
+        let src = [0, interval, 3 * interval, 4 * interval];
+        assert_eq!(pts(&mut clock, &src), src);
+        assert_eq!(discontinuities("dropped-frame"), 0);
+    }
+
+    #[test]
+    fn backwards_timestamp_advances_one_frame() {
+        let interval = SEC / 25;
+        let mut clock = PtsClock::new("backwards".into());
+        pts(&mut clock, &[10 * SEC, 10 * SEC + interval]);
+        assert_eq!(clock.next(9 * SEC), (2 * interval, interval));
+        // The source clock is followed again from the new origin.
+        assert_eq!(clock.next(9 * SEC + interval), (3 * interval, interval));
+        assert_eq!(discontinuities("backwards"), 1);
+    }
+
+    #[test]
+    fn duplicate_timestamps_stay_strictly_increasing() {
+        let interval = SEC / 60;
+        let mut clock = PtsClock::new("duplicate".into());
+        let src = [0, interval, interval, interval, 2 * interval];
+        assert_eq!(
+            pts(&mut clock, &src),
+            [0, interval, 2 * interval, 3 * interval, 4 * interval]
+        );
+        // A run of repeated stamps counts every one.
+        assert_eq!(discontinuities("duplicate"), 2);
+    }
+
+    #[test]
+    fn large_jump_advances_one_frame() {
+        let interval = SEC / 15;
+        let mut clock = PtsClock::new("jump".into());
+        pts(&mut clock, &[0, interval]);
+        let jump = interval + MAX_TIMESTAMP_JUMP_NS + 1;
+        assert_eq!(clock.next(jump), (2 * interval, interval));
+        assert_eq!(clock.next(jump + interval), (3 * interval, interval));
+        // A jump of exactly the limit is still real time.
+        let mut clock = PtsClock::new("jump-limit".into());
+        pts(&mut clock, &[0]);
+        assert_eq!(clock.next(MAX_TIMESTAMP_JUMP_NS).0, MAX_TIMESTAMP_JUMP_NS);
+        assert_eq!(discontinuities("jump"), 1);
+        assert_eq!(discontinuities("jump-limit"), 0);
+    }
+}
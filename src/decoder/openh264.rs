@@ -0,0 +1,172 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, mpsc};
+use std::time::{Duration, Instant};
+
+use image::RgbaImage;
+use openh264::formats::YUVSource;
+
+use crate::codec::Codec;
+use crate::frame::DecodedFrame;
+use crate::gate::KeyframeGate;
+use crate::metrics::METRICS;
+use crate::queue::FrameReceiver;
+
+use super::{Decoder, DecoderBuilder, DecoderProbe};
+
+/// Start [`run_loop`] on a background thread.
+pub(super) fn spawn(
+    config: DecoderBuilder,
+    frame_rx: FrameReceiver,
+    decoded_tx: mpsc::SyncSender<DecodedFrame>,
+) -> anyhow::Result<Decoder> {
+    // Fail now rather than on the first frame if the library is unusable.
+    openh264::decoder::Decoder::new()?;
+    let stop = Arc::new(AtomicBool::new(false));
+    let stop_thread = stop.clone();
+    std::thread::spawn(move || {
+        run_loop(config, frame_rx, decoded_tx, &stop_thread);
+    });
+    Ok(Decoder::new(move || stop.store(true, Ordering::Relaxed)))
+}
+
+/// The built-in decoder, as `probe-decoders` lists it.
+pub(super) fn probe() -> DecoderProbe {
+    DecoderProbe {
+        name: "openh264".to_string(),
+        description: "OpenH264 (built in)".to_string(),
+        hardware: false,
+        installed: true,
+        usable: openh264::decoder::Decoder::new().is_ok(),
+    }
+}
+
+/// Decode H.264 frames from `frame_rx` into `decoded_tx` with OpenH264 until
+/// `frame_rx` disconnects or `stop` is set: the same contract as the
+/// GStreamer pipeline, without any plugins.
+///
+/// Still images are decoded too; frames of other codecs are dropped.
+/// Decoding starts at a keyframe and resyncs after gaps through the same
+/// [`KeyframeGate`]; after a decode error the decoder is recreated and waits
+/// for the next keyframe. Frames are scaled down to the builder's
+/// [`OutputSize`](crate::scaling::OutputSize) in software. Recording needs
+/// the GStreamer backend; the stream says so once and is not recorded.
+fn run_loop(
+    config: DecoderBuilder,
+    frame_rx: FrameReceiver,
+    decoded_tx: mpsc::SyncSender<DecodedFrame>,
+    stop: &AtomicBool,
+) {
+    let DecoderBuilder {
+        name: stream_name,
+        recording,
+        keyframe_requests,
+        timestamp_gaps,
+        output_size,
+        ..
+    } = config;
+
+    let mut decoder: Option<openh264::decoder::Decoder> = None;
+    let mut gate = KeyframeGate::new(Codec::H264, timestamp_gaps);
+    // Why the next decoder starts, for the keyframe request.
+    let mut start_reason = "start";
+    // Last unsupported codec reported, so it is logged once.
+    let mut unsupported: Option<Codec> = None;
+    // Compressed bytes consumed since the previous decoded frame.
+    let mut compressed_size = 0;
+    // Whether the stream was told that it is not recorded.
+    let mut not_recorded = false;
+
+    while !stop.load(Ordering::Relaxed) {
+        // Follows the stream to another topic.
+        let name = stream_name.get();
+
+        // The toggle is shared with every stream; only log for this one.
+        if recording.is_enabled() && !not_recorded {
+            eprintln!("  [{name}] Recording needs the GStreamer backend");
+            not_recorded = true;
+        }
+
+        let mut frame = match frame_rx.recv_timeout(Duration::from_millis(100)) {
+            Ok(frame) => frame,
+            Err(mpsc::RecvTimeoutError::Timeout) => continue,
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        };
+        if frame.codec.is_image() {
+            super::show_image(&frame, &name, &output_size, &decoded_tx);
+            continue;
+        }
+        if frame.codec != Codec::H264 {
+            if unsupported != Some(frame.codec) {
+                eprintln!(
+                    "OpenH264 cannot decode {}, dropping its frames",
+                    frame.codec
+                );
+                unsupported = Some(frame.codec);
+            }
+            METRICS
+                .dropped
+                .with_label_values(&[&name, "unsupported codec"])
+                .inc();
+            continue;
+        }
+
+        let active = match &mut decoder {
+            Some(active) => active,
+            None => match openh264::decoder::Decoder::new() {
+                Ok(new) => {
+                    println!("Starting OpenH264 decoder...");
+                    gate.reset(Codec::H264);
+                    compressed_size = 0;
+                    if let Some(requester) = &keyframe_requests {
+                        requester.request(start_reason);
+                    }
+                    decoder.insert(new)
+                }
+                Err(e) => {
+                    eprintln!("OpenH264 failed to start: {e}");
+                    break;
+                }
+            },
+        };
+
+        if !super::admit(&mut gate, &mut frame, &name, keyframe_requests.as_ref()) {
+            continue;
+        }
+        compressed_size += frame.data.len();
+
+        let failed = match active.decode(&frame.data) {
+            Ok(Some(yuv)) => {
+                let (width, height) = yuv.dimensions();
+                let mut rgba = vec![0; width * height * 4];
+                yuv.write_rgba8(&mut rgba);
+                let mut timing = frame.timing;
+                timing.decoded = Some(Instant::now());
+                let image = RgbaImage::from_raw(width as u32, height as u32, rgba)
+                    .expect("buffer sized to the frame");
+                super::send_rgba(
+                    image,
+                    Some(timing),
+                    std::mem::take(&mut compressed_size),
+                    &name,
+                    &output_size,
+                    &decoded_tx,
+                );
+                false
+            }
+            // The decoder needs more data for a picture.
+            Ok(None) => false,
+            Err(e) => {
+                eprintln!("OpenH264 decode error: {e}, restarting at the next keyframe");
+                true
+            }
+        };
+        if failed {
+            METRICS.pipeline_restarts.with_label_values(&[&name]).inc();
+            if let Some(requester) = &keyframe_requests {
+                requester.request("corruption");
+            }
+            start_reason = "restart";
+            decoder = None;
+        }
+    }
+}
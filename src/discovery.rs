@@ -0,0 +1,153 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use zenoh::sample::SampleKind;
+
+use crate::cdr;
+use crate::codec::Codec;
+use crate::nal;
+
+/// Window over which the publish rate is measured.
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+/// Topics not heard from for this long are shown as stale.
+pub const STALE_AFTER: Duration = Duration::from_secs(5);
+
+/// What has been learned about one key publishing CompressedVideo,
+/// CompressedImage or RawImage.
+#[derive(Debug, Clone)]
+pub struct DiscoveredTopic {
+    pub codec: Option<Codec>,
+    /// Raw `format` (RawImage: `encoding`) string as published, kept for
+    /// unknown formats.
+    pub format: String,
+    /// Resolution from the first SPS seen (H.264/H.265) or from the header
+    /// (RawImage).
+    pub resolution: Option<(u32, u32)>,
+    /// Messages per second over the last `RATE_WINDOW`.
+    pub rate: f32,
+    pub samples: u64,
+    pub last_seen: Instant,
+    /// Liveliness token state for this key, if the publisher declares one.
+    pub alive: Option<bool>,
+
+    window_start: Instant,
+    window_samples: u32,
+}
+
+impl DiscoveredTopic {
+    fn new(message: &cdr::Message) -> Self {
+        let now = Instant::now();
+        Self {
+            codec: message.codec(),
+            format: message.format().to_string(),
+            resolution: None,
+            rate: 0.0,
+            samples: 0,
+            last_seen: now,
+            alive: None,
+            window_start: now,
+            window_samples: 0,
+        }
+    }
+
+    /// Account for one more sample.
+    fn record(&mut self, message: &cdr::Message) {
+        let now = Instant::now();
+        if message.format() != self.format {
+            self.codec = message.codec();
+            self.format = message.format().to_string();
+            self.resolution = None;
+        }
+        if let cdr::Message::Raw(image) = message {
+            self.resolution = Some((image.width, image.height));
+        } else if self.resolution.is_none() {
+            self.resolution = self
+                .codec
+                .and_then(|codec| nal::find_sps(codec, message.data()))
+                .map(|sps| (sps.width, sps.height));
+        }
+
+        self.samples += 1;
+        self.last_seen = now;
+        self.window_samples += 1;
+        let elapsed = now.duration_since(self.window_start);
+        if elapsed >= RATE_WINDOW {
+            self.rate = self.window_samples as f32 / elapsed.as_secs_f32();
+            self.window_start = now;
+            self.window_samples = 0;
+        }
+    }
+
+    pub fn is_stale(&self) -> bool {
+        self.last_seen.elapsed() >= STALE_AFTER || self.alive == Some(false)
+    }
+}
+
+/// Topics found so far, keyed by Zenoh key expression.
+pub type Discovered = Arc<Mutex<BTreeMap<String, DiscoveredTopic>>>;
+
+/// Subscribe to `key_expr` (usually a wildcard such as `video/**`) and record
+/// every key that publishes a CDR CompressedVideo, CompressedImage or
+/// RawImage. Liveliness tokens on the
+/// same key expression mark topics alive or gone.
+///
+/// Runs until the task is aborted.
+pub async fn run(session: zenoh::Session, key_expr: String, discovered: Discovered) {
+    let subscriber = match session.declare_subscriber(&key_expr).await {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("Discovery: failed to subscribe to '{key_expr}': {e}");
+            return;
+        }
+    };
+    let liveliness = match session
+        .liveliness()
+        .declare_subscriber(&key_expr)
+        .history(true)
+        .await
+    {
+        Ok(s) => Some(s),
+        Err(e) => {
+            eprintln!("Discovery: liveliness unavailable on '{key_expr}': {e}");
+            None
+        }
+    };
+    println!("Discovery active on '{key_expr}'");
+
+    // Tokens usually arrive before the first data sample of their key.
+    let mut tokens: HashMap<String, bool> = HashMap::new();
+
+    loop {
+        tokio::select! {
+            sample = subscriber.recv_async() => {
+                let Ok(sample) = sample else { break };
+                let payload = sample.payload().to_bytes();
+                // Only keys carrying a known schema are listed.
+                let Ok(msg) = cdr::decode_message(&payload, cdr::Schema::Auto) else {
+                    continue;
+                };
+                let key = sample.key_expr().as_str().to_string();
+                let alive = tokens.get(&key).copied();
+                let mut discovered = discovered.lock().unwrap();
+                discovered
+                    .entry(key)
+                    .or_insert_with(|| DiscoveredTopic {
+                        alive,
+                        ..DiscoveredTopic::new(&msg)
+                    })
+                    .record(&msg);
+            }
+            token = async { liveliness.as_ref()?.recv_async().await.ok() }, if liveliness.is_some() => {
+                let Some(token) = token else { break };
+                let key = token.key_expr().as_str();
+                let alive = token.kind() == SampleKind::Put;
+                tokens.insert(key.to_string(), alive);
+                if let Some(topic) = discovered.lock().unwrap().get_mut(key) {
+                    topic.alive = Some(alive);
+                }
+            }
+        }
+    }
+}
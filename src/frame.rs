@@ -0,0 +1,128 @@
+use std::ops::{Deref, Range};
+
+use zenoh::bytes::ZBytes;
+
+use crate::cdr::{Encapsulation, Timestamp};
+use crate::codec::Codec;
+use crate::images::RawLayout;
+use crate::latency::FrameTiming;
+
+/// One compressed video frame (or still image) on its way from Zenoh (or an
+/// MCAP file) to a decoder.
+#[derive(Debug, Clone)]
+pub struct EncodedFrame {
+    /// Bitstream of one access unit / temporal unit, or one image.
+    pub data: FrameData,
+    pub codec: Codec,
+    /// Pixel layout of `data` for [`Codec::Raw`], `None` otherwise.
+    pub raw: Option<RawLayout>,
+    /// CDR representation of the message, `None` for bare Annex B.
+    pub encapsulation: Option<Encapsulation>,
+    /// `frame_id` of the message (empty for raw payloads).
+    pub frame_id: String,
+    /// Zenoh key the sample arrived on.
+    pub key: String,
+    /// Publisher frame counter, sent as an 8-byte little-endian sample
+    /// attachment, if any.
+    pub sequence: Option<u64>,
+    pub timing: FrameTiming,
+}
+
+impl EncodedFrame {
+    /// Timestamp carried in the message, if set.
+    pub fn timestamp(&self) -> Option<Timestamp> {
+        self.timing.timestamp
+    }
+}
+
+/// Bytes of an [`EncodedFrame`]: owned, or part of a received Zenoh payload
+/// that is shared, without a copy, all the way into the decoder.
+#[derive(Debug, Clone)]
+pub enum FrameData {
+    Owned(Vec<u8>),
+    /// `range` of the payload, which is a single contiguous slice.
+    Payload {
+        payload: ZBytes,
+        range: Range<usize>,
+    },
+}
+
+impl FrameData {
+    /// `data`, a part of `payload`, shared if `payload` is one contiguous
+    /// slice; copied if it is fragmented or `data` lies outside it.
+    pub fn from_payload(payload: &ZBytes, data: &[u8]) -> Self {
+        let mut slices = payload.slices();
+        if let (Some(slice), None) = (slices.next(), slices.next()) {
+            let (outer, inner) = (slice.as_ptr_range(), data.as_ptr_range());
+            if outer.start <= inner.start && inner.end <= outer.end {
+                let start = inner.start.addr() - outer.start.addr();
+                return Self::Payload {
+                    payload: payload.clone(),
+                    range: start..start + data.len(),
+                };
+            }
+        }
+        Self::Owned(data.to_vec())
+    }
+
+    /// The bytes to modify, copied out of a shared payload first.
+    pub fn to_mut(&mut self) -> &mut Vec<u8> {
+        if let Self::Payload { .. } = self {
+            *self = Self::Owned(self.to_vec());
+        }
+        match self {
+            Self::Owned(data) => data,
+            Self::Payload { .. } => unreachable!("copied above"),
+        }
+    }
+}
+
+impl Deref for FrameData {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Self::Owned(data) => data,
+            Self::Payload { payload, range } => {
+                &payload.slices().next().expect("contiguous payload")[range.clone()]
+            }
+        }
+    }
+}
+
+impl AsRef<[u8]> for FrameData {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl From<Vec<u8>> for FrameData {
+    fn from(data: Vec<u8>) -> Self {
+        Self::Owned(data)
+    }
+}
+
+/// Pixel layout of a [`DecodedFrame`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// 8-bit RGBA, 4 bytes per pixel, rows tightly packed.
+    Rgba,
+}
+
+/// One decoded frame ready for display.
+#[derive(Debug, Clone)]
+pub struct DecodedFrame {
+    pub pixels: Vec<u8>,
+    pub format: PixelFormat,
+    pub width: u32,
+    pub height: u32,
+    /// Size the decoder produced, before scaling for display.
+    pub source_width: u32,
+    pub source_height: u32,
+    /// Timing of the compressed frame this was decoded from, if it could be
+    /// matched.
+    pub timing: Option<FrameTiming>,
+    /// Compressed bytes consumed since the previous decoded frame, including
+    /// frames the decoder dropped.
+    pub compressed_size: usize,
+}
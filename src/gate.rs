@@ -0,0 +1,389 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::codec::Codec;
+use crate::frame::{EncodedFrame, FrameData};
+use crate::nal;
+
+/// A timestamp step this many nominal frame durations long is a gap.
+const GAP_FRAMES: u64 = 3;
+
+/// Recent timestamp steps whose median is the nominal frame duration.
+const RECENT_STEPS: usize = 8;
+
+/// Steps needed before the median is trusted to find gaps.
+const MIN_STEPS: usize = 3;
+
+/// What the decoder should do with a frame, see [`KeyframeGate::admit`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Admit {
+    /// Decodable: push it.
+    Decode,
+    /// Depends on data the decoder never got; drop it and keep waiting for
+    /// a keyframe.
+    Wait,
+    /// Data was lost just before this frame. Drop it and wait for a
+    /// keyframe; the count of missing frames is known with a frame counter.
+    Gap { missing: Option<u64> },
+}
+
+/// Keeps undecodable data away from the decoder.
+///
+/// Nothing passes until a keyframe does, at start and again after every
+/// detected gap. Gaps are found from the publisher's frame counter when it
+/// sends one, and otherwise (if enabled) from jumps in the source
+/// timestamps.
+///
+/// For H.264/H.265 the latest parameter sets are remembered, and prepended
+/// to a keyframe that arrives without them, so a stream whose SPS/PPS were
+/// sent separately can still start at its next IDR.
+pub struct KeyframeGate {
+    codec: Codec,
+    synced: bool,
+    timestamp_gaps: bool,
+    /// Latest parameter set NAL unit of each type.
+    parameter_sets: BTreeMap<u8, Vec<u8>>,
+    last_sequence: Option<u64>,
+    last_src_ns: Option<u64>,
+    /// Source time between the last frames, newest last. Their median
+    /// stands up to jitter and batched frames, unlike the last step alone.
+    recent_steps: VecDeque<u64>,
+}
+
+impl KeyframeGate {
+    /// With `timestamp_gaps`, timestamp jumps count as gaps for frames
+    /// without a frame counter.
+    pub fn new(codec: Codec, timestamp_gaps: bool) -> Self {
+        Self {
+            codec,
+            synced: false,
+            timestamp_gaps,
+            parameter_sets: BTreeMap::new(),
+            last_sequence: None,
+            last_src_ns: None,
+            recent_steps: VecDeque::with_capacity(RECENT_STEPS),
+        }
+    }
+
+    /// Wait for a keyframe again, e.g. for a fresh decoder. Parameter sets
+    /// are kept unless the codec changes.
+    pub fn reset(&mut self, codec: Codec) {
+        if codec != self.codec {
+            *self = Self::new(codec, self.timestamp_gaps);
+        }
+        self.synced = false;
+    }
+
+    /// Check `frame` and, for a keyframe missing parameter sets, add them.
+    pub fn admit(&mut self, frame: &mut EncodedFrame) -> Admit {
+        let gap = self.gap(frame);
+        if gap.is_some() {
+            self.synced = false;
+        }
+
+        self.remember_parameter_sets(&frame.data);
+        let mut keyframe = nal::is_keyframe(self.codec, &frame.data);
+        if keyframe && !self.synced {
+            self.complete_parameter_sets(&mut frame.data);
+            keyframe = self.has_parameter_sets(&frame.data);
+        }
+
+        if keyframe {
+            self.synced = true;
+        }
+        match gap {
+            Some(missing) if !self.synced => Admit::Gap { missing },
+            _ if !self.synced => Admit::Wait,
+            _ => Admit::Decode,
+        }
+    }
+
+    /// `Some` if data was lost before `frame`, with the number of missing
+    /// frames when the frame counter tells.
+    fn gap(&mut self, frame: &EncodedFrame) -> Option<Option<u64>> {
+        let src_ns = frame
+            .timestamp()
+            .filter(|ts| !ts.is_zero())
+            .map(|ts| ts.as_nanos());
+        let last_src_ns = std::mem::replace(&mut self.last_src_ns, src_ns);
+
+        if let Some(sequence) = frame.sequence {
+            let last = self.last_sequence.replace(sequence)?;
+            return match sequence.checked_sub(last) {
+                Some(1) => None,
+                // Publisher restarted its counter.
+                None | Some(0) => Some(None),
+                Some(step) => Some(Some(step - 1)),
+            };
+        }
+        if !self.timestamp_gaps {
+            return None;
+        }
+
+        // Backwards steps are publisher restarts or reordering, not loss.
+        let delta = src_ns?.checked_sub(last_src_ns?)?;
+        let gap = self
+            .frame_duration_ns()
+            .is_some_and(|duration| delta >= duration * GAP_FRAMES);
+        // Follow frame rate changes; a real gap is outvoted by the others.
+        if delta > 0 {
+            if self.recent_steps.len() == RECENT_STEPS {
+                self.recent_steps.pop_front();
+            }
+            self.recent_steps.push_back(delta);
+        }
+        gap.then_some(None)
+    }
+
+    /// Nominal time between frames: the median of the recent steps, once
+    /// there are enough of them.
+    fn frame_duration_ns(&self) -> Option<u64> {
+        if self.recent_steps.len() < MIN_STEPS {
+            return None;
+        }
+        let mut steps: Vec<u64> = self.recent_steps.iter().copied().collect();
+        steps.sort_unstable();
+        Some(steps[steps.len() / 2])
+    }
+
+    fn remember_parameter_sets(&mut self, data: &[u8]) {
+        if !matches!(self.codec, Codec::H264 | Codec::H265) {
+            return;
+        }
+        for unit in nal::split_annex_b(data) {
+            if let Some(t) = nal::nal_type(self.codec, unit)
+                && nal::is_parameter_set(self.codec, t)
+            {
+                self.parameter_sets.insert(t, unit.to_vec());
+            }
+        }
+    }
+
+    /// Whether `data` carries every parameter set its codec needs to decode.
+    fn has_parameter_sets(&self, data: &[u8]) -> bool {
+        let required: &[u8] = match self.codec {
+            Codec::H264 => &[7, 8],
+            Codec::H265 => &[32, 33, 34],
+            _ => return true,
+        };
+        let present: Vec<u8> = nal::split_annex_b(data)
+            .filter_map(|unit| nal::nal_type(self.codec, unit))
+            .collect();
+        required.iter().all(|t| present.contains(t))
+    }
+
+    /// Prepend the remembered parameter sets that `data` does not carry.
+    fn complete_parameter_sets(&self, data: &mut FrameData) {
+        if self.parameter_sets.is_empty() {
+            return;
+        }
+        let present: Vec<u8> = nal::split_annex_b(data)
+            .filter_map(|unit| nal::nal_type(self.codec, unit))
+            .collect();
+        let mut prefix = Vec::new();
+        for (t, unit) in &self.parameter_sets {
+            if !present.contains(t) {
+                prefix.extend_from_slice(&[0, 0, 0, 1]);
+                prefix.extend_from_slice(unit);
+            }
+        }
+        if !prefix.is_empty() {
+            data.to_mut().splice(0..0, prefix);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cdr::Timestamp;
+    use crate::latency::FrameTiming;
+
+    const H264_SPS: &[u8] = &[0x67, 0x42, 0x00, 0x1e];
+    const H264_PPS: &[u8] = &[0x68, 0xce, 0x38, 0x80];
+    const H264_IDR: &[u8] = &[0x65, 0x88, 0x84];
+    const H264_P: &[u8] = &[0x41, 0x9a, 0x02];
+
+    const H265_VPS: &[u8] = &[0x40, 0x01, 0x0c];
+    const H265_SPS: &[u8] = &[0x42, 0x01, 0x01];
+    const H265_PPS: &[u8] = &[0x44, 0x01, 0xc1];
+    const H265_IDR: &[u8] = &[0x26, 0x01, 0xaf];
+    const H265_P: &[u8] = &[0x02, 0x01, 0xd0];
+
+    /// An Annex B access unit of `units`.
+    fn au(units: &[&[u8]]) -> Vec<u8> {
+        units
+            .iter()
+            .flat_map(|unit| [&[0, 0, 0, 1][..], unit].concat())
+            .collect()
+    }
+
+    fn frame(codec: Codec, data: Vec<u8>, sequence: Option<u64>, ms: Option<u64>) -> EncodedFrame {
+        let timestamp = ms.map(|ms| Timestamp {
+            sec: (ms / 1000) as u32,
+            nsec: (ms % 1000 * 1_000_000) as u32,
+        });
+        EncodedFrame {
+            data: data.into(),
+            codec,
+            raw: None,
+            encapsulation: None,
+            frame_id: String::new(),
+            key: "video".to_string(),
+            sequence,
+            timing: FrameTiming::received(timestamp, None),
+        }
+    }
+
+    /// Admit each access unit of `codec`, without counter or timestamp.
+    fn admit(gate: &mut KeyframeGate, codec: Codec, units: &[Vec<u8>]) -> Vec<Admit> {
+        units
+            .iter()
+            .map(|data| gate.admit(&mut frame(codec, data.clone(), None, None)))
+            .collect()
+    }
+
+    #[test]
+    fn h264_waits_for_parameter_sets_and_idr() {
+        let mut gate = KeyframeGate::new(Codec::H264, true);
+        assert_eq!(
+            admit(
+                &mut gate,
+                Codec::H264,
+                &[
+                    au(&[H264_P]),
+                    au(&[H264_IDR]),
+                    au(&[H264_SPS, H264_PPS]),
+                    au(&[H264_P])
+                ]
+            ),
+            [Admit::Wait; 4]
+        );
+
+        // The parameter sets sent on their own are added to the next IDR.
+        let mut idr = frame(Codec::H264, au(&[H264_IDR]), None, None);
+        assert_eq!(gate.admit(&mut idr), Admit::Decode);
+        assert_eq!(&*idr.data, au(&[H264_SPS, H264_PPS, H264_IDR]));
+        assert_eq!(
+            admit(&mut gate, Codec::H264, &[au(&[H264_P])]),
+            [Admit::Decode]
+        );
+
+        // A fresh decoder waits again, with the parameter sets kept.
+        gate.reset(Codec::H264);
+        assert_eq!(
+            admit(
+                &mut gate,
+                Codec::H264,
+                &[au(&[H264_P]), au(&[H264_IDR]), au(&[H264_P])]
+            ),
+            [Admit::Wait, Admit::Decode, Admit::Decode]
+        );
+    }
+
+    #[test]
+    fn h265_waits_for_parameter_sets_and_irap() {
+        let mut gate = KeyframeGate::new(Codec::H265, true);
+        assert_eq!(
+            admit(
+                &mut gate,
+                Codec::H265,
+                &[
+                    au(&[H265_P]),
+                    au(&[H265_SPS, H265_PPS, H265_IDR]),
+                    au(&[H265_P])
+                ]
+            ),
+            [Admit::Wait; 3]
+        );
+        assert_eq!(
+            admit(
+                &mut gate,
+                Codec::H265,
+                &[au(&[H265_VPS, H265_SPS, H265_PPS, H265_IDR]), au(&[H265_P])]
+            ),
+            [Admit::Decode; 2]
+        );
+
+        // Another codec starts from scratch.
+        gate.reset(Codec::H264);
+        assert_eq!(
+            admit(&mut gate, Codec::H264, &[au(&[H264_IDR])]),
+            [Admit::Wait]
+        );
+    }
+
+    #[test]
+    fn frame_counter_gaps() {
+        let mut gate = KeyframeGate::new(Codec::H264, true);
+        let idr = au(&[H264_SPS, H264_PPS, H264_IDR]);
+        let p = au(&[H264_P]);
+        let mut admit = |data: &Vec<u8>, sequence| {
+            gate.admit(&mut frame(Codec::H264, data.clone(), Some(sequence), None))
+        };
+        assert_eq!(admit(&idr, 1), Admit::Decode);
+        assert_eq!(admit(&p, 2), Admit::Decode);
+        assert_eq!(admit(&p, 5), Admit::Gap { missing: Some(2) });
+        assert_eq!(admit(&p, 6), Admit::Wait);
+        assert_eq!(admit(&idr, 7), Admit::Decode);
+        assert_eq!(admit(&p, 8), Admit::Decode);
+        // A keyframe right after the gap is decodable.
+        assert_eq!(admit(&idr, 10), Admit::Decode);
+        // Publisher restarted its counter.
+        assert_eq!(admit(&p, 0), Admit::Gap { missing: None });
+        assert_eq!(admit(&idr, 1), Admit::Decode);
+    }
+
+    #[test]
+    fn timestamp_gaps() {
+        let idr = au(&[H264_SPS, H264_PPS, H264_IDR]);
+        let p = au(&[H264_P]);
+        let mut gate = KeyframeGate::new(Codec::H264, true);
+        let mut admit =
+            |data: &Vec<u8>, ms| gate.admit(&mut frame(Codec::H264, data.clone(), None, Some(ms)));
+        assert_eq!(admit(&idr, 1000), Admit::Decode);
+        for ms in [1040, 1080, 1120] {
+            assert_eq!(admit(&p, ms), Admit::Decode);
+        }
+        // Two lost frames are not yet a gap.
+        assert_eq!(admit(&p, 1200), Admit::Decode);
+        assert_eq!(admit(&p, 1320), Admit::Gap { missing: None });
+        assert_eq!(admit(&p, 1360), Admit::Wait);
+        assert_eq!(admit(&idr, 1400), Admit::Decode);
+        // Frame rate changes are followed: 80 ms becomes the interval.
+        for ms in [1480, 1560, 1640, 1720, 1800, 2000] {
+            assert_eq!(admit(&p, ms), Admit::Decode);
+        }
+        assert_eq!(admit(&p, 2240), Admit::Gap { missing: None });
+        assert_eq!(admit(&idr, 2320), Admit::Decode);
+        // Backwards steps are not loss.
+        assert_eq!(admit(&p, 500), Admit::Decode);
+
+        // Publishers that skip frames on purpose turn it off.
+        let mut gate = KeyframeGate::new(Codec::H264, false);
+        let mut admit =
+            |data: &Vec<u8>, ms| gate.admit(&mut frame(Codec::H264, data.clone(), None, Some(ms)));
+        assert_eq!(admit(&idr, 1000), Admit::Decode);
+        assert_eq!(admit(&p, 1040), Admit::Decode);
+        assert_eq!(admit(&p, 5000), Admit::Decode);
+    }
+
+    #[test]
+    fn timestamp_jitter_is_no_gap() {
+        let idr = au(&[H264_SPS, H264_PPS, H264_IDR]);
+        let p = au(&[H264_P]);
+        let mut gate = KeyframeGate::new(Codec::H264, true);
+        let mut admit =
+            |data: &Vec<u8>, ms| gate.admit(&mut frame(Codec::H264, data.clone(), None, Some(ms)));
+        // Stamped 1 ms apart at start, then ~30 fps with jitter and frames
+        // that an encoder sends in pairs.
+        let stamps = [
+            1000, 1001, 1033, 1067, 1100, 1101, 1167, 1200, 1232, 1268, 1300, 1302, 1366, 1400,
+        ];
+        assert_eq!(admit(&idr, stamps[0]), Admit::Decode);
+        for &ms in &stamps[1..] {
+            assert_eq!(admit(&p, ms), Admit::Decode, "at {ms} ms");
+        }
+        // A real gap is still found.
+        assert_eq!(admit(&p, 1533), Admit::Gap { missing: None });
+    }
+}
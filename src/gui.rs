@@ -1,240 +1,942 @@
-use std::collections::VecDeque;
+use std::sync::Arc;
 use std::sync::mpsc;
-use std::sync::{Arc, Mutex};
-use std::time::{Duration, Instant};
+use std::time::Duration;
 
 use eframe::egui;
-use egui_plot::{Line, Plot, PlotPoints};
-use gstreamer::prelude::*;
+use egui_plot::{Bar, BarChart, Legend, Line, Plot, PlotPoints};
 
-/// Tick interval for updating FPS / speed metrics (250 ms → 4 updates/s).
-const TICK_INTERVAL: Duration = Duration::from_millis(250);
+use video_zenoh_player::cdr::DecodeErrors;
+use video_zenoh_player::discovery::Discovered;
+use video_zenoh_player::inspect::{self, ColourField, Inspected, Inspector};
+use video_zenoh_player::latency::Latency;
+use video_zenoh_player::metrics::{METRICS, StreamName};
+use video_zenoh_player::nal::{self, SliceType};
+use video_zenoh_player::playback::{self, Playback};
+use video_zenoh_player::queue::Drops;
+use video_zenoh_player::recorder::Recording;
+use video_zenoh_player::scaling::{OutputSize, Scaling};
+use video_zenoh_player::stats::{HISTORY_LEN, StreamStats};
+use video_zenoh_player::zenoh_sub::{Command, Connection, ConnectionState};
+use video_zenoh_player::{DecodedFrame, Decoder, PixelFormat};
 
-/// Number of samples kept for charts (240 × 250 ms = 60 s of history).
-const HISTORY_LEN: usize = 240;
+/// Chart height in the single-stream / maximized layout.
+const CHART_HEIGHT: f32 = 80.0;
 
-/// The eframe application state.
-pub struct VideoPlayerApp {
-    pub rgba_rx: mpsc::Receiver<(Vec<u8>, u32, u32, usize)>,
-    pub texture: Option<egui::TextureHandle>,
-    pub video_width: u32,
-    pub video_height: u32,
-    pub frame_count: u64,
-    pub last_frame_time: Instant,
-    pub pipeline: Arc<Mutex<Option<gstreamer::Element>>>,
-    pub topic: String,
+/// Chart height inside a grid tile.
+const TILE_CHART_HEIGHT: f32 = 40.0;
+
+/// Gap between grid tiles.
+const TILE_SPACING: f32 = 4.0;
 
-    // Metrics sampling
-    tick: Instant,
-    frames_since_tick: u64,
-    bytes_since_tick: u64,
+/// Height of the inspector's frame size timeline.
+const TIMELINE_HEIGHT: f32 = 120.0;
 
-    // FPS
-    fps_current: f32,
-    fps_history: VecDeque<f32>,
+/// Frames listed individually in the inspector, newest first.
+const INSPECTOR_FRAMES: usize = 60;
 
-    // Speed (Mbps)
-    speed_current: f32,
-    speed_history: VecDeque<f32>,
+/// Decoded frames, texture and metrics of one subscribed topic.
+///
+/// Owns the stream's decoder, so dropping the view stops its pipeline.
+pub struct StreamView {
+    pub decoded_rx: mpsc::Receiver<DecodedFrame>,
+    pub texture: Option<egui::TextureHandle>,
+    /// Only held so the pipeline stops with the view.
+    _decoder: Decoder,
+    pub topic: String,
+    /// Labels the metrics of the stream's queue and decoder.
+    name: StreamName,
+    pub stats: StreamStats,
+    /// Scaling of the decoder's output, fed with the size of the video area.
+    pub output_size: OutputSize,
 }
 
-impl VideoPlayerApp {
+impl StreamView {
+    /// `name` is the topic, shared with the queue and `decoder`.
+    /// `queue_drops` counts the frames dropped before `decoder`, which
+    /// scales its output to `output_size`; `decode_errors` the payloads that
+    /// never became a frame.
     pub fn new(
-        rgba_rx: mpsc::Receiver<(Vec<u8>, u32, u32, usize)>,
-        pipeline: Arc<Mutex<Option<gstreamer::Element>>>,
-        topic: String,
+        decoded_rx: mpsc::Receiver<DecodedFrame>,
+        decoder: Decoder,
+        name: StreamName,
+        queue_drops: Drops,
+        decode_errors: DecodeErrors,
+        output_size: OutputSize,
     ) -> Self {
         Self {
-            rgba_rx,
+            decoded_rx,
             texture: None,
-            video_width: 0,
-            video_height: 0,
-            frame_count: 0,
-            last_frame_time: Instant::now(),
-            pipeline,
-            topic,
-            tick: Instant::now(),
-            frames_since_tick: 0,
-            bytes_since_tick: 0,
-            fps_current: 0.0,
-            fps_history: VecDeque::with_capacity(HISTORY_LEN),
-            speed_current: 0.0,
-            speed_history: VecDeque::with_capacity(HISTORY_LEN),
+            _decoder: decoder,
+            topic: name.get(),
+            name,
+            stats: StreamStats::new(queue_drops, decode_errors),
+            output_size,
         }
     }
-}
 
-impl Drop for VideoPlayerApp {
-    fn drop(&mut self) {
-        if let Some(pipeline) = self.pipeline.lock().unwrap().take() {
-            let _ = pipeline.set_state(gstreamer::State::Null);
-        }
+    /// Switch to another topic; the decoder keeps running and picks up the
+    /// new stream (and codec) from the next frame. The queue and decoder
+    /// count under the new topic from now on.
+    fn set_topic(&mut self, topic: String) {
+        METRICS.remove_stream(&self.topic);
+        self.name.set(topic.clone());
+        self.topic = topic;
+        self.texture = None;
+        self.stats.reset_video();
     }
-}
 
-impl eframe::App for VideoPlayerApp {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+    /// Drain the channel, upload the newest frame and update the metrics.
+    fn update(&mut self, ctx: &egui::Context) {
         // Drain channel, keep only the latest frame
-        let mut latest_frame: Option<(Vec<u8>, u32, u32)> = None;
-        while let Ok((data, w, h, h264_size)) = self.rgba_rx.try_recv() {
-            self.frame_count += 1;
-            self.frames_since_tick += 1;
-            self.bytes_since_tick += h264_size as u64;
-            latest_frame = Some((data, w, h));
+        let mut latest_frame: Option<DecodedFrame> = None;
+        while let Ok(frame) = self.decoded_rx.try_recv() {
+            self.stats.frame_decoded(frame.compressed_size);
+            latest_frame = Some(frame);
         }
 
-        if let Some((data, w, h)) = latest_frame {
-            self.video_width = w;
-            self.video_height = h;
-            self.last_frame_time = Instant::now();
-
-            let image = egui::ColorImage::from_rgba_unmultiplied([w as usize, h as usize], &data);
+        if let Some(frame) = latest_frame {
+            let size = [frame.width as usize, frame.height as usize];
+            let image = match frame.format {
+                PixelFormat::Rgba => egui::ColorImage::from_rgba_unmultiplied(size, &frame.pixels),
+            };
 
             match &mut self.texture {
                 Some(tex) => tex.set(image, egui::TextureOptions::LINEAR),
                 None => {
-                    self.texture =
-                        Some(ctx.load_texture("video_frame", image, egui::TextureOptions::LINEAR));
+                    self.texture = Some(ctx.load_texture(
+                        format!("video_frame:{}", self.topic),
+                        image,
+                        egui::TextureOptions::LINEAR,
+                    ));
                 }
             }
+
+            self.stats.frame_shown(&frame);
+        }
+
+        self.stats.update(&self.topic);
+    }
+
+    /// Connection state, resolution, counters and latency as a row of
+    /// labels.
+    fn stats_ui(&self, ui: &mut egui::Ui, connection: Option<&ConnectionState>) {
+        let stats = &self.stats;
+        if let Some(state) = connection {
+            let color = match state {
+                ConnectionState::Connected => ui.visuals().text_color(),
+                ConnectionState::Connecting
+                | ConnectionState::NoData
+                | ConnectionState::WaitingForPeers => ui.visuals().warn_fg_color,
+                ConnectionState::Error(_) => ui.visuals().error_fg_color,
+            };
+            ui.colored_label(color, state.to_string());
+            ui.separator();
+        }
+        let shown = (stats.video_width, stats.video_height);
+        let source = (stats.source_width, stats.source_height);
+        if shown == source {
+            ui.label(format!("Resolution: {}x{}", shown.0, shown.1));
+        } else {
+            ui.label(format!(
+                "Resolution: {}x{} → {}x{}",
+                source.0, source.1, shown.0, shown.1
+            ))
+            .on_hover_text("Source resolution → displayed resolution");
+        }
+        ui.separator();
+        ui.label(format!("Frames: {}", stats.frame_count));
+        ui.separator();
+        let drops = stats.queue_drops.get();
+        if drops > 0 {
+            ui.colored_label(ui.visuals().warn_fg_color, format!("Dropped: {drops}"))
+                .on_hover_text("Frames dropped because decoding fell behind");
+        } else {
+            ui.label("Dropped: 0");
         }
+        ui.separator();
+        let undecodable = stats.decode_errors.total();
+        if undecodable > 0 {
+            let classes: Vec<String> = stats
+                .decode_errors
+                .get()
+                .into_iter()
+                .filter(|&(_, n)| n > 0)
+                .map(|(class, n)| format!("{class}: {n}"))
+                .collect();
+            ui.colored_label(
+                ui.visuals().warn_fg_color,
+                format!("Undecodable: {undecodable}"),
+            )
+            .on_hover_text(format!(
+                "Payloads that are not a playable message, by error:\n{}",
+                classes.join("\n")
+            ));
+            ui.separator();
+        }
+        ui.label(format!("FPS: {:.1}", stats.fps_current));
+        ui.separator();
+        ui.label(format!("Speed: {:.2} Mbps", stats.speed_current));
+        ui.separator();
+        match stats.latency_current {
+            Some(l) => ui.label(format!(
+                "Latency: {:.0} ms (rx {} / dec {:.0} / disp {:.0})",
+                l.total_ms(),
+                l.receive_ms
+                    .map_or("n/a".to_string(), |v| format!("{v:.0}")),
+                l.decode_ms,
+                l.display_ms,
+            )),
+            None => ui.label("Latency: n/a"),
+        };
+        ui.separator();
+        match stats.last_frame_time {
+            Some(t) => ui.label(format!("Last frame: {:.1}s ago", t.elapsed().as_secs_f64())),
+            None => ui.label("Last frame: n/a"),
+        };
+    }
+
+    /// FPS, speed and latency charts side by side.
+    fn charts_ui(&self, ui: &mut egui::Ui, height: f32) {
+        let stats = &self.stats;
+        ui.columns(3, |cols| {
+            // FPS chart
+            cols[0].label("FPS");
+            let max_fps = stats
+                .fps_history
+                .iter()
+                .copied()
+                .fold(1.0_f32, f32::max)
+                .max(30.0);
 
-        // Update metrics every TICK_INTERVAL (250 ms)
-        let elapsed = self.tick.elapsed();
-        if elapsed >= TICK_INTERVAL {
-            let secs = elapsed.as_secs_f32();
+            let fps_points: PlotPoints = stats
+                .fps_history
+                .iter()
+                .enumerate()
+                .map(|(i, &v)| [i as f64, v as f64])
+                .collect();
 
-            self.fps_current = self.frames_since_tick as f32 / secs;
-            self.speed_current = (self.bytes_since_tick as f32 * 8.0) / (secs * 1_000_000.0); // Mbps
+            Plot::new("fps_chart")
+                .height(height)
+                .include_y(0.0)
+                .include_y(max_fps as f64)
+                .include_x(0.0)
+                .include_x(HISTORY_LEN as f64)
+                .show_axes([false, true])
+                .allow_drag(false)
+                .allow_zoom(false)
+                .allow_scroll(false)
+                .allow_boxed_zoom(false)
+                .show(&mut cols[0], |plot_ui| {
+                    plot_ui.line(Line::new("FPS", fps_points));
+                });
 
-            self.frames_since_tick = 0;
-            self.bytes_since_tick = 0;
-            self.tick = Instant::now();
+            // Speed chart
+            cols[1].label("Mbps");
+            let max_speed = stats
+                .speed_history
+                .iter()
+                .copied()
+                .fold(0.1_f32, f32::max)
+                .max(1.0);
 
-            if self.fps_history.len() >= HISTORY_LEN {
-                self.fps_history.pop_front();
-            }
-            self.fps_history.push_back(self.fps_current);
+            let speed_points: PlotPoints = stats
+                .speed_history
+                .iter()
+                .enumerate()
+                .map(|(i, &v)| [i as f64, v as f64])
+                .collect();
 
-            if self.speed_history.len() >= HISTORY_LEN {
-                self.speed_history.pop_front();
-            }
-            self.speed_history.push_back(self.speed_current);
+            Plot::new("speed_chart")
+                .height(height)
+                .include_y(0.0)
+                .include_y(max_speed as f64)
+                .include_x(0.0)
+                .include_x(HISTORY_LEN as f64)
+                .show_axes([false, true])
+                .allow_drag(false)
+                .allow_zoom(false)
+                .allow_scroll(false)
+                .allow_boxed_zoom(false)
+                .show(&mut cols[1], |plot_ui| {
+                    plot_ui.line(Line::new("Mbps", speed_points));
+                });
+
+            // Latency chart
+            cols[2].label("Latency (ms)");
+            let max_latency = stats
+                .latency_history
+                .iter()
+                .map(Latency::total_ms)
+                .fold(1.0_f32, f32::max)
+                .max(50.0);
+
+            let latency_line = |name: &str, value: fn(&Latency) -> f32| {
+                let points: PlotPoints = stats
+                    .latency_history
+                    .iter()
+                    .enumerate()
+                    .map(|(i, l)| [i as f64, value(l) as f64])
+                    .collect();
+                Line::new(name, points)
+            };
+
+            Plot::new("latency_chart")
+                .height(height)
+                .include_y(0.0)
+                .include_y(max_latency as f64)
+                .include_x(0.0)
+                .include_x(HISTORY_LEN as f64)
+                .show_axes([false, true])
+                .allow_drag(false)
+                .allow_zoom(false)
+                .allow_scroll(false)
+                .allow_boxed_zoom(false)
+                .legend(Legend::default())
+                .show(&mut cols[2], |plot_ui| {
+                    plot_ui.line(latency_line("total", Latency::total_ms));
+                    plot_ui.line(latency_line("receive", |l| l.receive_ms.unwrap_or(0.0)));
+                    plot_ui.line(latency_line("decode", |l| l.decode_ms));
+                    plot_ui.line(latency_line("display", |l| l.display_ms));
+                });
+        });
+    }
+
+    /// Paint the video (or the connection state while there is none) into a
+    /// `size` area.
+    ///
+    /// The area senses clicks so the caller can toggle the maximized view.
+    fn video_ui(
+        &self,
+        ui: &mut egui::Ui,
+        size: egui::Vec2,
+        connection: Option<&ConnectionState>,
+    ) -> egui::Response {
+        let (rect, response) = ui.allocate_exact_size(size, egui::Sense::click());
+        let pixels = rect.size() * ui.ctx().pixels_per_point();
+        self.output_size
+            .set_window(pixels.x.round() as u32, pixels.y.round() as u32);
+        if let Some(texture) = &self.texture {
+            let aspect = self.stats.video_width as f32 / self.stats.video_height.max(1) as f32;
+            let image_size = if rect.width() / rect.height() > aspect {
+                egui::vec2(rect.height() * aspect, rect.height())
+            } else {
+                egui::vec2(rect.width(), rect.width() / aspect)
+            };
+
+            egui::Image::new(egui::load::SizedTexture::new(texture.id(), image_size))
+                .paint_at(ui, egui::Rect::from_center_size(rect.center(), image_size));
+        } else {
+            ui.painter().text(
+                rect.center(),
+                egui::Align2::CENTER_CENTER,
+                match connection {
+                    Some(ConnectionState::Connected | ConnectionState::NoData) | None => {
+                        format!("Waiting for video on zenoh topic '{}'...", self.topic)
+                    }
+                    Some(ConnectionState::Connecting) => "Connecting to Zenoh...".to_string(),
+                    Some(ConnectionState::WaitingForPeers) => {
+                        "Waiting for Zenoh peers...".to_string()
+                    }
+                    Some(ConnectionState::Error(reason)) => format!("Zenoh error: {reason}"),
+                },
+                egui::TextStyle::Body.resolve(ui.style()),
+                ui.visuals().text_color(),
+            );
         }
+        response
+    }
+}
 
-        // Repaint at ~60 fps
-        ctx.request_repaint_after(Duration::from_millis(16));
+/// The eframe application state.
+///
+/// Shows a single stream full-window, or a grid of tiles when several topics
+/// are subscribed. Clicking a tile maximizes it; clicking again (or Esc)
+/// returns to the grid.
+///
+/// Streams come either from Zenoh (`zenoh_cmd` set) or from an MCAP file
+/// (`playback` set), which adds a transport bar at the bottom.
+pub struct VideoPlayerApp {
+    pub streams: Vec<StreamView>,
+    /// Index of the stream shown full-window in grid mode.
+    pub maximized: Option<usize>,
+    pub zenoh_cmd: Option<tokio::sync::mpsc::UnboundedSender<Command>>,
+    /// State of the Zenoh session and of each stream; `None` in playback.
+    pub connection: Option<Arc<Connection>>,
+    pub recording: Arc<Recording>,
+    pub playback: Option<Arc<Playback>>,
+    /// Slider position while the user drags it, in seconds.
+    scrub: Option<f64>,
 
-        // --- Top panel with stats ---
-        egui::TopBottomPanel::top("stats_panel").show(ctx, |ui| {
+    // Discovery panel
+    pub discovered: Discovered,
+    pub discover_key: String,
+    show_discovery: bool,
+
+    // Bitstream inspector panel, offered when set
+    pub inspected: Option<Inspected>,
+    show_inspector: bool,
+}
+
+impl VideoPlayerApp {
+    pub fn new(
+        streams: Vec<StreamView>,
+        zenoh_cmd: Option<tokio::sync::mpsc::UnboundedSender<Command>>,
+        connection: Option<Arc<Connection>>,
+        recording: Arc<Recording>,
+        discovered: Discovered,
+        discover_key: String,
+        playback: Option<Arc<Playback>>,
+    ) -> Self {
+        Self {
+            streams,
+            maximized: None,
+            zenoh_cmd,
+            connection,
+            recording,
+            playback,
+            scrub: None,
+            discovered,
+            discover_key,
+            show_discovery: false,
+            inspected: None,
+            show_inspector: false,
+        }
+    }
+
+    /// Offer the bitstream inspector, fed by the subscribers through
+    /// `inspected`.
+    pub fn inspector(mut self, inspected: Inspected) -> Self {
+        self.inspected = Some(inspected);
+        self
+    }
+
+    /// Index of the stream a discovered topic is switched into: the maximized
+    /// one, or the first.
+    fn active_stream(&self) -> usize {
+        self.maximized.unwrap_or(0)
+    }
+
+    /// Connection state of stream `index`, when streaming from Zenoh.
+    fn connection_state(&self, index: usize) -> Option<ConnectionState> {
+        self.connection.as_ref().map(|c| c.state(index))
+    }
+
+    /// Global toggles above everything else.
+    fn toolbar_ui(&mut self, ctx: &egui::Context) {
+        egui::TopBottomPanel::top("toolbar").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                if let Some(zenoh_cmd) = &self.zenoh_cmd
+                    && ui
+                        .toggle_value(&mut self.show_discovery, "🔍 Discover")
+                        .changed()
+                {
+                    let key_expr = self.show_discovery.then(|| self.discover_key.clone());
+                    let _ = zenoh_cmd.send(Command::Discover(key_expr));
+                }
+
+                if self.inspected.is_some() {
+                    ui.toggle_value(&mut self.show_inspector, "🔬 Inspect");
+                }
+
+                if let Some(first) = self.streams.first() {
+                    let mut scaling = first.output_size.scaling();
+                    egui::ComboBox::from_id_salt("scaling")
+                        .selected_text(scaling.to_string())
+                        .show_ui(ui, |ui| {
+                            for s in [Scaling::Native, Scaling::Max, Scaling::Window] {
+                                ui.selectable_value(&mut scaling, s, s.to_string());
+                            }
+                        })
+                        .response
+                        .on_hover_text("Resolution the streams are decoded to");
+                    if scaling != first.output_size.scaling() {
+                        for stream in &self.streams {
+                            stream.output_size.set_scaling(scaling);
+                        }
+                    }
+                }
+
+                let mut recording = self.recording.is_enabled();
+                if ui
+                    .toggle_value(&mut recording, "⏺ Record")
+                    .on_hover_text(format!("Write streams to {}", self.recording.dir.display()))
+                    .changed()
+                {
+                    self.recording.set_enabled(recording);
+                }
+            });
+        });
+    }
+
+    /// Topics found under the discovery key expression; clicking one
+    /// switches the active stream to it.
+    fn discovery_ui(&mut self, ctx: &egui::Context) {
+        let mut selected = None;
+        egui::SidePanel::right("discovery_panel").show(ctx, |ui| {
+            ui.heading("Discovery");
             ui.horizontal(|ui| {
+                ui.label("Key:");
+                let edit = ui.text_edit_singleline(&mut self.discover_key);
+                if edit.lost_focus()
+                    && ui.input(|i| i.key_pressed(egui::Key::Enter))
+                    && let Some(zenoh_cmd) = &self.zenoh_cmd
+                {
+                    let _ = zenoh_cmd.send(Command::Discover(Some(self.discover_key.clone())));
+                }
+            });
+            if let Some(stream) = self.streams.get(self.active_stream()) {
                 ui.label(format!(
-                    "Resolution: {}x{}",
-                    self.video_width, self.video_height
+                    "Click a topic to show it instead of '{}'",
+                    stream.topic
                 ));
+            }
+            ui.separator();
+
+            let discovered = self.discovered.lock().unwrap();
+            if discovered.is_empty() {
+                ui.label("No video or image topics seen yet...");
+            }
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                egui::Grid::new("discovery_grid")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.strong("Topic");
+                        ui.strong("Codec");
+                        ui.strong("Resolution");
+                        ui.strong("Rate");
+                        ui.end_row();
+
+                        for (key, topic) in discovered.iter() {
+                            let mut text = egui::RichText::new(key);
+                            if topic.is_stale() {
+                                text = text.weak();
+                            }
+                            if ui.link(text).clicked() {
+                                selected = Some(key.clone());
+                            }
+                            ui.label(match topic.codec {
+                                Some(codec) => codec.to_string(),
+                                None => format!("{} (unsupported)", topic.format),
+                            });
+                            ui.label(match topic.resolution {
+                                Some((w, h)) => format!("{w}x{h}"),
+                                None => "?".to_string(),
+                            });
+                            ui.label(format!("{:.1} Hz", topic.rate));
+                            ui.end_row();
+                        }
+                    });
+            });
+        });
+
+        if let Some(topic) = selected {
+            let index = self.active_stream();
+            if let Some(stream) = self.streams.get_mut(index)
+                && let Some(zenoh_cmd) = &self.zenoh_cmd
+            {
+                println!("Switching stream #{index} to '{topic}'");
+                stream.set_topic(topic.clone());
+                let _ = zenoh_cmd.send(Command::Retarget { index, topic });
+            }
+        }
+    }
+
+    /// Keep exactly the active stream inspected while the panel is open, so
+    /// the subscribers parse nothing else.
+    fn update_inspected(&self) {
+        let Some(inspected) = &self.inspected else {
+            return;
+        };
+        let mut inspected = inspected.lock().unwrap();
+        if self.show_inspector {
+            let index = self.active_stream();
+            inspected.retain(|&i, _| i == index);
+            inspected.entry(index).or_default();
+        } else {
+            inspected.clear();
+        }
+    }
+
+    /// NAL units, frame types, GOP and SPS of the active stream.
+    fn inspector_ui(&self, ctx: &egui::Context) {
+        let Some(inspected) = &self.inspected else {
+            return;
+        };
+        let index = self.active_stream();
+        egui::SidePanel::right("inspector_panel")
+            .default_width(360.0)
+            .show(ctx, |ui| {
+                ui.heading("Inspector");
+                if let Some(stream) = self.streams.get(index) {
+                    ui.label(format!("Stream: {}", stream.topic));
+                }
                 ui.separator();
-                ui.label(format!("Frames: {}", self.frame_count));
-                ui.separator();
-                ui.label(format!("FPS: {:.1}", self.fps_current));
-                ui.separator();
-                ui.label(format!("Speed: {:.2} Mbps", self.speed_current));
-                ui.separator();
+
+                let inspected = inspected.lock().unwrap();
+                match inspected.get(&index) {
+                    Some(inspector) if !inspector.frames.is_empty() => {
+                        inspector_details_ui(ui, inspector);
+                    }
+                    _ => {
+                        ui.label("Waiting for frames...");
+                    }
+                }
+            });
+    }
+
+    /// Play/pause, speed and seek bar for MCAP playback.
+    fn playback_ui(&mut self, ctx: &egui::Context) {
+        let Some(playback) = &self.playback else {
+            return;
+        };
+        egui::TopBottomPanel::bottom("playback_panel").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                let duration = playback.duration().as_secs_f64();
+                let position = playback.position().as_secs_f64();
+
+                let paused = playback.is_paused();
+                if ui.button(if paused { "▶" } else { "⏸" }).clicked() {
+                    // Play from the start again once the end was reached.
+                    if paused && position >= duration {
+                        playback.seek(Duration::ZERO);
+                    }
+                    playback.set_paused(!paused);
+                }
+
+                let mut speed = playback.speed();
+                egui::ComboBox::from_id_salt("playback_speed")
+                    .width(60.0)
+                    .selected_text(format!("{speed}×"))
+                    .show_ui(ui, |ui| {
+                        for s in playback::SPEEDS {
+                            ui.selectable_value(&mut speed, s, format!("{s}×"));
+                        }
+                    });
+                if speed != playback.speed() {
+                    playback.set_speed(speed);
+                }
+
+                let mut value = self.scrub.unwrap_or(position);
                 ui.label(format!(
-                    "Last frame: {:.1}s ago",
-                    self.last_frame_time.elapsed().as_secs_f64()
+                    "{} / {}",
+                    format_time(value),
+                    format_time(duration)
                 ));
+                ui.spacing_mut().slider_width = ui.available_width();
+                let slider =
+                    ui.add(egui::Slider::new(&mut value, 0.0..=duration).show_value(false));
+                if slider.dragged() {
+                    self.scrub = Some(value);
+                } else if slider.drag_stopped() || slider.changed() {
+                    self.scrub = None;
+                    playback.seek(Duration::from_secs_f64(value));
+                }
+            });
+        });
+    }
+
+    /// Single stream with stats on top, charts at the bottom.
+    fn single_ui(&mut self, ctx: &egui::Context, index: usize) {
+        let in_grid = self.streams.len() > 1;
+        let connection = self.connection_state(index);
+        let stream = &self.streams[index];
+
+        // --- Top panel with stats ---
+        egui::TopBottomPanel::top("stats_panel").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                if in_grid {
+                    ui.strong(&stream.topic);
+                    ui.separator();
+                }
+                stream.stats_ui(ui, connection.as_ref());
             });
         });
 
-        // --- Bottom panel with FPS + speed charts ---
+        // --- Bottom panel with FPS + speed + latency charts ---
         egui::TopBottomPanel::bottom("charts_panel").show(ctx, |ui| {
-            ui.columns(2, |cols| {
-                // FPS chart
-                cols[0].label("FPS");
-                let max_fps = self
-                    .fps_history
-                    .iter()
-                    .copied()
-                    .fold(1.0_f32, f32::max)
-                    .max(30.0);
+            stream.charts_ui(ui, CHART_HEIGHT);
+        });
 
-                let fps_points: PlotPoints = self
-                    .fps_history
-                    .iter()
-                    .enumerate()
-                    .map(|(i, &v)| [i as f64, v as f64])
-                    .collect();
+        // --- Central panel with video ---
+        let clicked = egui::CentralPanel::default()
+            .show(ctx, |ui| {
+                stream
+                    .video_ui(ui, ui.available_size(), connection.as_ref())
+                    .clicked()
+            })
+            .inner;
+        if clicked && in_grid {
+            self.maximized = None;
+        }
+    }
 
-                Plot::new("fps_chart")
-                    .height(80.0)
-                    .include_y(0.0)
-                    .include_y(max_fps as f64)
-                    .include_x(0.0)
-                    .include_x(HISTORY_LEN as f64)
-                    .show_axes([false, true])
-                    .allow_drag(false)
-                    .allow_zoom(false)
-                    .allow_scroll(false)
-                    .allow_boxed_zoom(false)
-                    .show(&mut cols[0], |plot_ui| {
-                        plot_ui.line(Line::new("FPS", fps_points));
-                    });
+    /// All streams as tiles in a near-square grid.
+    fn grid_ui(&mut self, ctx: &egui::Context) {
+        let count = self.streams.len();
+        let cols = (count as f32).sqrt().ceil() as usize;
+        let rows = count.div_ceil(cols);
 
-                // Speed chart
-                cols[1].label("Mbps");
-                let max_speed = self
-                    .speed_history
-                    .iter()
-                    .copied()
-                    .fold(0.1_f32, f32::max)
-                    .max(1.0);
+        let clicked = egui::CentralPanel::default()
+            .show(ctx, |ui| {
+                let area = ui.available_rect_before_wrap();
+                let tile = egui::vec2(area.width() / cols as f32, area.height() / rows as f32);
+                let mut clicked = None;
 
-                let speed_points: PlotPoints = self
-                    .speed_history
-                    .iter()
-                    .enumerate()
-                    .map(|(i, &v)| [i as f64, v as f64])
-                    .collect();
+                for (i, stream) in self.streams.iter().enumerate() {
+                    let connection = self.connection_state(i);
+                    let min = area.min
+                        + egui::vec2((i % cols) as f32 * tile.x, (i / cols) as f32 * tile.y);
+                    let rect = egui::Rect::from_min_size(min, tile).shrink(TILE_SPACING);
+                    ui.painter().rect_stroke(
+                        rect,
+                        2.0,
+                        ui.visuals().widgets.noninteractive.bg_stroke,
+                        egui::StrokeKind::Outside,
+                    );
 
-                Plot::new("speed_chart")
-                    .height(80.0)
-                    .include_y(0.0)
-                    .include_y(max_speed as f64)
-                    .include_x(0.0)
-                    .include_x(HISTORY_LEN as f64)
-                    .show_axes([false, true])
-                    .allow_drag(false)
-                    .allow_zoom(false)
-                    .allow_scroll(false)
-                    .allow_boxed_zoom(false)
-                    .show(&mut cols[1], |plot_ui| {
-                        plot_ui.line(Line::new("Mbps", speed_points));
+                    let builder = egui::UiBuilder::new().max_rect(rect).id_salt(("tile", i));
+                    ui.scope_builder(builder, |ui| {
+                        ui.strong(&stream.topic);
+                        ui.horizontal_wrapped(|ui| stream.stats_ui(ui, connection.as_ref()));
+
+                        // Leave room for the chart labels and plots below.
+                        let video_height = ui.available_height()
+                            - TILE_CHART_HEIGHT
+                            - 2.0 * ui.spacing().interact_size.y;
+                        let size = egui::vec2(ui.available_width(), video_height.max(0.0));
+                        if stream.video_ui(ui, size, connection.as_ref()).clicked() {
+                            clicked = Some(i);
+                        }
+
+                        stream.charts_ui(ui, TILE_CHART_HEIGHT);
                     });
+                }
+                clicked
+            })
+            .inner;
+
+        if clicked.is_some() {
+            self.maximized = clicked;
+        }
+    }
+}
+
+/// Stream parameters, frame size timeline and frame list of one inspector.
+fn inspector_details_ui(ui: &mut egui::Ui, inspector: &Inspector) {
+    let Some(codec) = inspector.codec else {
+        return;
+    };
+    let count = |t: SliceType| {
+        inspector
+            .frames
+            .iter()
+            .filter(|f| f.frame_type == Some(t))
+            .count()
+    };
+
+    egui::Grid::new("inspector_grid")
+        .num_columns(2)
+        .striped(true)
+        .show(ui, |ui| {
+            ui.label("Codec");
+            ui.label(codec.to_string());
+            ui.end_row();
+
+            ui.label("Encapsulation");
+            ui.label(
+                inspector
+                    .encapsulation
+                    .map_or("none (Annex B)".to_string(), |e| e.to_string()),
+            )
+            .on_hover_text("CDR representation of the messages");
+            ui.end_row();
+
+            ui.label("GOP length");
+            ui.label(
+                inspector
+                    .gop_length
+                    .map_or("?".to_string(), |n| n.to_string()),
+            );
+            ui.end_row();
+
+            ui.label("Frame types");
+            ui.label(format!(
+                "I {} / P {} / B {} (last {})",
+                count(SliceType::I),
+                count(SliceType::P),
+                count(SliceType::B),
+                inspector.frames.len()
+            ));
+            ui.end_row();
+
+            let Some(sps) = &inspector.sps else {
+                return;
+            };
+            ui.label("Profile");
+            ui.label(match inspect::profile_name(codec, sps.profile_idc) {
+                Some(name) => name.to_string(),
+                None => format!("profile_idc {}", sps.profile_idc),
             });
-        });
+            ui.end_row();
 
-        // --- Central panel with video ---
-        egui::CentralPanel::default().show(ctx, |ui| {
-            if let Some(texture) = &self.texture {
-                let avail = ui.available_size();
-                let aspect = self.video_width as f32 / self.video_height.max(1) as f32;
-                let size = if avail.x / avail.y > aspect {
-                    egui::vec2(avail.y * aspect, avail.y)
-                } else {
-                    egui::vec2(avail.x, avail.x / aspect)
-                };
+            ui.label("Level");
+            ui.label(format!("{:.1}", inspect::level(codec, sps.level_idc)));
+            ui.end_row();
 
-                ui.centered_and_justified(|ui| {
-                    ui.image(egui::load::SizedTexture::new(texture.id(), size));
-                });
-            } else {
-                ui.centered_and_justified(|ui| {
-                    ui.label(format!(
-                        "Waiting for video on zenoh topic '{}'...",
-                        self.topic
-                    ));
-                });
+            ui.label("Resolution");
+            ui.label(format!("{}x{}", sps.width, sps.height));
+            ui.end_row();
+
+            ui.label("Chroma / depth");
+            let chroma = match sps.chroma_format_idc {
+                0 => "4:0:0",
+                1 => "4:2:0",
+                2 => "4:2:2",
+                _ => "4:4:4",
+            };
+            ui.label(format!("{chroma}, {} bit", sps.bit_depth_luma));
+            ui.end_row();
+
+            let Some(vui) = &sps.vui else {
+                ui.label("VUI");
+                ui.label("not present");
+                ui.end_row();
+                return;
+            };
+            if let Some(fps) = vui.frame_rate {
+                ui.label("Frame rate");
+                ui.label(format!("{fps:.3} fps"));
+                ui.end_row();
+            }
+            if let Some((w, h)) = vui.sample_aspect_ratio {
+                ui.label("Sample aspect");
+                ui.label(format!("{w}:{h}"));
+                ui.end_row();
+            }
+            if let Some(full_range) = vui.full_range {
+                ui.label("Range");
+                ui.label(if full_range { "full" } else { "limited" });
+                ui.end_row();
+            }
+            if let Some((primaries, transfer, matrix)) = vui.colour {
+                let name = |code: u8, field| {
+                    inspect::colour_name(code, field).map_or(code.to_string(), str::to_string)
+                };
+                ui.label("Colour");
+                ui.label(format!(
+                    "{} / {} / {}",
+                    name(primaries, ColourField::Primaries),
+                    name(transfer, ColourField::Transfer),
+                    name(matrix, ColourField::Matrix)
+                ));
+                ui.end_row();
             }
         });
+    ui.separator();
+
+    // Frame sizes, one bar chart per frame type so the legend explains the
+    // colors.
+    ui.label("Frame size (KB)");
+    let chart = |name: &str, t: Option<SliceType>, color: egui::Color32| {
+        let bars = inspector
+            .frames
+            .iter()
+            .enumerate()
+            .filter(|(_, f)| f.frame_type == t)
+            .map(|(i, f)| Bar::new(i as f64, f.size as f64 / 1024.0).width(1.0))
+            .collect();
+        BarChart::new(name, bars).color(color)
+    };
+    Plot::new("inspector_timeline")
+        .height(TIMELINE_HEIGHT)
+        .include_y(0.0)
+        .include_x(0.0)
+        .include_x(inspect::HISTORY_LEN as f64)
+        .show_axes([false, true])
+        .allow_drag(false)
+        .allow_zoom(false)
+        .allow_scroll(false)
+        .allow_boxed_zoom(false)
+        .legend(Legend::default())
+        .show(ui, |plot_ui| {
+            plot_ui.bar_chart(chart("I", Some(SliceType::I), egui::Color32::LIGHT_RED));
+            plot_ui.bar_chart(chart("P", Some(SliceType::P), egui::Color32::LIGHT_BLUE));
+            plot_ui.bar_chart(chart("B", Some(SliceType::B), egui::Color32::LIGHT_GREEN));
+            plot_ui.bar_chart(chart("?", None, egui::Color32::GRAY));
+        });
+    ui.separator();
+
+    egui::ScrollArea::vertical().show(ui, |ui| {
+        egui::Grid::new("inspector_frames")
+            .striped(true)
+            .show(ui, |ui| {
+                ui.strong("Type");
+                ui.strong("Size");
+                ui.strong("NAL units");
+                ui.end_row();
+
+                for frame in inspector.frames.iter().rev().take(INSPECTOR_FRAMES) {
+                    let mut frame_type = match frame.frame_type {
+                        Some(t) => format!("{t:?}"),
+                        None => "?".to_string(),
+                    };
+                    if frame.keyframe {
+                        frame_type.push_str(" (key)");
+                    }
+                    ui.label(frame_type);
+                    ui.label(format!("{:.1} KB", frame.size as f64 / 1024.0));
+                    let names: Vec<&str> = frame
+                        .nal_types
+                        .iter()
+                        .map(|&t| nal::nal_type_name(codec, t))
+                        .collect();
+                    ui.label(names.join(" "));
+                    ui.end_row();
+                }
+            });
+    });
+}
+
+/// `m:ss.s` for the playback position.
+fn format_time(secs: f64) -> String {
+    format!("{}:{:04.1}", (secs / 60.0) as u64, secs % 60.0)
+}
+
+impl Drop for VideoPlayerApp {
+    fn drop(&mut self) {
+        // Finalize recordings before the stream pipelines are torn down.
+        self.recording.shutdown();
+    }
+}
+
+impl eframe::App for VideoPlayerApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        for stream in &mut self.streams {
+            stream.update(ctx);
+        }
+
+        // Repaint at ~60 fps
+        ctx.request_repaint_after(Duration::from_millis(16));
+
+        if ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
+            self.maximized = None;
+        }
+
+        self.toolbar_ui(ctx);
+        self.playback_ui(ctx);
+        if self.show_discovery {
+            self.discovery_ui(ctx);
+        }
+        self.update_inspected();
+        if self.show_inspector {
+            self.inspector_ui(ctx);
+        }
+
+        match (self.streams.len(), self.maximized) {
+            (0, _) => {}
+            (1, _) => self.single_ui(ctx, 0),
+            (_, Some(index)) => self.single_ui(ctx, index),
+            (_, None) => self.grid_ui(ctx),
+        }
     }
 }
@@ -0,0 +1,123 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::sync::mpsc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use video_zenoh_player::DecodedFrame;
+use video_zenoh_player::cdr::DecodeErrors;
+use video_zenoh_player::latency::Latency;
+use video_zenoh_player::queue::Drops;
+use video_zenoh_player::stats::StreamStats;
+
+/// How often the decoded-frame channels are drained (about one GUI frame).
+const POLL_INTERVAL: Duration = Duration::from_millis(16);
+
+/// One JSON line of headless output, per stream and interval.
+#[derive(Serialize)]
+struct Report<'a> {
+    /// Wall-clock time of the report, seconds since the UNIX epoch.
+    time: f64,
+    topic: &'a str,
+    frames: u64,
+    /// Frames dropped by the ingest queue so far.
+    dropped: u64,
+    /// Payloads that were not a playable message so far, by error class.
+    decode_errors: BTreeMap<&'static str, u64>,
+    width: u32,
+    height: u32,
+    source_width: u32,
+    source_height: u32,
+    fps: f32,
+    mbps: f32,
+    latency_ms: Option<Latency>,
+    total_latency_ms: Option<f32>,
+    /// Seconds since the last decoded frame; `None` before the first.
+    last_frame_age_s: Option<f32>,
+}
+
+/// Consume the decoded frames of every stream without a window and print
+/// the same metrics the GUI shows as one JSON line per stream every
+/// `interval`.
+///
+/// Runs until `duration` has passed, or forever if `None`. Returns `false`
+/// if any stream never produced a frame.
+pub fn run(
+    streams: Vec<(String, mpsc::Receiver<DecodedFrame>, Drops, DecodeErrors)>,
+    interval: Duration,
+    duration: Option<Duration>,
+) -> bool {
+    let mut stats: Vec<StreamStats> = streams
+        .iter()
+        .map(|(_, _, queue_drops, decode_errors)| {
+            StreamStats::new(queue_drops.clone(), decode_errors.clone())
+        })
+        .collect();
+    let started = Instant::now();
+    let mut next_report = started + interval;
+
+    loop {
+        for ((topic, decoded_rx, ..), stats) in streams.iter().zip(&mut stats) {
+            // Same accounting as the GUI: every frame counts, the newest one
+            // is "shown".
+            let mut latest = None;
+            while let Ok(frame) = decoded_rx.try_recv() {
+                stats.frame_decoded(frame.compressed_size);
+                latest = Some(frame);
+            }
+            if let Some(frame) = latest {
+                stats.frame_shown(&frame);
+            }
+            stats.update(topic);
+        }
+
+        let now = Instant::now();
+        if now >= next_report {
+            next_report += interval;
+            let time = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs_f64();
+            let mut out = std::io::stdout().lock();
+            for ((topic, ..), stats) in streams.iter().zip(&stats) {
+                let report = Report {
+                    time,
+                    topic,
+                    frames: stats.frame_count,
+                    dropped: stats.queue_drops.get(),
+                    decode_errors: stats.decode_errors.get().into_iter().collect(),
+                    width: stats.video_width,
+                    height: stats.video_height,
+                    source_width: stats.source_width,
+                    source_height: stats.source_height,
+                    fps: stats.fps_current,
+                    mbps: stats.speed_current,
+                    latency_ms: stats.latency_current,
+                    total_latency_ms: stats.latency_current.map(|l| l.total_ms()),
+                    last_frame_age_s: stats
+                        .last_frame_time
+                        .map(|t| now.duration_since(t).as_secs_f32()),
+                };
+                if let Ok(line) = serde_json::to_string(&report) {
+                    let _ = writeln!(out, "{line}");
+                }
+            }
+            let _ = out.flush();
+        }
+
+        if duration.is_some_and(|d| started.elapsed() >= d) {
+            break;
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+
+    let mut ok = true;
+    for ((topic, ..), stats) in streams.iter().zip(&stats) {
+        if stats.frame_count == 0 {
+            eprintln!("No frames received on '{topic}'");
+            ok = false;
+        }
+    }
+    ok
+}
@@ -0,0 +1,466 @@
+use anyhow::{Context, bail};
+use image::RgbaImage;
+
+use crate::codec::Codec;
+use crate::frame::EncodedFrame;
+
+/// Pixel encodings of a foxglove RawImage (or ROS `sensor_msgs/Image`) that
+/// can be shown.
+/// <https://docs.foxglove.dev/docs/visualization/message-schemas/raw-image>
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RawEncoding {
+    Rgb8,
+    Rgba8,
+    Bgr8,
+    Bgra8,
+    Mono8,
+    /// 16-bit gray, e.g. depth; stretched to the frame's range for
+    /// display.
+    Mono16,
+    /// YUV 4:2:2, Y0 U Y1 V.
+    Yuyv,
+    /// YUV 4:2:2, U Y0 V Y1.
+    Uyvy,
+    BayerRggb8,
+    BayerBggr8,
+    BayerGbrg8,
+    BayerGrbg8,
+}
+
+impl RawEncoding {
+    /// Parse a RawImage `encoding` string (case-insensitive).
+    ///
+    /// Accepts the Foxglove names plus the ROS aliases "8UC1", "16UC1",
+    /// "yuv422_yuy2" and "yuv422".
+    pub fn from_encoding(encoding: &str) -> Option<Self> {
+        match encoding.trim().to_ascii_lowercase().as_str() {
+            "rgb8" => Some(Self::Rgb8),
+            "rgba8" => Some(Self::Rgba8),
+            "bgr8" => Some(Self::Bgr8),
+            "bgra8" => Some(Self::Bgra8),
+            "mono8" | "8uc1" => Some(Self::Mono8),
+            "mono16" | "16uc1" => Some(Self::Mono16),
+            "yuyv" | "yuv422_yuy2" => Some(Self::Yuyv),
+            "uyvy" | "yuv422" => Some(Self::Uyvy),
+            "bayer_rggb8" => Some(Self::BayerRggb8),
+            "bayer_bggr8" => Some(Self::BayerBggr8),
+            "bayer_gbrg8" => Some(Self::BayerGbrg8),
+            "bayer_grbg8" => Some(Self::BayerGrbg8),
+            _ => None,
+        }
+    }
+
+    /// Canonical Foxglove encoding name.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Rgb8 => "rgb8",
+            Self::Rgba8 => "rgba8",
+            Self::Bgr8 => "bgr8",
+            Self::Bgra8 => "bgra8",
+            Self::Mono8 => "mono8",
+            Self::Mono16 => "mono16",
+            Self::Yuyv => "yuyv",
+            Self::Uyvy => "uyvy",
+            Self::BayerRggb8 => "bayer_rggb8",
+            Self::BayerBggr8 => "bayer_bggr8",
+            Self::BayerGbrg8 => "bayer_gbrg8",
+            Self::BayerGrbg8 => "bayer_grbg8",
+        }
+    }
+
+    /// Bytes of pixel data in a row of `width` pixels, without padding.
+    fn row_bytes(self, width: usize) -> usize {
+        match self {
+            Self::Rgb8 | Self::Bgr8 => width * 3,
+            Self::Rgba8 | Self::Bgra8 => width * 4,
+            Self::Mono16 => width * 2,
+            // One U/V pair per two pixels.
+            Self::Yuyv | Self::Uyvy => width.div_ceil(2) * 4,
+            Self::Mono8
+            | Self::BayerRggb8
+            | Self::BayerBggr8
+            | Self::BayerGbrg8
+            | Self::BayerGrbg8 => width,
+        }
+    }
+
+    /// Colour filter of a Bayer encoding: channel (0 = R, 1 = G, 2 = B) at
+    /// (0, 0), (1, 0), (0, 1) and (1, 1) of each 2×2 cell.
+    fn bayer_pattern(self) -> Option<[usize; 4]> {
+        match self {
+            Self::BayerRggb8 => Some([0, 1, 1, 2]),
+            Self::BayerBggr8 => Some([2, 1, 1, 0]),
+            Self::BayerGbrg8 => Some([1, 2, 0, 1]),
+            Self::BayerGrbg8 => Some([1, 0, 2, 1]),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for RawEncoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// How the pixels of a raw image frame are laid out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawLayout {
+    pub encoding: RawEncoding,
+    pub width: u32,
+    pub height: u32,
+    /// Bytes from one row to the next, padding included.
+    pub step: u32,
+    /// 16-bit samples are big-endian (ROS `is_bigendian`); Foxglove
+    /// RawImage is always little-endian.
+    pub big_endian: bool,
+}
+
+impl RawLayout {
+    /// Check that `len` bytes of data hold every row.
+    pub fn check(&self, len: usize) -> anyhow::Result<()> {
+        let (width, height) = (self.width as usize, self.height as usize);
+        let row_bytes = self.encoding.row_bytes(width);
+        if width == 0 || height == 0 {
+            bail!("empty {width}×{height} image");
+        }
+        if (self.step as usize) < row_bytes {
+            bail!(
+                "step {} too small for {width} {} pixels",
+                self.step,
+                self.encoding
+            );
+        }
+        // The last row may come without its padding.
+        let needed = self.step as usize * (height - 1) + row_bytes;
+        if len < needed {
+            bail!("{len} bytes of data, a {width}×{height} image needs {needed}");
+        }
+        Ok(())
+    }
+}
+
+/// Decode a still image frame (see [`Codec::is_image`]) to RGBA: JPEG and
+/// PNG with the `image` crate, raw pixels by conversion or debayering.
+pub fn decode(frame: &EncodedFrame) -> anyhow::Result<RgbaImage> {
+    match frame.codec {
+        Codec::Jpeg | Codec::Png => {
+            let format = if frame.codec == Codec::Jpeg {
+                image::ImageFormat::Jpeg
+            } else {
+                image::ImageFormat::Png
+            };
+            Ok(image::load_from_memory_with_format(&frame.data, format)?.to_rgba8())
+        }
+        Codec::Raw => {
+            let layout = frame.raw.context("raw image without a pixel layout")?;
+            convert(&layout, &frame.data)
+        }
+        codec => bail!("{codec} is not an image format"),
+    }
+}
+
+/// Convert raw pixels laid out as `layout` to RGBA.
+pub fn convert(layout: &RawLayout, data: &[u8]) -> anyhow::Result<RgbaImage> {
+    layout.check(data.len())?;
+    let encoding = layout.encoding;
+    let (width, height) = (layout.width as usize, layout.height as usize);
+    let row = |y: usize| &data[y * layout.step as usize..][..encoding.row_bytes(width)];
+
+    let mut rgba = Vec::with_capacity(width * height * 4);
+    match encoding {
+        RawEncoding::Rgb8 | RawEncoding::Bgr8 => {
+            let (r, b) = if encoding == RawEncoding::Rgb8 {
+                (0, 2)
+            } else {
+                (2, 0)
+            };
+            for y in 0..height {
+                for px in row(y).chunks_exact(3) {
+                    rgba.extend_from_slice(&[px[r], px[1], px[b], 255]);
+                }
+            }
+        }
+        RawEncoding::Rgba8 => {
+            for y in 0..height {
+                rgba.extend_from_slice(row(y));
+            }
+        }
+        RawEncoding::Bgra8 => {
+            for y in 0..height {
+                for px in row(y).chunks_exact(4) {
+                    rgba.extend_from_slice(&[px[2], px[1], px[0], px[3]]);
+                }
+            }
+        }
+        RawEncoding::Mono8 => {
+            for y in 0..height {
+                for &v in row(y) {
+                    rgba.extend_from_slice(&[v, v, v, 255]);
+                }
+            }
+        }
+        RawEncoding::Mono16 => {
+            let sample = |px: &[u8]| {
+                if layout.big_endian {
+                    u16::from_be_bytes([px[0], px[1]])
+                } else {
+                    u16::from_le_bytes([px[0], px[1]])
+                }
+            };
+            let (mut min, mut max) = (u16::MAX, 0);
+            for y in 0..height {
+                for px in row(y).chunks_exact(2) {
+                    let v = sample(px);
+                    min = min.min(v);
+                    max = max.max(v);
+                }
+            }
+            let range = u32::from(max.saturating_sub(min)).max(1);
+            for y in 0..height {
+                for px in row(y).chunks_exact(2) {
+                    let v = (u32::from(sample(px) - min) * 255 / range) as u8;
+                    rgba.extend_from_slice(&[v, v, v, 255]);
+                }
+            }
+        }
+        RawEncoding::Yuyv | RawEncoding::Uyvy => {
+            // Byte offsets of Y0, U, Y1 and V in each 4-byte pair.
+            let [y0, u, y1, v] = if encoding == RawEncoding::Yuyv {
+                [0, 1, 2, 3]
+            } else {
+                [1, 0, 3, 2]
+            };
+            for y in 0..height {
+                let start = rgba.len();
+                for pair in row(y).chunks_exact(4) {
+                    rgba.extend_from_slice(&yuv_to_rgba(pair[y0], pair[u], pair[v]));
+                    rgba.extend_from_slice(&yuv_to_rgba(pair[y1], pair[u], pair[v]));
+                }
+                // An odd width leaves half a pair over.
+                rgba.truncate(start + width * 4);
+            }
+        }
+        RawEncoding::BayerRggb8
+        | RawEncoding::BayerBggr8
+        | RawEncoding::BayerGbrg8
+        | RawEncoding::BayerGrbg8 => {
+            let pattern = encoding.bayer_pattern().expect("Bayer encoding");
+            debayer(pattern, width, height, &row, &mut rgba)?;
+        }
+    }
+    RgbaImage::from_raw(layout.width, layout.height, rgba).context("converted image size")
+}
+
+/// BT.601 limited range YUV to RGBA.
+fn yuv_to_rgba(y: u8, u: u8, v: u8) -> [u8; 4] {
+    let c = (i32::from(y) - 16) * 298;
+    let d = i32::from(u) - 128;
+    let e = i32::from(v) - 128;
+    let clamp = |x: i32| ((x + 128) >> 8).clamp(0, 255) as u8;
+    [
+        clamp(c + 409 * e),
+        clamp(c - 100 * d - 208 * e),
+        clamp(c + 516 * d),
+        255,
+    ]
+}
+
+/// Demosaic a Bayer image: every pixel takes its colour from the 2×2 cell
+/// it starts, which holds one red, two green and one blue sample. Cheap and
+/// without colour fringes, at the cost of some sharpness.
+fn debayer<'a>(
+    pattern: [usize; 4],
+    width: usize,
+    height: usize,
+    row: &impl Fn(usize) -> &'a [u8],
+    rgba: &mut Vec<u8>,
+) -> anyhow::Result<()> {
+    if width < 2 || height < 2 {
+        bail!("a Bayer image needs at least 2×2 pixels, got {width}×{height}");
+    }
+    for y in 0..height {
+        // The last row and column pair up with the one before.
+        let y0 = y.min(height - 2);
+        let (top, bottom) = (row(y0), row(y0 + 1));
+        for x in 0..width {
+            let x0 = x.min(width - 2);
+            let mut sum = [0u16; 3];
+            for (dy, line) in [top, bottom].into_iter().enumerate() {
+                for dx in 0..2 {
+                    let channel = pattern[((y0 + dy) % 2) * 2 + (x0 + dx) % 2];
+                    sum[channel] += u16::from(line[x0 + dx]);
+                }
+            }
+            rgba.extend_from_slice(&[sum[0] as u8, (sum[1] / 2) as u8, sum[2] as u8, 255]);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout(encoding: RawEncoding, width: u32, height: u32, step: u32) -> RawLayout {
+        RawLayout {
+            encoding,
+            width,
+            height,
+            step,
+            big_endian: false,
+        }
+    }
+
+    /// RGBA bytes of `data` converted as `layout`.
+    fn rgba(layout: RawLayout, data: &[u8]) -> Vec<u8> {
+        convert(&layout, data).unwrap().into_raw()
+    }
+
+    #[test]
+    fn parses_encoding_names() {
+        assert_eq!(
+            RawEncoding::from_encoding(" BGR8 "),
+            Some(RawEncoding::Bgr8)
+        );
+        assert_eq!(
+            RawEncoding::from_encoding("16UC1"),
+            Some(RawEncoding::Mono16)
+        );
+        assert_eq!(
+            RawEncoding::from_encoding("yuv422"),
+            Some(RawEncoding::Uyvy)
+        );
+        assert_eq!(RawEncoding::from_encoding("32FC1"), None);
+        for encoding in ["rgb8", "mono16", "yuyv", "bayer_grbg8"] {
+            assert_eq!(
+                RawEncoding::from_encoding(encoding).unwrap().as_str(),
+                encoding
+            );
+        }
+    }
+
+    #[test]
+    fn check_rejects_short_data_and_steps() {
+        let rgb = layout(RawEncoding::Rgb8, 2, 2, 8);
+        // The last row may come without its padding.
+        assert!(rgb.check(14).is_ok());
+        assert!(rgb.check(13).is_err());
+        assert!(layout(RawEncoding::Rgb8, 2, 2, 5).check(100).is_err());
+        assert!(layout(RawEncoding::Rgb8, 0, 2, 8).check(100).is_err());
+        // Half a YUV pair still takes four bytes.
+        assert!(layout(RawEncoding::Yuyv, 3, 1, 6).check(6).is_err());
+        assert!(layout(RawEncoding::Yuyv, 3, 1, 8).check(8).is_ok());
+        assert!(convert(&rgb, &[0; 13]).is_err());
+    }
+
+    #[test]
+    fn converts_channel_orders() {
+        // Two rows of two pixels, two bytes of padding per row.
+        let rgb = [10, 20, 30, 40, 50, 60, 0, 0, 70, 80, 90, 100, 110, 120];
+        assert_eq!(
+            rgba(layout(RawEncoding::Rgb8, 2, 2, 8), &rgb),
+            [
+                10, 20, 30, 255, 40, 50, 60, 255, 70, 80, 90, 255, 100, 110, 120, 255
+            ]
+        );
+        assert_eq!(
+            rgba(layout(RawEncoding::Bgr8, 2, 2, 8), &rgb),
+            [
+                30, 20, 10, 255, 60, 50, 40, 255, 90, 80, 70, 255, 120, 110, 100, 255
+            ]
+        );
+
+        let four = [10, 20, 30, 40, 50, 60, 70, 80];
+        assert_eq!(rgba(layout(RawEncoding::Rgba8, 2, 1, 8), &four), four);
+        assert_eq!(
+            rgba(layout(RawEncoding::Bgra8, 2, 1, 8), &four),
+            [30, 20, 10, 40, 70, 60, 50, 80]
+        );
+        assert_eq!(
+            rgba(layout(RawEncoding::Mono8, 2, 1, 2), &[7, 200]),
+            [7, 7, 7, 255, 200, 200, 200, 255]
+        );
+    }
+
+    #[test]
+    fn stretches_mono16_in_either_byte_order() {
+        let gray = |pixels: Vec<u8>| -> Vec<u8> { pixels.chunks(4).map(|px| px[0]).collect() };
+        let mut mono16 = layout(RawEncoding::Mono16, 3, 1, 6);
+        let le: Vec<u8> = [1000u16, 2000, 3000]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        assert_eq!(gray(rgba(mono16, &le)), [0, 127, 255]);
+
+        mono16.big_endian = true;
+        let be: Vec<u8> = [3000u16, 1000, 2000]
+            .iter()
+            .flat_map(|v| v.to_be_bytes())
+            .collect();
+        assert_eq!(gray(rgba(mono16, &be)), [255, 0, 127]);
+
+        // A flat image does not divide by zero.
+        assert_eq!(gray(rgba(mono16, &[1, 2, 1, 2, 1, 2])), [0, 0, 0]);
+    }
+
+    #[test]
+    fn converts_yuv_422_with_odd_widths() {
+        // Black, white and mid gray; the fourth luma sample is padding.
+        let yuyv = [16, 128, 235, 128, 126, 128, 99, 128];
+        let expected = [0, 0, 0, 255, 255, 255, 255, 255, 128, 128, 128, 255];
+        assert_eq!(rgba(layout(RawEncoding::Yuyv, 3, 1, 8), &yuyv), expected);
+        let uyvy = [128, 16, 128, 235, 128, 126, 128, 99];
+        assert_eq!(rgba(layout(RawEncoding::Uyvy, 3, 1, 8), &uyvy), expected);
+    }
+
+    #[test]
+    fn yuv_is_bt601_limited_range() {
+        assert_eq!(yuv_to_rgba(16, 128, 128), [0, 0, 0, 255]);
+        assert_eq!(yuv_to_rgba(235, 128, 128), [255, 255, 255, 255]);
+        assert_eq!(yuv_to_rgba(81, 90, 240), [255, 0, 0, 255]);
+        assert_eq!(yuv_to_rgba(145, 54, 34), [0, 255, 1, 255]);
+        assert_eq!(yuv_to_rgba(41, 240, 110), [0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn debayers_every_pattern() {
+        for encoding in [
+            RawEncoding::BayerRggb8,
+            RawEncoding::BayerBggr8,
+            RawEncoding::BayerGbrg8,
+            RawEncoding::BayerGrbg8,
+        ] {
+            // One 2×2 cell: red 200, greens 100 and 50, blue 10.
+            let mut greens = [100, 50].into_iter();
+            let cell: Vec<u8> = encoding
+                .bayer_pattern()
+                .unwrap()
+                .iter()
+                .map(|&channel| match channel {
+                    0 => 200,
+                    2 => 10,
+                    _ => greens.next().unwrap(),
+                })
+                .collect();
+            let pixels = rgba(layout(encoding, 2, 2, 2), &cell);
+            assert_eq!(pixels, [200, 75, 10, 255].repeat(4), "{encoding}");
+        }
+
+        // Odd sizes: the last row and column reuse the cell before.
+        let rggb = [200, 100, 200, 100, 10, 100, 200, 100, 200];
+        assert_eq!(
+            rgba(layout(RawEncoding::BayerRggb8, 3, 3, 3), &rggb),
+            [200, 100, 10, 255].repeat(9)
+        );
+    }
+
+    #[test]
+    fn rejects_bayer_smaller_than_a_cell() {
+        for (width, height) in [(1, 2), (2, 1), (1, 1)] {
+            let error =
+                convert(&layout(RawEncoding::BayerRggb8, width, height, 2), &[0; 4]).unwrap_err();
+            assert!(error.to_string().contains("2×2"), "{error:#}");
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use crate::cdr::Encapsulation;
+use crate::codec::Codec;
+use crate::frame::EncodedFrame;
+use crate::nal::{self, SliceType, Sps};
+
+/// Frames kept for the timeline and frame list.
+pub const HISTORY_LEN: usize = 300;
+
+/// What the inspector learned about one access unit.
+#[derive(Debug, Clone)]
+pub struct FrameInfo {
+    /// Compressed size in bytes.
+    pub size: usize,
+    /// NAL unit types in bitstream order (H.264/H.265 only).
+    pub nal_types: Vec<u8>,
+    /// Type of the first slice; for VP9/AV1 only I (key) or P.
+    pub frame_type: Option<SliceType>,
+    pub keyframe: bool,
+}
+
+/// Bitstream statistics of one stream for the inspector panel.
+#[derive(Debug, Default)]
+pub struct Inspector {
+    pub codec: Option<Codec>,
+    /// CDR representation of the latest message.
+    pub encapsulation: Option<Encapsulation>,
+    /// Most recent frames, oldest first.
+    pub frames: VecDeque<FrameInfo>,
+    /// Latest SPS seen.
+    pub sps: Option<Sps>,
+    /// Frames from the previous keyframe to the latest one.
+    pub gop_length: Option<u64>,
+    frames_since_keyframe: Option<u64>,
+    /// `num_extra_slice_header_bits` of the latest H.265 PPS.
+    extra_slice_header_bits: u32,
+}
+
+impl Inspector {
+    /// Parse one frame.
+    pub fn push(&mut self, frame: &EncodedFrame) {
+        let codec = frame.codec;
+        if self.codec != Some(codec) {
+            *self = Self {
+                codec: Some(codec),
+                ..Self::default()
+            };
+        }
+        self.encapsulation = frame.encapsulation;
+
+        let keyframe = nal::is_keyframe(codec, &frame.data);
+        let mut nal_types = Vec::new();
+        let mut frame_type = None;
+        if matches!(codec, Codec::H264 | Codec::H265) {
+            for unit in nal::split_annex_b(&frame.data) {
+                let Some(t) = nal::nal_type(codec, unit) else {
+                    continue;
+                };
+                nal_types.push(t);
+                if nal::is_sps(codec, t)
+                    && let Some(sps) = nal::parse_sps(codec, unit)
+                {
+                    self.sps = Some(sps);
+                }
+                if codec == Codec::H265
+                    && t == 34
+                    && let Some(bits) = nal::h265_extra_slice_header_bits(unit)
+                {
+                    self.extra_slice_header_bits = bits;
+                }
+                if frame_type.is_none() {
+                    frame_type = nal::slice_type(codec, unit, self.extra_slice_header_bits);
+                }
+            }
+        } else {
+            frame_type = Some(if keyframe { SliceType::I } else { SliceType::P });
+        }
+
+        if keyframe {
+            if let Some(n) = self.frames_since_keyframe {
+                self.gop_length = Some(n);
+            }
+            self.frames_since_keyframe = Some(0);
+        }
+        if let Some(n) = &mut self.frames_since_keyframe {
+            *n += 1;
+        }
+
+        if self.frames.len() >= HISTORY_LEN {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(FrameInfo {
+            size: frame.data.len(),
+            nal_types,
+            frame_type,
+            keyframe,
+        });
+    }
+}
+
+/// Inspectors of the streams being inspected, by stream index. The
+/// subscriber thread only parses streams that have an entry.
+pub type Inspected = Arc<Mutex<HashMap<usize, Inspector>>>;
+
+/// Profile name of an SPS `profile_idc`.
+pub fn profile_name(codec: Codec, profile_idc: u8) -> Option<&'static str> {
+    match (codec, profile_idc) {
+        (Codec::H264, 66) => Some("Baseline"),
+        (Codec::H264, 77) => Some("Main"),
+        (Codec::H264, 88) => Some("Extended"),
+        (Codec::H264, 100) => Some("High"),
+        (Codec::H264, 110) => Some("High 10"),
+        (Codec::H264, 122) => Some("High 4:2:2"),
+        (Codec::H264, 244) => Some("High 4:4:4"),
+        (Codec::H265, 1) => Some("Main"),
+        (Codec::H265, 2) => Some("Main 10"),
+        (Codec::H265, 3) => Some("Main Still Picture"),
+        (Codec::H265, 4) => Some("Range Extensions"),
+        _ => None,
+    }
+}
+
+/// Level number of an SPS `level_idc`, e.g. 4.1.
+pub fn level(codec: Codec, level_idc: u8) -> f32 {
+    match codec {
+        Codec::H265 => level_idc as f32 / 30.0,
+        _ => level_idc as f32 / 10.0,
+    }
+}
+
+/// Name of an ISO/IEC 23091-2 colour primaries / transfer / matrix code
+/// point, for the common ones.
+pub fn colour_name(code_point: u8, kind: ColourField) -> Option<&'static str> {
+    match (kind, code_point) {
+        (_, 1) => Some("BT.709"),
+        (ColourField::Primaries, 5) => Some("BT.601 625"),
+        (ColourField::Primaries, 6) => Some("BT.601 525"),
+        (ColourField::Primaries, 9) => Some("BT.2020"),
+        (ColourField::Transfer, 6) => Some("BT.601"),
+        (ColourField::Transfer, 13) => Some("sRGB"),
+        (ColourField::Transfer, 16) => Some("PQ"),
+        (ColourField::Transfer, 18) => Some("HLG"),
+        (ColourField::Matrix, 0) => Some("RGB"),
+        (ColourField::Matrix, 5 | 6) => Some("BT.601"),
+        (ColourField::Matrix, 9) => Some("BT.2020 NCL"),
+        _ => None,
+    }
+}
+
+/// Which of the VUI colour description fields a code point belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColourField {
+    Primaries,
+    Transfer,
+    Matrix,
+}
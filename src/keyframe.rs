@@ -0,0 +1,97 @@
+use std::time::Duration;
+
+use crate::metrics::METRICS;
+use crate::zenoh_sub::Command;
+
+/// Key a keyframe request for `topic` goes to, unless configured otherwise.
+pub const DEFAULT_KEY: &str = "{topic}/keyframe_request";
+
+/// How long a query waits for the publisher's reply.
+const QUERY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How keyframe requests reach the publisher.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum RequestMode {
+    /// Zenoh query; the publisher's reply, if any, is logged as its status.
+    Query,
+    /// Fire-and-forget put.
+    Put,
+    /// Never ask for keyframes.
+    Off,
+}
+
+/// Handle a decoder uses to ask the publisher of its stream for a keyframe,
+/// e.g. after joining mid-GOP or losing data.
+///
+/// Requests are rate-limited per stream by the subscriber thread, so callers
+/// can ask whenever they need one.
+#[derive(Clone)]
+pub struct KeyframeRequester {
+    pub(crate) index: usize,
+    pub(crate) cmd_tx: tokio::sync::mpsc::UnboundedSender<Command>,
+}
+
+impl KeyframeRequester {
+    /// Ask for a keyframe; `reason` is sent as the payload and counted in
+    /// the metrics.
+    pub fn request(&self, reason: &'static str) {
+        let _ = self.cmd_tx.send(Command::RequestKeyframe {
+            index: self.index,
+            reason,
+        });
+    }
+}
+
+/// Request key for `topic` from a template where `{topic}` stands for the
+/// subscribed key expression.
+pub(crate) fn request_key(template: &str, topic: &str) -> String {
+    template.replace("{topic}", topic)
+}
+
+/// Send one keyframe request for the stream on `topic`.
+pub(crate) async fn send(
+    session: zenoh::Session,
+    mode: RequestMode,
+    key: String,
+    topic: String,
+    reason: &'static str,
+) {
+    METRICS
+        .keyframe_requests
+        .with_label_values(&[&topic, reason])
+        .inc();
+    match mode {
+        RequestMode::Query => {
+            let replies = match session
+                .get(&key)
+                .payload(reason)
+                .timeout(QUERY_TIMEOUT)
+                .await
+            {
+                Ok(replies) => replies,
+                Err(e) => {
+                    eprintln!("Keyframe request on '{key}' failed: {e}");
+                    return;
+                }
+            };
+            while let Ok(reply) = replies.recv_async().await {
+                match reply.result() {
+                    Ok(sample) => println!(
+                        "Keyframe request on '{key}' ({reason}): {}",
+                        sample.payload().try_to_string().unwrap_or_default()
+                    ),
+                    Err(err) => eprintln!(
+                        "Keyframe request on '{key}' refused: {}",
+                        err.payload().try_to_string().unwrap_or_default()
+                    ),
+                }
+            }
+        }
+        RequestMode::Put => {
+            if let Err(e) = session.put(&key, reason).await {
+                eprintln!("Keyframe request on '{key}' failed: {e}");
+            }
+        }
+        RequestMode::Off => {}
+    }
+}
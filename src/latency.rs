@@ -0,0 +1,86 @@
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::cdr::Timestamp;
+
+/// Source timestamps further than this from the local wall clock are assumed
+/// to use a non-UNIX epoch and are not used for latency.
+const MAX_CLOCK_SKEW: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Timing of one frame as it moves through the player.
+#[derive(Debug, Clone, Copy)]
+pub struct FrameTiming {
+    /// Timestamp carried in the CompressedVideo message, if set.
+    pub timestamp: Option<Timestamp>,
+    /// Publisher HLC timestamp of the Zenoh sample, if the session adds one.
+    pub hlc: Option<SystemTime>,
+    /// Wall-clock time the sample arrived from Zenoh.
+    pub received_wall: SystemTime,
+    /// Monotonic time the sample arrived from Zenoh.
+    pub received: Instant,
+    /// Monotonic time the decoder produced the RGBA frame.
+    pub decoded: Option<Instant>,
+}
+
+/// Per-frame latency breakdown, in milliseconds.
+#[derive(Debug, Clone, Copy, Default, serde::Serialize)]
+pub struct Latency {
+    /// Source timestamp → Zenoh arrival. `None` without a usable source clock.
+    pub receive_ms: Option<f32>,
+    /// Zenoh arrival → decoded RGBA frame.
+    pub decode_ms: f32,
+    /// Decoded RGBA frame → texture upload.
+    pub display_ms: f32,
+}
+
+impl Latency {
+    /// Age of the frame on screen: receive + decode + display.
+    pub fn total_ms(&self) -> f32 {
+        self.receive_ms.unwrap_or(0.0) + self.decode_ms + self.display_ms
+    }
+}
+
+impl FrameTiming {
+    /// Start timing a sample that just arrived.
+    pub fn received(timestamp: Option<Timestamp>, hlc: Option<SystemTime>) -> Self {
+        Self {
+            timestamp,
+            hlc,
+            received_wall: SystemTime::now(),
+            received: Instant::now(),
+            decoded: None,
+        }
+    }
+
+    /// Best wall-clock estimate of when the frame was produced.
+    ///
+    /// Prefers the message timestamp when it looks like UNIX time, and falls
+    /// back to the Zenoh HLC timestamp otherwise.
+    pub fn source_time(&self) -> Option<SystemTime> {
+        self.timestamp
+            .map(|ts| UNIX_EPOCH + Duration::from_nanos(ts.as_nanos()))
+            .filter(|t| abs_diff(*t, self.received_wall) < MAX_CLOCK_SKEW)
+            .or(self.hlc)
+    }
+
+    /// Latency breakdown for a frame shown at `displayed`.
+    pub fn latency(&self, displayed: Instant) -> Latency {
+        let decoded = self.decoded.unwrap_or(displayed);
+        Latency {
+            // Negative values mean the clocks disagree; clamp instead of
+            // reporting time travel.
+            receive_ms: self.source_time().map(|src| {
+                self.received_wall
+                    .duration_since(src)
+                    .unwrap_or_default()
+                    .as_secs_f32()
+                    * 1000.0
+            }),
+            decode_ms: decoded.duration_since(self.received).as_secs_f32() * 1000.0,
+            display_ms: displayed.duration_since(decoded).as_secs_f32() * 1000.0,
+        }
+    }
+}
+
+fn abs_diff(a: SystemTime, b: SystemTime) -> Duration {
+    a.duration_since(b).unwrap_or_else(|e| e.duration())
+}
@@ -0,0 +1,39 @@
+//! Zenoh video pipeline: subscribe to foxglove CompressedVideo topics (or
+//! CompressedImage / RawImage ones), decode them with GStreamer or the
+//! built-in OpenH264 decoder, and record or replay the streams.
+//!
+//! [`SubscriberBuilder`] (or [`playback`]) produces [`EncodedFrame`]s into a
+//! bounded [`queue`], a [`DecoderBuilder`] thread turns them into
+//! [`DecodedFrame`]s sent over a `std::sync::mpsc` channel. The `player`
+//! binary is a GUI / headless client of this crate.
+
+#[cfg(not(any(feature = "gstreamer", feature = "openh264")))]
+compile_error!("enable at least one decoder backend: the `gstreamer` or `openh264` feature");
+
+pub mod cdr;
+pub mod codec;
+pub mod decoder;
+pub mod discovery;
+pub mod frame;
+pub mod gate;
+pub mod images;
+pub mod inspect;
+pub mod keyframe;
+pub mod latency;
+pub mod mcap;
+pub mod metrics;
+pub mod nal;
+pub mod playback;
+pub mod queue;
+pub mod recorder;
+pub mod scaling;
+pub mod session;
+pub mod stats;
+pub mod zenoh_sub;
+
+pub use codec::Codec;
+pub use decoder::{Decoder, DecoderBuilder, DecoderChoice};
+pub use frame::{DecodedFrame, EncodedFrame, PixelFormat};
+pub use latency::FrameTiming;
+pub use session::SessionConfig;
+pub use zenoh_sub::SubscriberBuilder;
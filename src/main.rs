@@ -1,38 +1,196 @@
-mod cdr;
 mod cli;
-mod decoder;
 mod gui;
-mod zenoh_sub;
+mod headless;
 
+use std::collections::HashMap;
+use std::sync::Arc;
 use std::sync::mpsc;
-use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use clap::Parser;
 use eframe::egui;
+use video_zenoh_player::{
+    Codec, DecodedFrame, DecoderBuilder, SubscriberBuilder, decoder, discovery, inspect, mcap,
+    metrics, playback, queue, recorder, scaling, zenoh_sub,
+};
 
 fn main() -> eframe::Result {
-    let args = cli::Args::parse();
-    let topic_display = args.topic.clone();
+    let mut args = cli::Args::parse();
+    let session = args.session_config();
+    // Fail on a bad config file now rather than retrying it forever.
+    if let Err(e) = session.to_config() {
+        eprintln!("{e:#}");
+        std::process::exit(1);
+    }
 
-    // Initialize GStreamer
-    gstreamer::init().expect("Failed to initialize GStreamer");
+    // Load the file to play before opening any window or pipeline.
+    let playback_file = match args.command.take() {
+        Some(cli::Command::Record { output }) => {
+            if let Err(e) = zenoh_sub::record_mcap(&session, &args.topics, &output, args.schema) {
+                eprintln!("Recording failed: {e:#}");
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Some(cli::Command::ProbeDecoders) => {
+            if let Err(e) = print_decoders() {
+                eprintln!("Cannot probe decoders: {e:#}");
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Some(cli::Command::Play { input }) => match mcap::McapFile::read(&input) {
+            Ok(file) => Some(file),
+            Err(e) => {
+                eprintln!("Cannot play {}: {e:#}", input.display());
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
 
-    // --- Channels ---
-    let (h264_tx, h264_rx) = mpsc::channel::<Vec<u8>>();
-    // (rgba_data, width, height, compressed_h264_size)
-    let (rgba_tx, rgba_rx) = mpsc::sync_channel::<(Vec<u8>, u32, u32, usize)>(2);
+    if let Some(addr) = args.metrics_listen
+        && let Err(e) = metrics::serve(addr)
+    {
+        eprintln!("{e:#}");
+        std::process::exit(1);
+    }
 
-    // --- Zenoh subscriber (background thread) ---
-    zenoh_sub::spawn(args.endpoint, args.topic, h264_tx);
+    // Streams: the subscribed topics, or the CDR channels of the file.
+    let channels: Vec<(u16, String)> = match &playback_file {
+        Some(file) => file
+            .channels
+            .iter()
+            .filter(|(_, channel)| channel.message_encoding == "cdr")
+            .map(|(&id, channel)| (id, channel.topic.clone()))
+            .collect(),
+        None => (0..).zip(args.topics).collect(),
+    };
+    if channels.is_empty() {
+        eprintln!("Nothing to play: no CDR channels in the file");
+        std::process::exit(1);
+    }
 
-    // --- GStreamer decode thread (auto-restarts on error) ---
-    let pipeline_element: Arc<Mutex<Option<gstreamer::Element>>> = Arc::new(Mutex::new(None));
-    let pipeline_for_app = pipeline_element.clone();
+    let recording = Arc::new(recorder::Recording::new(
+        args.record,
+        args.record_dir,
+        args.record_container,
+        args.record_max_size_mb * 1_000_000,
+        Duration::from_secs(args.record_max_duration),
+    ));
 
+    // --- Finalize recordings on Ctrl+C instead of leaving truncated files ---
+    let recording_for_signal = recording.clone();
     std::thread::spawn(move || {
-        decoder::run_loop(h264_rx, rgba_tx, pipeline_element);
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("Failed to create signal runtime");
+        if rt.block_on(tokio::signal::ctrl_c()).is_ok() {
+            println!("Interrupted, finalizing recordings...");
+            recording_for_signal.shutdown();
+            std::process::exit(130);
+        }
+    });
+
+    let discovered = discovery::Discovered::default();
+    let connection = Arc::new(zenoh_sub::Connection::default());
+    let inspected = inspect::Inspected::default();
+    // Created up front so the decoders can request keyframes through it.
+    let subscriber = playback_file.is_none().then(|| {
+        SubscriberBuilder::new(session)
+            .discovered(discovered.clone())
+            .connection(connection.clone())
+            .inspected(inspected.clone())
+            .schema(args.schema)
+            .keyframe_requests(args.keyframe_request, args.keyframe_request_key)
     });
 
+    // The file is paced already; only live streams shed frames.
+    let queue_policy = if playback_file.is_some() {
+        queue::DropPolicy::Block
+    } else {
+        args.queue_policy
+    };
+    let mut senders = Vec::new();
+    let mut receivers = Vec::new();
+
+    for (index, (_, topic)) in channels.iter().enumerate() {
+        // Relabelled by the GUI when the stream switches topics.
+        let name = metrics::StreamName::new(topic.clone());
+
+        // --- Channels ---
+        let (frame_tx, frame_rx) = queue::bounded(name.clone(), args.queue_len, queue_policy);
+        let (decoded_tx, decoded_rx) = mpsc::sync_channel::<DecodedFrame>(2);
+
+        // --- Decode thread per stream (auto-restarts on error) ---
+        let output_size = scaling::OutputSize::new(args.scaling, args.max_size);
+        let mut decoder = DecoderBuilder::new(name.clone())
+            .recording(recording.clone())
+            .backend(args.backend)
+            .decoder(args.decoder.clone())
+            .output_size(output_size.clone())
+            .timestamp_gaps(!args.no_timestamp_gaps);
+        if let Some(subscriber) = &subscriber {
+            decoder = decoder.keyframe_requests(subscriber.keyframe_requester(index));
+        }
+        let decoder = decoder.spawn(frame_rx, decoded_tx).unwrap_or_else(|e| {
+            eprintln!("Failed to start decoder: {e:#}");
+            std::process::exit(1);
+        });
+
+        receivers.push((
+            name,
+            decoded_rx,
+            decoder,
+            frame_tx.drops(),
+            frame_tx.decode_errors(),
+            output_size,
+        ));
+        senders.push(frame_tx);
+    }
+
+    let (zenoh_cmd, playback) = match (playback_file, subscriber) {
+        // --- MCAP player (background thread, paced by log time) ---
+        (Some(file), _) => {
+            let outputs: HashMap<_, _> = channels.iter().map(|(id, _)| *id).zip(senders).collect();
+            (None, Some(playback::spawn(file, outputs, args.schema)))
+        }
+        // --- Zenoh subscribers (background thread, one shared session) ---
+        (None, Some(mut subscriber)) => {
+            for ((_, topic), frame_tx) in channels.into_iter().zip(senders) {
+                subscriber = subscriber.topic(topic, frame_tx);
+            }
+            (Some(subscriber.spawn()), None)
+        }
+        (None, None) => unreachable!("the subscriber is built unless a file is played"),
+    };
+
+    // --- Headless: metrics as JSON lines instead of a window ---
+    if args.headless {
+        // Keep the decoders alive until exit.
+        let (streams, _decoders): (Vec<_>, Vec<_>) = receivers
+            .into_iter()
+            .map(|(name, decoded_rx, decoder, drops, decode_errors, _)| {
+                ((name.get(), decoded_rx, drops, decode_errors), decoder)
+            })
+            .unzip();
+        let interval = Duration::from_secs_f64(args.stats_interval.max(0.01));
+        let duration = args.exit_after.map(|s| Duration::from_secs_f64(s.max(0.0)));
+        let ok = headless::run(streams, interval, duration);
+        recording.shutdown();
+        std::process::exit(if ok { 0 } else { 1 });
+    }
+
+    let streams = receivers
+        .into_iter()
+        .map(
+            |(name, decoded_rx, decoder, drops, decode_errors, output_size)| {
+                gui::StreamView::new(decoded_rx, decoder, name, drops, decode_errors, output_size)
+            },
+        )
+        .collect();
+
     // --- Run the eframe/egui application ---
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default().with_inner_size([960.0, 600.0]),
@@ -43,11 +201,45 @@ fn main() -> eframe::Result {
         "Zenoh Video Player",
         options,
         Box::new(move |_cc| {
-            Ok(Box::new(gui::VideoPlayerApp::new(
-                rgba_rx,
-                pipeline_for_app,
-                topic_display,
-            )))
+            let live = playback.is_none();
+            let mut app = gui::VideoPlayerApp::new(
+                streams,
+                zenoh_cmd,
+                live.then_some(connection),
+                recording,
+                discovered,
+                args.discover,
+                playback,
+            );
+            if live {
+                app = app.inspector(inspected);
+            }
+            Ok(Box::new(app))
         }),
     )
 }
+
+/// Print the decoders of every codec in the order `--decoder auto` tries
+/// them, with whether they are installed and can start on this machine.
+fn print_decoders() -> anyhow::Result<()> {
+    for codec in Codec::VIDEO {
+        println!("{codec}:");
+        for probe in decoder::probe_decoders(codec)? {
+            let status = match (probe.installed, probe.usable) {
+                (false, _) => "not installed",
+                (true, false) => "unusable",
+                (true, true) => "usable",
+            };
+            let kind = if probe.hardware {
+                "hardware"
+            } else {
+                "software"
+            };
+            println!(
+                "  {:<16} {kind:<9} {status:<14} {}",
+                probe.name, probe.description
+            );
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,827 @@
+//! Minimal MCAP reader/writer for raw Zenoh samples.
+//! <https://mcap.dev/spec>
+//!
+//! The writer produces an unchunked, unindexed file (valid MCAP, readable by
+//! Foxglove). The reader understands unchunked files and chunked files with
+//! no, `zstd` or `lz4` compression, ignoring index and summary records, and
+//! indexes the messages without loading their data.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use anyhow::{Context, bail};
+
+use crate::cdr::Schema;
+
+const MAGIC: &[u8; 8] = b"\x89MCAP0\r\n";
+
+const OP_HEADER: u8 = 0x01;
+const OP_FOOTER: u8 = 0x02;
+const OP_SCHEMA: u8 = 0x03;
+const OP_CHANNEL: u8 = 0x04;
+const OP_MESSAGE: u8 = 0x05;
+const OP_CHUNK: u8 = 0x06;
+const OP_DATA_END: u8 = 0x0f;
+
+/// Opcode and content length in front of every record.
+const RECORD_HEADER: u64 = 9;
+
+/// Largest chunk the reader decompresses. Writers default to chunks of a few
+/// MiB; a larger `uncompressed_size` is most likely corrupt.
+const MAX_CHUNK_SIZE: u64 = 512 << 20;
+
+/// OMG IDL definition of foxglove.CompressedVideo, matching `cdr::CompressedVideo`.
+const COMPRESSED_VIDEO_IDL: &str = "\
+module foxglove {
+struct Time {
+  uint32 sec;
+  uint32 nsec;
+};
+// A single frame of a compressed video bitstream
+struct CompressedVideo {
+  Time timestamp;
+  string frame_id;
+  sequence<uint8> data;
+  string format;
+};
+};
+";
+
+/// OMG IDL definition of foxglove.CompressedImage, matching `cdr::CompressedImage`.
+const COMPRESSED_IMAGE_IDL: &str = "\
+module foxglove {
+struct Time {
+  uint32 sec;
+  uint32 nsec;
+};
+// A compressed image
+struct CompressedImage {
+  Time timestamp;
+  string frame_id;
+  sequence<uint8> data;
+  string format;
+};
+};
+";
+
+/// OMG IDL definition of foxglove.RawImage, matching `cdr::RawImage`.
+const RAW_IMAGE_IDL: &str = "\
+module foxglove {
+struct Time {
+  uint32 sec;
+  uint32 nsec;
+};
+// A raw image
+struct RawImage {
+  Time timestamp;
+  string frame_id;
+  uint32 width;
+  uint32 height;
+  string encoding;
+  uint32 step;
+  sequence<uint8> data;
+};
+};
+";
+
+/// Definitions `sensor_msgs` messages depend on, in ros2msg form.
+macro_rules! ros_header {
+    () => {
+        "\
+================================================================================
+MSG: std_msgs/Header
+builtin_interfaces/Time stamp
+string frame_id
+================================================================================
+MSG: builtin_interfaces/Time
+int32 sec
+uint32 nanosec
+"
+    };
+}
+
+/// ros2msg definition of sensor_msgs/msg/CompressedImage, matching
+/// `cdr::RosCompressedImage`.
+const ROS_COMPRESSED_IMAGE_MSG: &str = concat!(
+    "\
+std_msgs/Header header
+string format
+uint8[] data
+",
+    ros_header!()
+);
+
+/// ros2msg definition of sensor_msgs/msg/Image, matching `cdr::RosImage`.
+const ROS_IMAGE_MSG: &str = concat!(
+    "\
+std_msgs/Header header
+uint32 height
+uint32 width
+string encoding
+uint8 is_bigendian
+uint32 step
+uint8[] data
+",
+    ros_header!()
+);
+
+/// Name, encoding and definition of the MCAP schema of each message layout.
+const SCHEMAS: [(Schema, &str, &str, &str); 5] = [
+    (
+        Schema::CompressedVideo,
+        "foxglove::CompressedVideo",
+        "omgidl",
+        COMPRESSED_VIDEO_IDL,
+    ),
+    (
+        Schema::CompressedImage,
+        "foxglove::CompressedImage",
+        "omgidl",
+        COMPRESSED_IMAGE_IDL,
+    ),
+    (
+        Schema::RawImage,
+        "foxglove::RawImage",
+        "omgidl",
+        RAW_IMAGE_IDL,
+    ),
+    (
+        Schema::RosCompressedImage,
+        "sensor_msgs/msg/CompressedImage",
+        "ros2msg",
+        ROS_COMPRESSED_IMAGE_MSG,
+    ),
+    (
+        Schema::RosImage,
+        "sensor_msgs/msg/Image",
+        "ros2msg",
+        ROS_IMAGE_MSG,
+    ),
+];
+
+/// The message layout of channels with the MCAP schema `name`, if it is
+/// one the player writes.
+pub fn schema_by_name(name: &str) -> Option<Schema> {
+    SCHEMAS
+        .iter()
+        .find(|(_, schema_name, ..)| *schema_name == name)
+        .map(|&(schema, ..)| schema)
+}
+
+/// Writes every sample as a `cdr` message on one channel per Zenoh key,
+/// with the MCAP schema of the key's message layout.
+pub struct Writer<W: Write> {
+    out: W,
+    /// Id of each schema written so far.
+    schemas: HashMap<Schema, u16>,
+    /// Channel id and next sequence number per key.
+    channels: HashMap<String, (u16, u32)>,
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(mut out: W) -> io::Result<Self> {
+        out.write_all(MAGIC)?;
+
+        let mut header = Vec::new();
+        put_str(&mut header, "");
+        put_str(
+            &mut header,
+            concat!("video-zenoh-player ", env!("CARGO_PKG_VERSION")),
+        );
+        put_record(&mut out, OP_HEADER, &header)?;
+
+        Ok(Self {
+            out,
+            schemas: HashMap::new(),
+            channels: HashMap::new(),
+        })
+    }
+
+    /// Append one message of layout `schema`; times are nanoseconds since
+    /// the UNIX epoch. A topic keeps the schema of its first message. Fails
+    /// for layouts that are no CDR message (`Auto`, bare Annex B).
+    pub fn write(
+        &mut self,
+        topic: &str,
+        schema: Schema,
+        log_time: u64,
+        publish_time: u64,
+        data: &[u8],
+    ) -> io::Result<()> {
+        let (channel_id, sequence) = match self.channels.get_mut(topic) {
+            Some((id, seq)) => {
+                *seq = seq.wrapping_add(1);
+                (*id, *seq)
+            }
+            None => {
+                let schema_id = self.schema_id(schema)?;
+                let id = self.channels.len() as u16 + 1;
+                let mut channel = Vec::new();
+                channel.extend_from_slice(&id.to_le_bytes());
+                channel.extend_from_slice(&schema_id.to_le_bytes());
+                put_str(&mut channel, topic);
+                put_str(&mut channel, "cdr");
+                channel.extend_from_slice(&0u32.to_le_bytes()); // empty metadata
+                put_record(&mut self.out, OP_CHANNEL, &channel)?;
+                self.channels.insert(topic.to_string(), (id, 0));
+                (id, 0)
+            }
+        };
+
+        let mut message = Vec::with_capacity(22 + data.len());
+        message.extend_from_slice(&channel_id.to_le_bytes());
+        message.extend_from_slice(&sequence.to_le_bytes());
+        message.extend_from_slice(&log_time.to_le_bytes());
+        message.extend_from_slice(&publish_time.to_le_bytes());
+        message.extend_from_slice(data);
+        put_record(&mut self.out, OP_MESSAGE, &message)
+    }
+
+    /// Id of the MCAP schema of `schema`, writing the schema record first
+    /// if it is new.
+    fn schema_id(&mut self, schema: Schema) -> io::Result<u16> {
+        if let Some(&id) = self.schemas.get(&schema) {
+            return Ok(id);
+        }
+        let Some(&(_, name, encoding, definition)) =
+            SCHEMAS.iter().find(|(known, ..)| *known == schema)
+        else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{schema} messages have no MCAP schema"),
+            ));
+        };
+        let id = self.schemas.len() as u16 + 1;
+        let mut record = Vec::new();
+        record.extend_from_slice(&id.to_le_bytes());
+        put_str(&mut record, name);
+        put_str(&mut record, encoding);
+        put_str(&mut record, definition);
+        put_record(&mut self.out, OP_SCHEMA, &record)?;
+        self.schemas.insert(schema, id);
+        Ok(id)
+    }
+
+    /// Write the data-end and footer records; the file is complete after this.
+    pub fn finish(mut self) -> io::Result<W> {
+        put_record(&mut self.out, OP_DATA_END, &0u32.to_le_bytes())?;
+        // No summary section.
+        put_record(&mut self.out, OP_FOOTER, &[0; 20])?;
+        self.out.write_all(MAGIC)?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+fn put_str(buf: &mut Vec<u8>, s: &str) {
+    buf.extend_from_slice(&(s.len() as u32).to_le_bytes());
+    buf.extend_from_slice(s.as_bytes());
+}
+
+fn put_record(out: &mut impl Write, op: u8, content: &[u8]) -> io::Result<()> {
+    out.write_all(&[op])?;
+    out.write_all(&(content.len() as u64).to_le_bytes())?;
+    out.write_all(content)
+}
+
+/// A channel declared in an MCAP file.
+#[derive(Debug, Clone)]
+pub struct Channel {
+    pub topic: String,
+    pub message_encoding: String,
+    pub schema_name: Option<String>,
+}
+
+/// A message of an MCAP file. Its data stays on disk until
+/// [`Payloads::read`] loads it.
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub channel_id: u16,
+    pub log_time: u64,
+    pub publish_time: u64,
+    location: Location,
+}
+
+#[cfg(test)]
+impl Message {
+    /// A message without data, for tests that only need the index.
+    pub(crate) fn at(channel_id: u16, log_time: u64) -> Self {
+        Self {
+            channel_id,
+            log_time,
+            publish_time: log_time,
+            location: Location::File { offset: 0, len: 0 },
+        }
+    }
+}
+
+/// Where the data of a [`Message`] is stored.
+#[derive(Debug, Clone, Copy)]
+enum Location {
+    /// `len` bytes at `offset` in the file.
+    File { offset: u64, len: u64 },
+    /// `len` bytes at `offset` in the decompressed records of a chunk.
+    Chunk { chunk: usize, offset: u64, len: u64 },
+}
+
+/// A compressed chunk of records, decompressed again to read a payload.
+#[derive(Debug, Clone)]
+struct Chunk {
+    /// Offset and length of the compressed records in the file.
+    offset: u64,
+    len: u64,
+    compression: String,
+    uncompressed_size: u64,
+}
+
+/// Channels and messages of an MCAP file, messages sorted by log time.
+#[derive(Debug)]
+pub struct McapFile {
+    pub channels: BTreeMap<u16, Channel>,
+    pub messages: Vec<Message>,
+    /// Reads the message data from the file.
+    pub payloads: Payloads,
+}
+
+impl McapFile {
+    /// Index the channels and messages of the file at `path`. Records are
+    /// read one at a time and message data is left in the file, so memory
+    /// holds a small entry per message, not the recording.
+    ///
+    /// A file that ends in the middle of a record (the recorder was killed)
+    /// is read up to the last complete one, with a warning.
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        let input = File::open(path).with_context(|| format!("reading {}", path.display()))?;
+        let len = input.metadata()?.len();
+        let mut input = BufReader::new(input);
+        let mut magic = [0; MAGIC.len()];
+        if input.read_exact(&mut magic).is_err() || &magic != MAGIC {
+            bail!("{} is not an MCAP file", path.display());
+        }
+
+        let mut index = Index::default();
+        let mut offset = MAGIC.len() as u64;
+        loop {
+            let mut header = [0; RECORD_HEADER as usize];
+            let content_len = match input.read_exact(&mut header) {
+                Ok(()) => u64::from_le_bytes(header[1..].try_into()?),
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => u64::MAX,
+                Err(e) => return Err(e.into()),
+            };
+            let op = header[0];
+            if content_len > len.saturating_sub(offset + RECORD_HEADER) {
+                eprintln!(
+                    "{} is truncated after {offset} bytes, playing the {} messages before",
+                    path.display(),
+                    index.messages.len()
+                );
+                break;
+            }
+            offset += RECORD_HEADER;
+            if op == OP_FOOTER {
+                break;
+            }
+            let mut content = vec![0; content_len as usize];
+            input.read_exact(&mut content)?;
+            index.parse_record(op, &content, Base::File(offset))?;
+            offset += content_len;
+        }
+
+        let Index {
+            channels,
+            mut messages,
+            chunks,
+            ..
+        } = index;
+        messages.sort_by_key(|m| m.log_time);
+        Ok(Self {
+            channels,
+            messages,
+            payloads: Payloads {
+                input: input.into_inner(),
+                chunks,
+                cached: None,
+            },
+        })
+    }
+}
+
+/// Reads the data of the messages of an [`McapFile`] from the file.
+#[derive(Debug)]
+pub struct Payloads {
+    input: File,
+    chunks: Vec<Chunk>,
+    /// The chunk decompressed last, as messages are mostly read in order.
+    cached: Option<(usize, Vec<u8>)>,
+}
+
+impl Payloads {
+    /// The data of `message`.
+    pub fn read(&mut self, message: &Message) -> anyhow::Result<Vec<u8>> {
+        match message.location {
+            Location::File { offset, len } => read_at(&mut self.input, offset, len),
+            Location::Chunk { chunk, offset, len } => {
+                let records = match &mut self.cached {
+                    Some((cached, records)) if *cached == chunk => records,
+                    cached => {
+                        let Chunk {
+                            offset,
+                            len,
+                            compression,
+                            uncompressed_size,
+                        } = &self.chunks[chunk];
+                        let compressed = read_at(&mut self.input, *offset, *len)?;
+                        let records = decompress(compression, &compressed, *uncompressed_size)?;
+                        &mut cached.insert((chunk, records)).1
+                    }
+                };
+                let range = offset as usize..(offset + len) as usize;
+                records
+                    .get(range)
+                    .map(<[u8]>::to_vec)
+                    .context("message outside its chunk")
+            }
+        }
+    }
+}
+
+fn read_at(input: &mut File, offset: u64, len: u64) -> anyhow::Result<Vec<u8>> {
+    input.seek(SeekFrom::Start(offset))?;
+    let mut data = vec![0; len as usize];
+    input.read_exact(&mut data)?;
+    Ok(data)
+}
+
+/// The records of a chunk, `uncompressed_size` bytes once decompressed.
+fn decompress(
+    compression: &str,
+    compressed: &[u8],
+    uncompressed_size: u64,
+) -> anyhow::Result<Vec<u8>> {
+    if uncompressed_size > MAX_CHUNK_SIZE {
+        bail!("chunk of {uncompressed_size} bytes exceeds the {MAX_CHUNK_SIZE} byte limit");
+    }
+    Ok(match compression {
+        "" => compressed.to_vec(),
+        "zstd" => zstd::bulk::decompress(compressed, uncompressed_size as usize)?,
+        "lz4" => {
+            let mut out = Vec::with_capacity(uncompressed_size as usize);
+            lz4_flex::frame::FrameDecoder::new(compressed)
+                .take(uncompressed_size)
+                .read_to_end(&mut out)?;
+            out
+        }
+        other => bail!("unsupported chunk compression '{other}'"),
+    })
+}
+
+/// Where the records being parsed start.
+#[derive(Debug, Clone, Copy)]
+enum Base {
+    /// At this offset in the file.
+    File(u64),
+    /// At this offset in the decompressed records of a chunk.
+    Chunk(usize, u64),
+}
+
+impl Base {
+    fn location(self, offset: usize, len: usize) -> Location {
+        let (offset, len) = (offset as u64, len as u64);
+        match self {
+            Self::File(base) => Location::File {
+                offset: base + offset,
+                len,
+            },
+            Self::Chunk(chunk, base) => Location::Chunk {
+                chunk,
+                offset: base + offset,
+                len,
+            },
+        }
+    }
+
+    fn advance(self, n: u64) -> Self {
+        match self {
+            Self::File(base) => Self::File(base + n),
+            Self::Chunk(chunk, base) => Self::Chunk(chunk, base + n),
+        }
+    }
+}
+
+/// What [`McapFile::read`] gathers from the records.
+#[derive(Default)]
+struct Index {
+    channels: BTreeMap<u16, Channel>,
+    messages: Vec<Message>,
+    chunks: Vec<Chunk>,
+    schemas: HashMap<u16, String>,
+}
+
+impl Index {
+    /// Parse the records of a chunk, stored at `base`, up to a footer.
+    fn parse_records(&mut self, mut records: &[u8], mut base: Base) -> anyhow::Result<()> {
+        while !records.is_empty() {
+            let mut c = Cursor(records);
+            let op = c.take(1).context("truncated MCAP record")?[0];
+            let content = c.bytes_u64().context("truncated MCAP record")?;
+            if op == OP_FOOTER {
+                break;
+            }
+            base = base.advance(RECORD_HEADER);
+            self.parse_record(op, content, base)?;
+            base = base.advance(content.len() as u64);
+            records = c.0;
+        }
+        Ok(())
+    }
+
+    /// Parse one record whose `content` is stored at `base`.
+    fn parse_record(&mut self, op: u8, content: &[u8], base: Base) -> anyhow::Result<()> {
+        let mut c = Cursor(content);
+        match op {
+            OP_SCHEMA => {
+                let id = c.u16()?;
+                self.schemas.insert(id, c.string()?);
+            }
+            OP_CHANNEL => {
+                let id = c.u16()?;
+                let schema_id = c.u16()?;
+                let topic = c.string()?;
+                let message_encoding = c.string()?;
+                self.channels.insert(
+                    id,
+                    Channel {
+                        topic,
+                        message_encoding,
+                        schema_name: self.schemas.get(&schema_id).cloned(),
+                    },
+                );
+            }
+            OP_MESSAGE => {
+                let channel_id = c.u16()?;
+                let _sequence = c.u32()?;
+                let log_time = c.u64()?;
+                let publish_time = c.u64()?;
+                self.messages.push(Message {
+                    channel_id,
+                    log_time,
+                    publish_time,
+                    location: base.location(content.len() - c.0.len(), c.0.len()),
+                });
+            }
+            OP_CHUNK => {
+                let Base::File(offset) = base else {
+                    bail!("chunk inside a chunk");
+                };
+                c.u64()?; // message_start_time
+                c.u64()?; // message_end_time
+                let uncompressed_size = c.u64()?;
+                c.u32()?; // uncompressed_crc
+                let compression = c.string()?;
+                let compressed = c.bytes_u64()?;
+                let records_at = offset + (content.len() - compressed.len()) as u64;
+                if compression.is_empty() {
+                    // Payloads are read straight from the file.
+                    return self.parse_records(compressed, Base::File(records_at));
+                }
+                let records = decompress(&compression, compressed, uncompressed_size)?;
+                let chunk = self.chunks.len();
+                self.chunks.push(Chunk {
+                    offset: records_at,
+                    len: compressed.len() as u64,
+                    compression,
+                    uncompressed_size,
+                });
+                self.parse_records(&records, Base::Chunk(chunk, 0))?;
+            }
+            _ => {} // header, indexes, summary, attachments, ...
+        }
+        Ok(())
+    }
+}
+
+/// Little-endian reader over a byte slice.
+struct Cursor<'a>(&'a [u8]);
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, n: usize) -> anyhow::Result<&'a [u8]> {
+        if self.0.len() < n {
+            bail!("truncated MCAP record");
+        }
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn u16(&mut self) -> anyhow::Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into()?))
+    }
+
+    fn u32(&mut self) -> anyhow::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn u64(&mut self) -> anyhow::Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+
+    fn string(&mut self) -> anyhow::Result<String> {
+        let len = self.u32()? as usize;
+        Ok(String::from_utf8_lossy(self.take(len)?).into_owned())
+    }
+
+    fn bytes_u64(&mut self) -> anyhow::Result<&'a [u8]> {
+        let len = self.u64()? as usize;
+        self.take(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Write `bytes` to a file of its own and read it back. The open file
+    /// still reads payloads once removed.
+    fn read(name: &str, bytes: &[u8]) -> anyhow::Result<McapFile> {
+        let path = std::env::temp_dir().join(format!("{name}-{}.mcap", std::process::id()));
+        std::fs::write(&path, bytes).unwrap();
+        let file = McapFile::read(&path);
+        std::fs::remove_file(&path).unwrap();
+        file
+    }
+
+    /// (channel, log time, publish time, data) of every message in order.
+    fn messages(file: &mut McapFile) -> Vec<(u16, u64, u64, Vec<u8>)> {
+        file.messages
+            .iter()
+            .map(|m| {
+                let data = file.payloads.read(m).unwrap();
+                (m.channel_id, m.log_time, m.publish_time, data)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reads_what_the_writer_wrote() {
+        let mut writer = Writer::new(Vec::new()).unwrap();
+        writer
+            .write("video/front", Schema::CompressedVideo, 30, 10, b"first")
+            .unwrap();
+        writer
+            .write("camera/rear", Schema::RosCompressedImage, 20, 20, b"second")
+            .unwrap();
+        writer
+            .write("video/front", Schema::CompressedVideo, 10, 30, b"third")
+            .unwrap();
+        let mut file = read("round-trip", &writer.finish().unwrap()).unwrap();
+
+        let channels: Vec<_> = file
+            .channels
+            .values()
+            .map(|c| {
+                assert_eq!(c.message_encoding, "cdr");
+                (c.topic.as_str(), c.schema_name.as_deref())
+            })
+            .collect();
+        assert_eq!(
+            channels,
+            [
+                ("video/front", Some("foxglove::CompressedVideo")),
+                ("camera/rear", Some("sensor_msgs/msg/CompressedImage")),
+            ]
+        );
+        assert_eq!(
+            messages(&mut file),
+            [
+                (1, 10, 30, b"third".to_vec()),
+                (2, 20, 20, b"second".to_vec()),
+                (1, 30, 10, b"first".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn writes_a_schema_per_layout() {
+        let mut writer = Writer::new(Vec::new()).unwrap();
+        for (topic, &(schema, ..)) in ["a", "b", "c", "d", "e"].iter().zip(&SCHEMAS) {
+            writer.write(topic, schema, 1, 1, b"").unwrap();
+        }
+        // Reused by a second channel of the same layout.
+        writer.write("f", Schema::RawImage, 1, 1, b"").unwrap();
+        let error = writer.write("g", Schema::AnnexB, 1, 1, b"").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+
+        let file = read("schemas", &writer.finish().unwrap()).unwrap();
+        let schemas: Vec<_> = file
+            .channels
+            .values()
+            .map(|c| schema_by_name(c.schema_name.as_deref().unwrap()))
+            .collect();
+        assert_eq!(
+            schemas,
+            [
+                Some(Schema::CompressedVideo),
+                Some(Schema::CompressedImage),
+                Some(Schema::RawImage),
+                Some(Schema::RosCompressedImage),
+                Some(Schema::RosImage),
+                Some(Schema::RawImage),
+            ]
+        );
+    }
+
+    #[test]
+    fn plays_up_to_a_cut_record() {
+        let mut writer = Writer::new(Vec::new()).unwrap();
+        for (time, data) in [(10, &b"first"[..]), (20, b"second")] {
+            writer
+                .write("video/front", Schema::CompressedVideo, time, time, data)
+                .unwrap();
+        }
+        let complete = writer.out.len();
+        writer
+            .write("video/front", Schema::CompressedVideo, 30, 30, b"third")
+            .unwrap();
+
+        // Killed while writing the third message: no footer, half a record.
+        let cut = &writer.out[..complete + 12];
+        let mut file = read("cut-record", cut).unwrap();
+        assert_eq!(
+            messages(&mut file),
+            [
+                (1, 10, 10, b"first".to_vec()),
+                (1, 20, 20, b"second".to_vec()),
+            ]
+        );
+
+        // Cut inside a record header too.
+        let mut file = read("cut-header", &writer.out[..complete + 4]).unwrap();
+        assert_eq!(file.messages.len(), 2);
+        assert_eq!(file.payloads.read(&file.messages[1]).unwrap(), b"second");
+    }
+
+    #[test]
+    fn reads_compressed_chunks() {
+        let mut records = Writer::new(Vec::new()).unwrap();
+        records
+            .write("video/front", Schema::CompressedVideo, 1, 1, b"frame")
+            .unwrap();
+        let records = &records.out[MAGIC.len()..];
+
+        for (compression, compressed) in [
+            ("", records.to_vec()),
+            ("zstd", zstd::bulk::compress(records, 0).unwrap()),
+            ("lz4", {
+                let mut lz4 = lz4_flex::frame::FrameEncoder::new(Vec::new());
+                lz4.write_all(records).unwrap();
+                lz4.finish().unwrap()
+            }),
+        ] {
+            let file = read(
+                &format!("chunk-{compression}"),
+                &chunked(records.len() as u64, compression, &compressed),
+            );
+            let mut file = file.unwrap();
+            assert_eq!(file.channels[&1].topic, "video/front");
+            assert_eq!(messages(&mut file), [(1, 1, 1, b"frame".to_vec())]);
+        }
+    }
+
+    #[test]
+    fn rejects_oversized_chunks_and_records() {
+        let chunk = chunked(u64::MAX, "zstd", &[]);
+        let error = read("oversized-chunk", &chunk).unwrap_err();
+        assert!(error.to_string().contains("exceeds"), "{error:#}");
+
+        // A record longer than the file is where the file was cut.
+        let mut record = MAGIC.to_vec();
+        record.push(OP_MESSAGE);
+        record.extend_from_slice(&u64::MAX.to_le_bytes());
+        let file = read("oversized-record", &record).unwrap();
+        assert!(file.messages.is_empty());
+
+        // Inside a chunk, it is corrupt.
+        let mut records = vec![OP_MESSAGE];
+        records.extend_from_slice(&u64::MAX.to_le_bytes());
+        let chunk = chunked(records.len() as u64, "", &records);
+        let error = read("oversized-chunk-record", &chunk).unwrap_err();
+        assert!(error.to_string().contains("truncated"), "{error:#}");
+    }
+
+    /// An MCAP file of one chunk record.
+    fn chunked(uncompressed_size: u64, compression: &str, compressed: &[u8]) -> Vec<u8> {
+        let mut chunk = Vec::new();
+        chunk.extend_from_slice(&[0; 16]); // message start and end time
+        chunk.extend_from_slice(&uncompressed_size.to_le_bytes());
+        chunk.extend_from_slice(&0u32.to_le_bytes()); // no CRC
+        put_str(&mut chunk, compression);
+        chunk.extend_from_slice(&(compressed.len() as u64).to_le_bytes());
+        chunk.extend_from_slice(compressed);
+
+        let mut file = MAGIC.to_vec();
+        put_record(&mut file, OP_CHUNK, &chunk).unwrap();
+        file
+    }
+}
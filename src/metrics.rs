@@ -0,0 +1,195 @@
+use std::net::SocketAddr;
+use std::sync::{Arc, LazyLock, Mutex};
+
+use prometheus::{Encoder, GaugeVec, IntCounterVec, Opts, Registry, TextEncoder};
+
+use crate::latency::Latency;
+
+/// Per-topic stream health metrics, exported on `--metrics-listen`.
+///
+/// Always collected; serving them is optional.
+pub struct Metrics {
+    registry: Registry,
+    /// Zenoh samples received.
+    pub received: IntCounterVec,
+    /// Samples whose payload was not a playable video or image message, by
+    /// `error` class (see [`DecodeError::class`](crate::cdr::DecodeError::class)).
+    pub decode_failures: IntCounterVec,
+    /// Frames produced by the decoder.
+    pub decoded: IntCounterVec,
+    /// Compressed or decoded frames that never reached the display, by
+    /// `reason`.
+    pub dropped: IntCounterVec,
+    /// Pipeline rebuilds after an error.
+    pub pipeline_restarts: IntCounterVec,
+    /// Frame counter or timestamp discontinuities.
+    pub gaps: IntCounterVec,
+    /// Source timestamps that stepped backwards, repeated or jumped too far
+    /// ahead to be used for the PTS.
+    pub timestamp_discontinuities: IntCounterVec,
+    /// Keyframe requests sent to the publisher, by `reason`.
+    pub keyframe_requests: IntCounterVec,
+    pub fps: GaugeVec,
+    pub bitrate: GaugeVec,
+    /// Source timestamp → display, i.e. the total latency of the last frames.
+    pub frame_age: GaugeVec,
+}
+
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+/// The label of one stream in the metrics and logs, shared by its queue,
+/// decoder and view so that switching the stream to another topic relabels
+/// all of them.
+#[derive(Debug, Clone)]
+pub struct StreamName(Arc<Mutex<String>>);
+
+impl StreamName {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self(Arc::new(Mutex::new(name.into())))
+    }
+
+    pub fn get(&self) -> String {
+        self.0.lock().unwrap().clone()
+    }
+
+    pub fn set(&self, name: impl Into<String>) {
+        *self.0.lock().unwrap() = name.into();
+    }
+}
+
+impl From<String> for StreamName {
+    fn from(name: String) -> Self {
+        Self::new(name)
+    }
+}
+
+impl From<&str> for StreamName {
+    fn from(name: &str) -> Self {
+        Self::new(name)
+    }
+}
+
+impl std::fmt::Display for StreamName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0.lock().unwrap())
+    }
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new();
+        let counter = |name: &str, help: &str, labels: &[&str]| {
+            let counter = IntCounterVec::new(Opts::new(name, help), labels).unwrap();
+            registry.register(Box::new(counter.clone())).unwrap();
+            counter
+        };
+        let gauge = |name: &str, help: &str| {
+            let gauge = GaugeVec::new(Opts::new(name, help), &["topic"]).unwrap();
+            registry.register(Box::new(gauge.clone())).unwrap();
+            gauge
+        };
+
+        Self {
+            received: counter(
+                "video_received_samples_total",
+                "Zenoh samples received",
+                &["topic"],
+            ),
+            decode_failures: counter(
+                "video_cdr_decode_failures_total",
+                "Samples that failed to decode as a video or image message",
+                &["topic", "error"],
+            ),
+            decoded: counter(
+                "video_decoded_frames_total",
+                "Frames produced by the video decoder",
+                &["topic"],
+            ),
+            dropped: counter(
+                "video_dropped_frames_total",
+                "Frames that never reached the display",
+                &["topic", "reason"],
+            ),
+            pipeline_restarts: counter(
+                "video_pipeline_restarts_total",
+                "Decoder pipeline rebuilds after an error",
+                &["topic"],
+            ),
+            gaps: counter(
+                "video_stream_gaps_total",
+                "Discontinuities detected in the frame counter or timestamps",
+                &["topic"],
+            ),
+            timestamp_discontinuities: counter(
+                "video_timestamp_discontinuities_total",
+                "Source timestamps that went backwards, repeated or jumped too far",
+                &["topic"],
+            ),
+            keyframe_requests: counter(
+                "video_keyframe_requests_total",
+                "Keyframe requests sent to the publisher",
+                &["topic", "reason"],
+            ),
+            fps: gauge("video_fps", "Displayed frames per second"),
+            bitrate: gauge("video_bitrate_bits_per_second", "Compressed video bitrate"),
+            frame_age: gauge(
+                "video_frame_age_seconds",
+                "Age of the displayed frame since its source timestamp",
+            ),
+            registry,
+        }
+    }
+
+    /// Publish the latest FPS, bitrate (Mbps) and latency of `topic`.
+    pub fn set_stream(&self, topic: &str, fps: f32, mbps: f32, latency: Option<Latency>) {
+        self.fps.with_label_values(&[topic]).set(fps as f64);
+        self.bitrate
+            .with_label_values(&[topic])
+            .set(mbps as f64 * 1_000_000.0);
+        if let Some(latency) = latency {
+            self.frame_age
+                .with_label_values(&[topic])
+                .set(latency.total_ms() as f64 / 1000.0);
+        }
+    }
+
+    /// Drop the gauges of a topic that is no longer shown.
+    pub fn remove_stream(&self, topic: &str) {
+        for gauge in [&self.fps, &self.bitrate, &self.frame_age] {
+            let _ = gauge.remove_label_values(&[topic]);
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        let _ = TextEncoder::new().encode(&self.registry.gather(), &mut buf);
+        buf
+    }
+}
+
+/// Serve the metrics in the Prometheus text format on `addr` from a
+/// background thread.
+pub fn serve(addr: SocketAddr) -> anyhow::Result<()> {
+    let server = tiny_http::Server::http(addr)
+        .map_err(|e| anyhow::anyhow!("Cannot listen on {addr}: {e}"))?;
+    println!("Metrics on http://{addr}/metrics");
+
+    std::thread::spawn(move || {
+        let content_type = tiny_http::Header::from_bytes(
+            &b"Content-Type"[..],
+            TextEncoder::new().format_type().as_bytes(),
+        )
+        .expect("valid header");
+        for request in server.incoming_requests() {
+            let response = if request.url() == "/metrics" {
+                tiny_http::Response::from_data(METRICS.encode())
+                    .with_header(content_type.clone())
+                    .boxed()
+            } else {
+                tiny_http::Response::empty(404).boxed()
+            };
+            let _ = request.respond(response);
+        }
+    });
+    Ok(())
+}
@@ -0,0 +1,773 @@
+use crate::codec::Codec;
+
+/// Iterator over the NAL units of an Annex B byte stream.
+///
+/// Yields each NAL unit without its start code (`00 00 01` or
+/// `00 00 00 01`) and without trailing zero bytes.
+pub struct AnnexB<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+/// Split an Annex B byte stream into NAL units.
+pub fn split_annex_b(data: &[u8]) -> AnnexB<'_> {
+    let pos = find_start_code(data, 0).map_or(data.len(), |(_, end)| end);
+    AnnexB { data, pos }
+}
+
+impl<'a> Iterator for AnnexB<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        while self.pos < self.data.len() {
+            let start = self.pos;
+            let (end, next) =
+                find_start_code(self.data, start).unwrap_or((self.data.len(), self.data.len()));
+            self.pos = next;
+
+            let mut nal = &self.data[start..end];
+            while let [rest @ .., 0] = nal {
+                nal = rest;
+            }
+            if !nal.is_empty() {
+                return Some(nal);
+            }
+        }
+        None
+    }
+}
+
+/// Find the next `00 00 01` at or after `from`.
+/// Returns (start of the start code, first byte after it).
+fn find_start_code(data: &[u8], from: usize) -> Option<(usize, usize)> {
+    data.get(from..)?
+        .windows(3)
+        .position(|w| w == [0, 0, 1])
+        .map(|i| (from + i, from + i + 3))
+}
+
+/// NAL unit type from the first header byte(s).
+pub fn nal_type(codec: Codec, nal: &[u8]) -> Option<u8> {
+    let header = *nal.first()?;
+    match codec {
+        Codec::H264 => Some(header & 0x1f),
+        Codec::H265 => Some((header >> 1) & 0x3f),
+        _ => None,
+    }
+}
+
+/// Whether `nal_type` is a sequence parameter set.
+pub fn is_sps(codec: Codec, nal_type: u8) -> bool {
+    match codec {
+        Codec::H264 => nal_type == 7,
+        Codec::H265 => nal_type == 33,
+        _ => false,
+    }
+}
+
+/// Short name of a NAL unit type, e.g. `IDR` or `SPS`.
+pub fn nal_type_name(codec: Codec, nal_type: u8) -> &'static str {
+    match (codec, nal_type) {
+        (Codec::H264, 1) => "non-IDR",
+        (Codec::H264, 2..=4) => "partition",
+        (Codec::H264, 5) => "IDR",
+        (Codec::H264, 6) => "SEI",
+        (Codec::H264, 7) => "SPS",
+        (Codec::H264, 8) => "PPS",
+        (Codec::H264, 9) => "AUD",
+        (Codec::H264, 10) => "end of seq",
+        (Codec::H264, 11) => "end of stream",
+        (Codec::H264, 12) => "filler",
+        (Codec::H265, 0 | 1) => "TRAIL",
+        (Codec::H265, 2 | 3) => "TSA",
+        (Codec::H265, 4 | 5) => "STSA",
+        (Codec::H265, 6 | 7) => "RADL",
+        (Codec::H265, 8 | 9) => "RASL",
+        (Codec::H265, 16..=18) => "BLA",
+        (Codec::H265, 19 | 20) => "IDR",
+        (Codec::H265, 21) => "CRA",
+        (Codec::H265, 32) => "VPS",
+        (Codec::H265, 33) => "SPS",
+        (Codec::H265, 34) => "PPS",
+        (Codec::H265, 35) => "AUD",
+        (Codec::H265, 36) => "end of seq",
+        (Codec::H265, 37) => "end of stream",
+        (Codec::H265, 38) => "filler",
+        (Codec::H265, 39 | 40) => "SEI",
+        _ => "other",
+    }
+}
+
+/// Coding type of a slice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SliceType {
+    I,
+    P,
+    B,
+}
+
+/// Slice type of a coded slice NAL unit (header included).
+///
+/// H.265 slice headers can only be read without the PPS for the first slice
+/// of a picture, and need the PPS's `num_extra_slice_header_bits`; other
+/// slices give `None`.
+pub fn slice_type(codec: Codec, nal: &[u8], extra_slice_header_bits: u32) -> Option<SliceType> {
+    let t = nal_type(codec, nal)?;
+    match codec {
+        Codec::H264 if matches!(t, 1 | 5) => {
+            // The fields needed are in the first few bytes.
+            let rbsp = unescape(nal.get(1..nal.len().min(17))?);
+            let mut r = BitReader::new(&rbsp);
+            r.ue()?; // first_mb_in_slice
+            match r.ue()? % 5 {
+                0 | 3 => Some(SliceType::P),
+                1 => Some(SliceType::B),
+                _ => Some(SliceType::I),
+            }
+        }
+        Codec::H265 if t <= 21 => {
+            let rbsp = unescape(nal.get(2..nal.len().min(18))?);
+            let mut r = BitReader::new(&rbsp);
+            if !r.flag()? {
+                return None; // first_slice_segment_in_pic_flag
+            }
+            if (16..=23).contains(&t) {
+                r.skip(1)?; // no_output_of_prior_pics_flag
+            }
+            r.ue()?; // slice_pic_parameter_set_id
+            r.skip(extra_slice_header_bits as usize)?; // slice_reserved_flag
+            match r.ue()? {
+                0 => Some(SliceType::B),
+                1 => Some(SliceType::P),
+                2 => Some(SliceType::I),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// `num_extra_slice_header_bits` of an H.265 PPS NAL unit (header included).
+pub fn h265_extra_slice_header_bits(nal: &[u8]) -> Option<u32> {
+    let rbsp = unescape(nal.get(2..nal.len().min(18))?);
+    let mut r = BitReader::new(&rbsp);
+    r.ue()?; // pps_pic_parameter_set_id
+    r.ue()?; // pps_seq_parameter_set_id
+    r.skip(2)?; // dependent_slice_segments_enabled_flag, output_flag_present_flag
+    r.bits(3)
+}
+
+/// Whether `nal_type` is a parameter set (H.265 VPS, SPS or PPS).
+pub fn is_parameter_set(codec: Codec, nal_type: u8) -> bool {
+    match codec {
+        Codec::H264 => matches!(nal_type, 7 | 8),
+        Codec::H265 => matches!(nal_type, 32..=34),
+        _ => false,
+    }
+}
+
+/// Whether an access unit can be decoded without earlier frames.
+///
+/// H.264: contains an IDR slice. H.265: contains an IRAP picture
+/// (BLA/IDR/CRA). VP9: uncompressed header says KEY_FRAME. AV1: carries a
+/// sequence header OBU, which encoders emit with every key frame. Still
+/// images always are.
+pub fn is_keyframe(codec: Codec, data: &[u8]) -> bool {
+    match codec {
+        Codec::H264 | Codec::H265 => split_annex_b(data).any(|nal| {
+            nal_type(codec, nal).is_some_and(|t| match codec {
+                Codec::H264 => t == 5,
+                _ => (16..=21).contains(&t),
+            })
+        }),
+        Codec::Vp9 => vp9_is_keyframe(data).unwrap_or(false),
+        Codec::Av1 => av1_has_sequence_header(data),
+        Codec::Jpeg | Codec::Png | Codec::Raw => true,
+    }
+}
+
+fn vp9_is_keyframe(data: &[u8]) -> Option<bool> {
+    let mut r = BitReader::new(data);
+    if r.bits(2)? != 0b10 {
+        return None; // frame_marker
+    }
+    let profile = r.bits(1)? | (r.bits(1)? << 1);
+    if profile == 3 {
+        r.skip(1)?; // reserved_zero
+    }
+    if r.flag()? {
+        return Some(false); // show_existing_frame
+    }
+    Some(!r.flag()?) // frame_type: 0 = KEY_FRAME
+}
+
+fn av1_has_sequence_header(data: &[u8]) -> bool {
+    let mut rest = data;
+    while let [header, tail @ ..] = rest {
+        if (header >> 3) & 0x0f == 1 {
+            return true; // OBU_SEQUENCE_HEADER
+        }
+        let has_extension = header & 0x04 != 0;
+        let has_size = header & 0x02 != 0;
+        let tail = if has_extension {
+            tail.get(1..)
+        } else {
+            Some(tail)
+        };
+        let Some(tail) = tail.filter(|_| has_size) else {
+            break;
+        };
+        let Some((size, len)) = leb128(tail) else {
+            break;
+        };
+        rest = tail.get(len + size..).unwrap_or_default();
+    }
+    false
+}
+
+/// Decode an AV1 `leb128()` value, returning (value, bytes consumed).
+fn leb128(data: &[u8]) -> Option<(usize, usize)> {
+    let mut value = 0usize;
+    for (i, &byte) in data.iter().take(8).enumerate() {
+        value |= ((byte & 0x7f) as usize) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
+
+/// Fields of a sequence parameter set.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sps {
+    pub profile_idc: u8,
+    pub level_idc: u8,
+    pub chroma_format_idc: u32,
+    pub bit_depth_luma: u32,
+    /// Displayed width after cropping.
+    pub width: u32,
+    /// Displayed height after cropping.
+    pub height: u32,
+    pub vui: Option<Vui>,
+}
+
+/// Video usability information of an SPS: the parts that matter for
+/// display.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Vui {
+    /// Sample (pixel) aspect ratio as width:height.
+    pub sample_aspect_ratio: Option<(u32, u32)>,
+    pub full_range: Option<bool>,
+    /// `colour_primaries`, `transfer_characteristics` and
+    /// `matrix_coefficients` (ISO/IEC 23091-2 code points).
+    pub colour: Option<(u8, u8, u8)>,
+    /// Frame rate from the timing info.
+    pub frame_rate: Option<f64>,
+}
+
+/// Sample aspect ratios for `aspect_ratio_idc` 1–16.
+const SAMPLE_ASPECT_RATIOS: [(u32, u32); 16] = [
+    (1, 1),
+    (12, 11),
+    (10, 11),
+    (16, 11),
+    (40, 33),
+    (24, 11),
+    (20, 11),
+    (32, 11),
+    (80, 33),
+    (18, 11),
+    (15, 11),
+    (64, 33),
+    (160, 99),
+    (4, 3),
+    (3, 2),
+    (2, 1),
+];
+
+/// Parse `vui_parameters()` up to and including the timing info.
+fn parse_vui(codec: Codec, r: &mut BitReader) -> Option<Vui> {
+    let mut vui = Vui::default();
+    if r.flag()? {
+        // aspect_ratio_info_present_flag
+        vui.sample_aspect_ratio = match r.bits(8)? {
+            255 => Some((r.bits(16)?, r.bits(16)?)), // Extended_SAR
+            idc @ 1..=16 => Some(SAMPLE_ASPECT_RATIOS[idc as usize - 1]),
+            _ => None,
+        };
+    }
+    if r.flag()? {
+        r.skip(1)?; // overscan_appropriate_flag
+    }
+    if r.flag()? {
+        // video_signal_type_present_flag
+        r.skip(3)?; // video_format
+        vui.full_range = Some(r.flag()?);
+        if r.flag()? {
+            vui.colour = Some((r.bits(8)? as u8, r.bits(8)? as u8, r.bits(8)? as u8));
+        }
+    }
+    if r.flag()? {
+        r.ue()?; // chroma_sample_loc_type_top_field
+        r.ue()?; // chroma_sample_loc_type_bottom_field
+    }
+    // Each H.264 tick is a field, each H.265 tick a picture.
+    let ticks_per_frame = match codec {
+        Codec::H265 => {
+            r.skip(3)?; // neutral_chroma_indication, field_seq, frame_field_info_present
+            if r.flag()? {
+                // default_display_window_flag
+                for _ in 0..4 {
+                    r.ue()?;
+                }
+            }
+            1.0
+        }
+        _ => 2.0,
+    };
+    if r.flag()? {
+        // timing_info_present_flag
+        let num_units_in_tick = r.bits(32)?;
+        let time_scale = r.bits(32)?;
+        if num_units_in_tick > 0 {
+            vui.frame_rate = Some(time_scale as f64 / (ticks_per_frame * num_units_in_tick as f64));
+        }
+    }
+    Some(vui)
+}
+
+/// Parse the SPS of an H.264 or H.265 NAL unit (header included).
+pub fn parse_sps(codec: Codec, nal: &[u8]) -> Option<Sps> {
+    match codec {
+        Codec::H264 => parse_h264_sps(&unescape(nal.get(1..)?)),
+        Codec::H265 => parse_h265_sps(&unescape(nal.get(2..)?)),
+        _ => None,
+    }
+}
+
+/// Find and parse the first SPS in an Annex B access unit.
+pub fn find_sps(codec: Codec, data: &[u8]) -> Option<Sps> {
+    split_annex_b(data)
+        .filter(|nal| nal_type(codec, nal).is_some_and(|t| is_sps(codec, t)))
+        .find_map(|nal| parse_sps(codec, nal))
+}
+
+/// Remove emulation prevention bytes (`00 00 03` → `00 00`).
+fn unescape(rbsp: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(rbsp.len());
+    let mut zeros = 0;
+    for &b in rbsp {
+        if zeros >= 2 && b == 3 {
+            zeros = 0;
+            continue;
+        }
+        zeros = if b == 0 { zeros + 1 } else { 0 };
+        out.push(b);
+    }
+    out
+}
+
+fn parse_h264_sps(rbsp: &[u8]) -> Option<Sps> {
+    let mut r = BitReader::new(rbsp);
+    let profile_idc = r.bits(8)? as u8;
+    r.skip(8)?; // constraint_set flags + reserved
+    let level_idc = r.bits(8)? as u8;
+    r.ue()?; // seq_parameter_set_id
+
+    let mut chroma_format_idc = 1;
+    let mut separate_colour_plane = false;
+    let mut bit_depth_luma = 8;
+    if matches!(
+        profile_idc,
+        100 | 110 | 122 | 244 | 44 | 83 | 86 | 118 | 128 | 138 | 139 | 134 | 135
+    ) {
+        chroma_format_idc = r.ue()?;
+        if chroma_format_idc == 3 {
+            separate_colour_plane = r.flag()?;
+        }
+        bit_depth_luma = r.ue()?.checked_add(8)?;
+        r.ue()?; // bit_depth_chroma_minus8
+        r.skip(1)?; // qpprime_y_zero_transform_bypass_flag
+        if r.flag()? {
+            // seq_scaling_matrix_present_flag
+            let lists = if chroma_format_idc == 3 { 12 } else { 8 };
+            for i in 0..lists {
+                if r.flag()? {
+                    skip_scaling_list(&mut r, if i < 6 { 16 } else { 64 })?;
+                }
+            }
+        }
+    }
+
+    r.ue()?; // log2_max_frame_num_minus4
+    match r.ue()? {
+        0 => {
+            r.ue()?; // log2_max_pic_order_cnt_lsb_minus4
+        }
+        1 => {
+            r.skip(1)?; // delta_pic_order_always_zero_flag
+            r.se()?; // offset_for_non_ref_pic
+            r.se()?; // offset_for_top_to_bottom_field
+            for _ in 0..r.ue()? {
+                r.se()?; // offset_for_ref_frame
+            }
+        }
+        _ => {}
+    }
+    r.ue()?; // max_num_ref_frames
+    r.skip(1)?; // gaps_in_frame_num_value_allowed_flag
+
+    let width_mbs = r.ue()?.checked_add(1)?;
+    let height_map_units = r.ue()?.checked_add(1)?;
+    let frame_mbs_only = r.flag()?;
+    if !frame_mbs_only {
+        r.skip(1)?; // mb_adaptive_frame_field_flag
+    }
+    r.skip(1)?; // direct_8x8_inference_flag
+
+    let mut width = width_mbs.checked_mul(16)?;
+    let mut height = (2 - frame_mbs_only as u32)
+        .checked_mul(height_map_units)?
+        .checked_mul(16)?;
+    if r.flag()? {
+        // frame_cropping_flag
+        let (crop_x, crop_y) = if chroma_format_idc == 0 || separate_colour_plane {
+            (1, 2 - frame_mbs_only as u32)
+        } else {
+            let sub_width = if chroma_format_idc == 3 { 1 } else { 2 };
+            let sub_height = if chroma_format_idc == 1 { 2 } else { 1 };
+            (sub_width, sub_height * (2 - frame_mbs_only as u32))
+        };
+        let (left, right, top, bottom) = (r.ue()?, r.ue()?, r.ue()?, r.ue()?);
+        width = width.checked_sub(left.checked_add(right)?.checked_mul(crop_x)?)?;
+        height = height.checked_sub(top.checked_add(bottom)?.checked_mul(crop_y)?)?;
+    }
+    // A truncated or unusual VUI still leaves the fields above usable.
+    let vui = r.flag()?.then(|| parse_vui(Codec::H264, &mut r)).flatten();
+
+    Some(Sps {
+        profile_idc,
+        level_idc,
+        chroma_format_idc,
+        bit_depth_luma,
+        width,
+        height,
+        vui,
+    })
+}
+
+fn skip_scaling_list(r: &mut BitReader, size: usize) -> Option<()> {
+    let mut last = 8i32;
+    let mut next = 8i32;
+    for _ in 0..size {
+        if next != 0 {
+            next = (last + r.se()? + 256) % 256;
+        }
+        if next != 0 {
+            last = next;
+        }
+    }
+    Some(())
+}
+
+fn parse_h265_sps(rbsp: &[u8]) -> Option<Sps> {
+    let mut r = BitReader::new(rbsp);
+    r.skip(4)?; // sps_video_parameter_set_id
+    let max_sub_layers_minus1 = r.bits(3)? as usize;
+    r.skip(1)?; // sps_temporal_id_nesting_flag
+
+    // profile_tier_level(1, sps_max_sub_layers_minus1)
+    r.skip(3)?; // general_profile_space + general_tier_flag
+    let profile_idc = r.bits(5)? as u8;
+    r.skip(32)?; // general_profile_compatibility_flags
+    r.skip(48)?; // source flags + reserved constraint bits
+    let level_idc = r.bits(8)? as u8;
+    let mut sub_layers = Vec::with_capacity(max_sub_layers_minus1);
+    for _ in 0..max_sub_layers_minus1 {
+        sub_layers.push((r.flag()?, r.flag()?));
+    }
+    if max_sub_layers_minus1 > 0 {
+        r.skip(2 * (8 - max_sub_layers_minus1))?; // reserved_zero_2bits
+    }
+    for (profile_present, level_present) in sub_layers {
+        if profile_present {
+            r.skip(88)?;
+        }
+        if level_present {
+            r.skip(8)?;
+        }
+    }
+
+    r.ue()?; // sps_seq_parameter_set_id
+    let chroma_format_idc = r.ue()?;
+    if chroma_format_idc == 3 {
+        r.skip(1)?; // separate_colour_plane_flag
+    }
+    let mut width = r.ue()?;
+    let mut height = r.ue()?;
+    if r.flag()? {
+        // conformance_window_flag, in chroma sample units
+        let (sub_width, sub_height) = match chroma_format_idc {
+            1 => (2, 2),
+            2 => (2, 1),
+            _ => (1, 1),
+        };
+        let (left, right, top, bottom) = (r.ue()?, r.ue()?, r.ue()?, r.ue()?);
+        width = width.checked_sub(left.checked_add(right)?.checked_mul(sub_width)?)?;
+        height = height.checked_sub(top.checked_add(bottom)?.checked_mul(sub_height)?)?;
+    }
+    let bit_depth_luma = r.ue()?.checked_add(8)?;
+    let vui = h265_vui(&mut r, max_sub_layers_minus1);
+
+    Some(Sps {
+        profile_idc,
+        level_idc,
+        chroma_format_idc,
+        bit_depth_luma,
+        width,
+        height,
+        vui,
+    })
+}
+
+/// Skip the rest of an H.265 SPS after `bit_depth_luma_minus8` and parse its
+/// VUI, if present.
+fn h265_vui(r: &mut BitReader, max_sub_layers_minus1: usize) -> Option<Vui> {
+    r.ue()?; // bit_depth_chroma_minus8
+    let log2_max_poc_lsb = r.ue()? as usize + 4;
+    let first_sub_layer = if r.flag()? { 0 } else { max_sub_layers_minus1 };
+    for _ in first_sub_layer..=max_sub_layers_minus1 {
+        r.ue()?; // sps_max_dec_pic_buffering_minus1
+        r.ue()?; // sps_max_num_reorder_pics
+        r.ue()?; // sps_max_latency_increase_plus1
+    }
+    for _ in 0..6 {
+        r.ue()?; // coding / transform block sizes, transform hierarchy depths
+    }
+    if r.flag()? && r.flag()? {
+        // scaling_list_enabled_flag && sps_scaling_list_data_present_flag
+        skip_h265_scaling_list_data(r)?;
+    }
+    r.skip(2)?; // amp_enabled_flag, sample_adaptive_offset_enabled_flag
+    if r.flag()? {
+        // pcm_enabled_flag
+        r.skip(8)?; // pcm sample bit depths
+        r.ue()?; // log2_min_pcm_luma_coding_block_size_minus3
+        r.ue()?; // log2_diff_max_min_pcm_luma_coding_block_size
+        r.skip(1)?; // pcm_loop_filter_disabled_flag
+    }
+
+    let num_sets = r.ue()? as usize;
+    if num_sets > 64 {
+        return None;
+    }
+    let mut num_delta_pocs = vec![0u32; num_sets];
+    for idx in 0..num_sets {
+        num_delta_pocs[idx] = skip_st_ref_pic_set(r, idx, &num_delta_pocs)?;
+    }
+    if r.flag()? {
+        // long_term_ref_pics_present_flag
+        let count = r.ue()?;
+        if count > 32 {
+            return None;
+        }
+        for _ in 0..count {
+            r.skip(log2_max_poc_lsb + 1)?; // lt_ref_pic_poc_lsb_sps, used_by_curr_pic_lt_sps_flag
+        }
+    }
+    r.skip(2)?; // sps_temporal_mvp_enabled_flag, strong_intra_smoothing_enabled_flag
+    if r.flag()? {
+        parse_vui(Codec::H265, r)
+    } else {
+        None
+    }
+}
+
+fn skip_h265_scaling_list_data(r: &mut BitReader) -> Option<()> {
+    for size_id in 0..4 {
+        let step = if size_id == 3 { 3 } else { 1 };
+        for _ in (0..6).step_by(step) {
+            if !r.flag()? {
+                r.ue()?; // scaling_list_pred_matrix_id_delta
+                continue;
+            }
+            let coefficients = 64.min(1 << (4 + (size_id << 1)));
+            if size_id > 1 {
+                r.se()?; // scaling_list_dc_coef_minus8
+            }
+            for _ in 0..coefficients {
+                r.se()?; // scaling_list_delta_coef
+            }
+        }
+    }
+    Some(())
+}
+
+/// Skip `st_ref_pic_set(idx)` of an SPS, returning its number of delta POCs.
+/// `num_delta_pocs` holds those of the sets before it.
+fn skip_st_ref_pic_set(r: &mut BitReader, idx: usize, num_delta_pocs: &[u32]) -> Option<u32> {
+    if idx > 0 && r.flag()? {
+        // inter_ref_pic_set_prediction_flag; in an SPS it refers to idx - 1.
+        r.skip(1)?; // delta_rps_sign
+        r.ue()?; // abs_delta_rps_minus1
+        let mut count = 0;
+        for _ in 0..=num_delta_pocs[idx - 1] {
+            let used_by_curr_pic = r.flag()?;
+            if used_by_curr_pic || r.flag()? {
+                count += 1; // used_by_curr_pic_flag || use_delta_flag
+            }
+        }
+        return Some(count);
+    }
+    let negative = r.ue()?;
+    let positive = r.ue()?;
+    if negative > 16 || positive > 16 {
+        return None;
+    }
+    for _ in 0..negative + positive {
+        r.ue()?; // delta_poc_s0/s1_minus1
+        r.skip(1)?; // used_by_curr_pic_s0/s1_flag
+    }
+    Some(negative + positive)
+}
+
+/// MSB-first bit reader with Exp-Golomb support.
+struct BitReader<'a> {
+    data: &'a [u8],
+    bit: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, bit: 0 }
+    }
+
+    fn bits(&mut self, n: usize) -> Option<u32> {
+        debug_assert!(n <= 32);
+        let mut value = 0u32;
+        for _ in 0..n {
+            let byte = *self.data.get(self.bit / 8)?;
+            value = (value << 1) | ((byte >> (7 - self.bit % 8)) & 1) as u32;
+            self.bit += 1;
+        }
+        Some(value)
+    }
+
+    fn skip(&mut self, n: usize) -> Option<()> {
+        if self.bit + n > self.data.len() * 8 {
+            return None;
+        }
+        self.bit += n;
+        Some(())
+    }
+
+    fn flag(&mut self) -> Option<bool> {
+        self.bits(1).map(|b| b == 1)
+    }
+
+    /// Unsigned Exp-Golomb.
+    fn ue(&mut self) -> Option<u32> {
+        let mut zeros = 0;
+        while !self.flag()? {
+            zeros += 1;
+            if zeros > 31 {
+                return None;
+            }
+        }
+        Some(((1u64 << zeros) - 1 + self.bits(zeros)? as u64) as u32)
+    }
+
+    /// Signed Exp-Golomb.
+    fn se(&mut self) -> Option<i32> {
+        let k = self.ue()? as i64;
+        Some(if k % 2 == 1 { (k + 1) / 2 } else { -(k / 2) } as i32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes the bits of a hand-built SPS.
+    #[derive(Default)]
+    struct BitWriter {
+        bits: Vec<bool>,
+    }
+
+    impl BitWriter {
+        fn bits(&mut self, value: u64, n: usize) -> &mut Self {
+            self.bits.extend((0..n).rev().map(|i| value >> i & 1 == 1));
+            self
+        }
+
+        fn ue(&mut self, value: u32) -> &mut Self {
+            let code = value as u64 + 1;
+            let len = 64 - code.leading_zeros() as usize;
+            self.bits(0, len - 1).bits(code, len)
+        }
+
+        /// The NAL unit, with emulation prevention bytes inserted.
+        fn finish(&mut self) -> Vec<u8> {
+            self.bits(1, 1); // rbsp_stop_one_bit
+            let mut out = Vec::new();
+            let mut zeros = 0;
+            for chunk in self.bits.chunks(8) {
+                let byte = (0..8).fold(0u8, |b, i| b << 1 | chunk.get(i).is_some_and(|&x| x) as u8);
+                if zeros >= 2 && byte <= 3 {
+                    out.push(3);
+                    zeros = 0;
+                }
+                zeros = if byte == 0 { zeros + 1 } else { 0 };
+                out.push(byte);
+            }
+            out
+        }
+    }
+
+    #[test]
+    fn parses_h264_1080p_sps() {
+        // x264, High profile level 4.0, 1920x1088 cropped to 1080.
+        let nal = [
+            0x67, 0x64, 0x00, 0x28, 0xac, 0xd9, 0x40, 0x78, 0x02, 0x27, 0xe5, 0xc0, 0x44, 0x00,
+            0x00, 0x03, 0x00, 0x04, 0x00, 0x00, 0x03, 0x00, 0xc8, 0x3c, 0x60, 0xc6, 0x58,
+        ];
+        let sps = parse_sps(Codec::H264, &nal).expect("valid SPS");
+        assert_eq!((sps.profile_idc, sps.level_idc), (100, 40));
+        assert_eq!((sps.chroma_format_idc, sps.bit_depth_luma), (1, 8));
+        assert_eq!((sps.width, sps.height), (1920, 1080));
+    }
+
+    #[test]
+    fn parses_h265_1080p_sps() {
+        // x265, Main profile level 4.0, 1920x1080.
+        let nal = [
+            0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0x90, 0x00, 0x00, 0x03, 0x00,
+            0x00, 0x03, 0x00, 0x78, 0xa0, 0x03, 0xc0, 0x80, 0x10, 0xe5, 0x96, 0x66, 0x69, 0x24,
+            0xca, 0xe0, 0x10, 0x00, 0x00, 0x03, 0x00, 0x10, 0x00, 0x00, 0x03, 0x01, 0xe0, 0x80,
+        ];
+        let sps = parse_sps(Codec::H265, &nal).expect("valid SPS");
+        assert_eq!((sps.profile_idc, sps.level_idc), (1, 120));
+        assert_eq!((sps.chroma_format_idc, sps.bit_depth_luma), (1, 8));
+        assert_eq!((sps.width, sps.height), (1920, 1080));
+    }
+
+    #[test]
+    fn rejects_h264_sps_with_overflowing_size() {
+        let mut w = BitWriter::default();
+        w.bits(0x67, 8).bits(66, 8).bits(0, 8).bits(30, 8);
+        w.ue(0).ue(0).ue(2).ue(1).bits(0, 1); // id, frame_num, poc type, refs, gaps
+        w.ue(u32::MAX - 1).ue(67).bits(1, 1).bits(1, 1); // width, height, frame/8x8 flags
+        assert!(parse_sps(Codec::H264, &w.finish()).is_none());
+    }
+
+    #[test]
+    fn rejects_h265_sps_with_overflowing_window() {
+        let mut w = BitWriter::default();
+        w.bits(0x4201, 16).bits(0, 4).bits(0, 3).bits(1, 1); // vps id, sub-layers
+        w.bits(1, 8).bits(0, 32).bits(0, 48).bits(120, 8); // profile_tier_level
+        w.ue(0).ue(1).ue(1920).ue(1080).bits(1, 1); // id, chroma, size, window
+        w.ue(u32::MAX - 1).ue(u32::MAX - 1).ue(0).ue(0);
+        w.ue(0);
+        assert!(parse_sps(Codec::H265, &w.finish()).is_none());
+    }
+}
@@ -0,0 +1,439 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+use zenoh::bytes::ZBytes;
+
+use crate::cdr::{self, Schema};
+use crate::mcap::{self, McapFile, Message, Payloads};
+use crate::nal;
+use crate::queue::FrameSender;
+use crate::zenoh_sub;
+
+/// Longest sleep between checks of the playback controls.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// How far before a seek target to look for a keyframe to start decoding at.
+const MAX_SEEK_PREROLL: Duration = Duration::from_secs(30);
+
+/// Playback speeds offered by the GUI.
+pub const SPEEDS: [f32; 6] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0];
+
+/// Shared playback controls: the GUI writes them, the player thread follows.
+pub struct Playback {
+    /// Log time of the first and last message (ns since the UNIX epoch).
+    start_ns: u64,
+    end_ns: u64,
+    state: Mutex<State>,
+}
+
+struct State {
+    paused: bool,
+    speed: f32,
+    /// Log time of the last message sent to a decoder.
+    position_ns: u64,
+    /// Pending seek target, taken by the player thread.
+    seek_ns: Option<u64>,
+}
+
+impl Playback {
+    fn new(start_ns: u64, end_ns: u64) -> Self {
+        Self {
+            start_ns,
+            end_ns,
+            state: Mutex::new(State {
+                paused: false,
+                speed: 1.0,
+                position_ns: start_ns,
+                seek_ns: None,
+            }),
+        }
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::from_nanos(self.end_ns - self.start_ns)
+    }
+
+    /// Current position relative to the start of the file.
+    pub fn position(&self) -> Duration {
+        let state = self.state.lock().unwrap();
+        Duration::from_nanos(state.position_ns.saturating_sub(self.start_ns))
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.state.lock().unwrap().paused
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        self.state.lock().unwrap().paused = paused;
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.state.lock().unwrap().speed
+    }
+
+    pub fn set_speed(&self, speed: f32) {
+        self.state.lock().unwrap().speed = speed;
+    }
+
+    /// Jump to `position` relative to the start of the file.
+    pub fn seek(&self, position: Duration) {
+        let target = self.start_ns + (position.as_nanos() as u64).min(self.end_ns - self.start_ns);
+        let mut state = self.state.lock().unwrap();
+        state.seek_ns = Some(target);
+        state.position_ns = target;
+    }
+}
+
+/// Spawn a thread that replays `file` in real time (scaled by the playback
+/// speed), sending each channel's messages to its entry in `outputs`.
+///
+/// Messages go through the same payload decoding as live samples, with the
+/// same `schema`; with [`Schema::Auto`], a channel recorded with a known
+/// MCAP schema is read as that layout. Their timing is rebuilt from the recorded log and publish
+/// times, so the latency charts show the latency at recording time. Each
+/// payload is read from the file when its message is due.
+pub fn spawn(file: McapFile, outputs: HashMap<u16, FrameSender>, schema: Schema) -> Arc<Playback> {
+    let McapFile {
+        channels,
+        messages,
+        payloads,
+    } = file;
+    let messages: Vec<Message> = messages
+        .into_iter()
+        .filter(|m| outputs.contains_key(&m.channel_id))
+        .collect();
+    let topics: HashMap<u16, (String, Schema)> = channels
+        .into_iter()
+        .map(|(id, channel)| {
+            let recorded = channel
+                .schema_name
+                .as_deref()
+                .and_then(mcap::schema_by_name);
+            let schema = match (schema, recorded) {
+                (Schema::Auto, Some(recorded)) => recorded,
+                _ => schema,
+            };
+            (id, (channel.topic, schema))
+        })
+        .collect();
+
+    let start_ns = messages.first().map_or(0, |m| m.log_time);
+    let end_ns = messages.last().map_or(start_ns, |m| m.log_time);
+    let playback = Arc::new(Playback::new(start_ns, end_ns));
+
+    let control = playback.clone();
+    std::thread::spawn(move || run(&messages, payloads, &topics, outputs, &control));
+
+    playback
+}
+
+fn run(
+    messages: &[Message],
+    mut payloads: Payloads,
+    topics: &HashMap<u16, (String, Schema)>,
+    mut outputs: HashMap<u16, FrameSender>,
+    control: &Playback,
+) {
+    let mut counts: HashMap<u16, u64> = HashMap::new();
+    let mut next = 0;
+    let mut pacer = Pacer::default();
+    // After a seek, messages before this log time are sent unpaced so the
+    // decoders catch up from the preceding keyframe.
+    let mut catch_up_until = 0;
+
+    while !outputs.is_empty() {
+        let (paused, speed, seek, position) = {
+            let mut state = control.state.lock().unwrap();
+            (
+                state.paused,
+                state.speed,
+                state.seek_ns.take(),
+                state.position_ns,
+            )
+        };
+
+        if let Some(target) = seek {
+            let index = messages.partition_point(|m| m.log_time < target);
+            next = keyframe_before(messages, index, outputs.len(), |msg| {
+                let schema = topics.get(&msg.channel_id).map_or(Schema::Auto, |t| t.1);
+                payloads.read(msg).is_ok_and(|data| {
+                    cdr::decode_message(&data, schema).is_ok_and(|message| {
+                        message
+                            .codec()
+                            .is_some_and(|codec| nal::is_keyframe(codec, message.data()))
+                    })
+                })
+            });
+            catch_up_until = target;
+            pacer.reset();
+        }
+
+        if next >= messages.len() && !paused {
+            println!("Playback finished");
+            control.set_paused(true);
+        }
+        // Finish a seek while paused so the target frame is shown.
+        let catching_up = messages
+            .get(next)
+            .is_some_and(|m| m.log_time < catch_up_until);
+        if (paused && !catching_up) || next >= messages.len() {
+            pacer.reset();
+            std::thread::sleep(POLL_INTERVAL);
+            continue;
+        }
+
+        let msg = &messages[next];
+        if msg.log_time >= catch_up_until {
+            let now = Instant::now();
+            let due = pacer.due(msg.log_time, position, speed, now);
+            if due > now {
+                std::thread::sleep((due - now).min(POLL_INTERVAL));
+                continue;
+            }
+        }
+
+        let count = counts.entry(msg.channel_id).or_default();
+        *count += 1;
+        let (topic, schema) = topics
+            .get(&msg.channel_id)
+            .map_or(("", Schema::Auto), |(topic, schema)| {
+                (topic.as_str(), *schema)
+            });
+        let hlc = (msg.publish_time != msg.log_time)
+            .then(|| UNIX_EPOCH + Duration::from_nanos(msg.publish_time));
+        let data = match payloads.read(msg) {
+            Ok(data) => Some(data),
+            Err(e) => {
+                eprintln!("[{topic}] Cannot read message #{count}: {e:#}");
+                None
+            }
+        };
+        // Channels whose decoder is gone (window closed) have no output.
+        if let Some(tx) = outputs.get(&msg.channel_id)
+            && let Some(data) = data
+            && let Some(mut frame) = zenoh_sub::decode_payload(
+                topic,
+                *count,
+                topic,
+                &ZBytes::from(data),
+                hlc,
+                schema,
+                &tx.decode_errors(),
+            )
+        {
+            frame.timing.received_wall = UNIX_EPOCH + Duration::from_nanos(msg.log_time);
+            if tx.send(frame).is_err() {
+                outputs.remove(&msg.channel_id);
+            }
+        }
+
+        let mut state = control.state.lock().unwrap();
+        if state.seek_ns.is_none() {
+            state.position_ns = msg.log_time;
+        }
+        next += 1;
+    }
+}
+
+/// Maps log times onto the wall clock at the playback speed.
+#[derive(Debug, Default)]
+struct Pacer {
+    /// Wall time, log time and speed that pacing is measured from.
+    anchor: Option<(Instant, u64, f32)>,
+}
+
+impl Pacer {
+    /// Start over from the next message, for when the timeline is
+    /// interrupted by a pause or a seek.
+    fn reset(&mut self) {
+        self.anchor = None;
+    }
+
+    /// When the message logged at `log_time` is due at `speed`, given that
+    /// `position` is the log time of the last message sent. The first
+    /// message after a reset is due `now`.
+    fn due(&mut self, log_time: u64, position: u64, speed: f32, now: Instant) -> Instant {
+        let (wall, media, anchored_speed) = *self.anchor.get_or_insert((now, log_time, speed));
+        if anchored_speed != speed {
+            // Keep the current position, continue at the new rate.
+            self.anchor = Some((now, position.max(media), speed));
+            return self.due(log_time, position, speed, now);
+        }
+        let offset = Duration::from_nanos(log_time.saturating_sub(media));
+        wall + offset.div_f32(speed)
+    }
+}
+
+/// Index to resume from so each of the `channels` starts decoding at a
+/// keyframe at or before `index`. Looks back at most `MAX_SEEK_PREROLL`.
+fn keyframe_before(
+    messages: &[Message],
+    index: usize,
+    channels: usize,
+    mut is_keyframe: impl FnMut(&Message) -> bool,
+) -> usize {
+    let Some(target) = messages.get(index).or(messages.last()) else {
+        return 0;
+    };
+    let limit = target
+        .log_time
+        .saturating_sub(MAX_SEEK_PREROLL.as_nanos() as u64);
+
+    let mut start = index;
+    let mut found = HashSet::new();
+    for (i, msg) in messages[..(index + 1).min(messages.len())]
+        .iter()
+        .enumerate()
+        .rev()
+    {
+        if msg.log_time < limit || found.len() == channels {
+            break;
+        }
+        if found.contains(&msg.channel_id) {
+            continue;
+        }
+        if is_keyframe(msg) {
+            found.insert(msg.channel_id);
+            start = i;
+        }
+    }
+    start
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: u64 = 1_000_000;
+    /// Log time of the first message, a wall clock time as recorded.
+    const T0: u64 = 1_700_000_000_000 * MS;
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    /// `due` equals `expected` up to the rounding of the speed division.
+    #[track_caller]
+    fn assert_due(due: Instant, expected: Instant) {
+        let error = due.max(expected) - due.min(expected);
+        assert!(error < Duration::from_micros(1), "{due:?} != {expected:?}");
+    }
+
+    #[test]
+    fn pacing_follows_log_time_at_speed() {
+        let now = Instant::now();
+        let mut pacer = Pacer::default();
+        assert_due(pacer.due(T0, T0, 1.0, now), now);
+        assert_due(pacer.due(T0 + 100 * MS, T0, 1.0, now), now + ms(100));
+        // Late calls do not move the schedule.
+        assert_due(
+            pacer.due(T0 + 200 * MS, T0, 1.0, now + ms(500)),
+            now + ms(200),
+        );
+
+        let mut pacer = Pacer::default();
+        assert_due(pacer.due(T0, T0, 4.0, now), now);
+        assert_due(pacer.due(T0 + 100 * MS, T0, 4.0, now), now + ms(25));
+    }
+
+    #[test]
+    fn speed_change_keeps_the_position() {
+        let now = Instant::now();
+        let mut pacer = Pacer::default();
+        pacer.due(T0, T0, 1.0, now);
+        pacer.due(T0 + 100 * MS, T0, 1.0, now);
+        // Doubled 150 ms in, with the message at 100 ms sent.
+        let later = now + ms(150);
+        assert_due(
+            pacer.due(T0 + 200 * MS, T0 + 100 * MS, 2.0, later),
+            later + ms(50),
+        );
+        assert_due(
+            pacer.due(T0 + 300 * MS, T0 + 200 * MS, 2.0, later),
+            later + ms(100),
+        );
+    }
+
+    #[test]
+    fn reset_restarts_from_the_next_message() {
+        let now = Instant::now();
+        let mut pacer = Pacer::default();
+        pacer.due(T0, T0, 1.0, now);
+        pacer.reset();
+        // After a pause or a seek far ahead, nothing is due in the past or
+        // far in the future.
+        let later = now + ms(2000);
+        assert_due(pacer.due(T0 + 60_000 * MS, T0, 1.0, later), later);
+        assert_due(
+            pacer.due(T0 + 60_040 * MS, T0 + 60_000 * MS, 1.0, later),
+            later + ms(40),
+        );
+    }
+
+    /// Messages of `channels` channels, one every 40 ms in turn, with a
+    /// keyframe every `gop` messages of each channel.
+    fn stream(len: u64, channels: u16, gop: u64) -> (Vec<Message>, HashSet<u64>) {
+        let messages: Vec<_> = (0..len)
+            .map(|i| Message::at((i % channels as u64) as u16 + 1, T0 + i * 40 * MS))
+            .collect();
+        let keyframes = (0..len)
+            .filter(|i| (i / channels as u64).is_multiple_of(gop))
+            .collect();
+        (messages, keyframes)
+    }
+
+    fn seek(messages: &[Message], keyframes: &HashSet<u64>, channels: usize, target: u64) -> usize {
+        let index = messages.partition_point(|m| m.log_time < target);
+        keyframe_before(messages, index, channels, |msg| {
+            keyframes.contains(&((msg.log_time - T0) / (40 * MS)))
+        })
+    }
+
+    #[test]
+    fn seek_starts_at_the_previous_keyframe() {
+        let (messages, keyframes) = stream(100, 1, 10);
+        assert_eq!(seek(&messages, &keyframes, 1, T0 + 37 * 40 * MS), 30);
+        // A keyframe at the target itself.
+        assert_eq!(seek(&messages, &keyframes, 1, T0 + 40 * 40 * MS), 40);
+        assert_eq!(seek(&messages, &keyframes, 1, T0), 0);
+        // Past the end: from the last keyframe.
+        assert_eq!(seek(&messages, &keyframes, 1, T0 + 3_600_000 * MS), 90);
+        assert_eq!(seek(&[], &keyframes, 1, T0), 0);
+    }
+
+    #[test]
+    fn seek_waits_for_every_channel() {
+        // Keyframes at 0-1, 20-21, 40-41, ...; channel 2's first one after
+        // index 40 is cut off by the target.
+        let (messages, mut keyframes) = stream(100, 2, 10);
+        assert_eq!(seek(&messages, &keyframes, 2, T0 + 45 * 40 * MS), 40);
+        assert_eq!(seek(&messages, &keyframes, 2, T0 + 41 * 40 * MS), 40);
+        assert_eq!(seek(&messages, &keyframes, 2, T0 + 40 * 40 * MS), 21);
+        // Channel 2 without keyframes: it waits for one once playing.
+        keyframes.retain(|i| i % 2 == 0);
+        assert_eq!(seek(&messages, &keyframes, 2, T0 + 45 * 40 * MS), 40);
+    }
+
+    #[test]
+    fn seek_preroll_is_bounded() {
+        // One keyframe, 40 s before the target.
+        let (messages, keyframes) = stream(1500, 1, 1500);
+        let target = T0 + 1000 * 40 * MS;
+        assert_eq!(seek(&messages, &keyframes, 1, target), 1000);
+        assert_eq!(seek(&messages, &keyframes, 1, T0 + 700 * 40 * MS), 0);
+    }
+
+    #[test]
+    fn seek_position_is_clamped() {
+        let playback = Playback::new(T0, T0 + 10_000 * MS);
+        playback.seek(ms(4000));
+        assert_eq!(playback.position(), ms(4000));
+        assert_eq!(
+            playback.state.lock().unwrap().seek_ns.take(),
+            Some(T0 + 4000 * MS)
+        );
+        playback.seek(ms(60_000));
+        assert_eq!(playback.position(), playback.duration());
+    }
+}
@@ -0,0 +1,426 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{RecvError, RecvTimeoutError, TryRecvError};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::cdr::DecodeErrors;
+use crate::frame::EncodedFrame;
+use crate::metrics::{METRICS, StreamName};
+use crate::nal;
+
+/// What a full queue does with the next frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum DropPolicy {
+    /// Wait for the decoder; nothing is lost, but the publisher side backs
+    /// up instead.
+    Block,
+    /// Drop the oldest frames, up to the next queued keyframe. Without one,
+    /// drop the whole queue and skip frames until a keyframe arrives.
+    DropOldest,
+    /// Drop the new frame and, since later frames reference it, every frame
+    /// until the next keyframe.
+    DropNewest,
+}
+
+/// Count of frames a queue dropped, readable from anywhere.
+#[derive(Debug, Clone, Default)]
+pub struct Drops(Arc<AtomicU64>);
+
+impl Drops {
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+struct Shared {
+    name: StreamName,
+    capacity: usize,
+    policy: DropPolicy,
+    state: Mutex<State>,
+    /// Signalled when a frame is queued or the last sender leaves.
+    not_empty: Condvar,
+    /// Signalled when a frame is taken or the receiver leaves.
+    not_full: Condvar,
+    drops: Drops,
+    /// Counted by the source, for payloads that never became a frame.
+    decode_errors: DecodeErrors,
+}
+
+struct State {
+    frames: VecDeque<Queued>,
+    senders: usize,
+    receiver: bool,
+    /// Frames are dropped until the next keyframe.
+    skipping: bool,
+}
+
+/// A queued frame and whether it is a keyframe, worked out once on send.
+struct Queued {
+    frame: EncodedFrame,
+    keyframe: bool,
+}
+
+impl Shared {
+    fn count_drops(&self, n: usize) {
+        self.drops.0.fetch_add(n as u64, Ordering::Relaxed);
+        METRICS
+            .dropped
+            .with_label_values(&[&self.name.get(), "queue"])
+            .inc_by(n as u64);
+    }
+}
+
+/// A bounded frame queue between a source and a decoder, replacing an
+/// unbounded `mpsc::channel` so a slow decoder cannot grow memory and
+/// latency without limit. `name` labels the drop metrics.
+pub fn bounded(
+    name: impl Into<StreamName>,
+    capacity: usize,
+    policy: DropPolicy,
+) -> (FrameSender, FrameReceiver) {
+    let shared = Arc::new(Shared {
+        name: name.into(),
+        capacity: capacity.max(1),
+        policy,
+        state: Mutex::new(State {
+            frames: VecDeque::with_capacity(capacity),
+            senders: 1,
+            receiver: true,
+            skipping: false,
+        }),
+        not_empty: Condvar::new(),
+        not_full: Condvar::new(),
+        drops: Drops::default(),
+        decode_errors: DecodeErrors::default(),
+    });
+    (
+        FrameSender {
+            shared: shared.clone(),
+        },
+        FrameReceiver { shared },
+    )
+}
+
+/// The receiving half of a queue is gone; the frame was discarded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Disconnected;
+
+/// Sending half of [`bounded`]; cloneable like `mpsc::Sender`.
+pub struct FrameSender {
+    shared: Arc<Shared>,
+}
+
+impl FrameSender {
+    /// Queue `frame` according to the drop policy. Fails only when the
+    /// receiver is gone; dropped frames are counted, not errors.
+    pub fn send(&self, frame: EncodedFrame) -> Result<(), Disconnected> {
+        let shared = &*self.shared;
+        // Scanned here, not under the lock the decoder needs.
+        let keyframe = nal::is_keyframe(frame.codec, &frame.data);
+        let mut state = shared.state.lock().unwrap();
+        if !state.receiver {
+            return Err(Disconnected);
+        }
+
+        if state.skipping {
+            if !keyframe {
+                drop(state);
+                shared.count_drops(1);
+                return Ok(());
+            }
+            state.skipping = false;
+        }
+
+        if state.frames.len() >= shared.capacity {
+            match shared.policy {
+                DropPolicy::Block => {
+                    state = shared
+                        .not_full
+                        .wait_while(state, |s| s.receiver && s.frames.len() >= shared.capacity)
+                        .unwrap();
+                    if !state.receiver {
+                        return Err(Disconnected);
+                    }
+                }
+                DropPolicy::DropOldest => {
+                    // Keep the queue decodable: it must start at a keyframe.
+                    let next_keyframe = state
+                        .frames
+                        .iter()
+                        .skip(1)
+                        .position(|queued| queued.keyframe)
+                        .map(|i| i + 1);
+                    let n = match next_keyframe {
+                        Some(i) => i,
+                        None => {
+                            state.skipping = !keyframe;
+                            state.frames.len()
+                        }
+                    };
+                    state.frames.drain(..n);
+                    shared.count_drops(n);
+                    if state.skipping {
+                        drop(state);
+                        shared.count_drops(1);
+                        return Ok(());
+                    }
+                }
+                DropPolicy::DropNewest => {
+                    state.skipping = true;
+                    drop(state);
+                    shared.count_drops(1);
+                    return Ok(());
+                }
+            }
+        }
+
+        state.frames.push_back(Queued { frame, keyframe });
+        drop(state);
+        shared.not_empty.notify_one();
+        Ok(())
+    }
+
+    /// Whether [`send`](Self::send) may wait for the decoder.
+    pub fn may_block(&self) -> bool {
+        self.shared.policy == DropPolicy::Block
+    }
+
+    /// Frames this queue has dropped so far.
+    pub fn drops(&self) -> Drops {
+        self.shared.drops.clone()
+    }
+
+    /// Payloads of this stream that failed to decode, for the source to
+    /// count into and the display to show.
+    pub fn decode_errors(&self) -> DecodeErrors {
+        self.shared.decode_errors.clone()
+    }
+}
+
+impl Clone for FrameSender {
+    fn clone(&self) -> Self {
+        self.shared.state.lock().unwrap().senders += 1;
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl Drop for FrameSender {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().senders -= 1;
+        self.shared.not_empty.notify_all();
+    }
+}
+
+/// Receiving half of [`bounded`], with the `mpsc::Receiver` methods the
+/// decoder uses.
+pub struct FrameReceiver {
+    shared: Arc<Shared>,
+}
+
+impl FrameReceiver {
+    pub fn recv(&self) -> Result<EncodedFrame, RecvError> {
+        let state = self.shared.state.lock().unwrap();
+        let mut state = self
+            .shared
+            .not_empty
+            .wait_while(state, |s| s.frames.is_empty() && s.senders > 0)
+            .unwrap();
+        let queued = state.frames.pop_front().ok_or(RecvError)?;
+        drop(state);
+        self.shared.not_full.notify_one();
+        Ok(queued.frame)
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<EncodedFrame, RecvTimeoutError> {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.shared.state.lock().unwrap();
+        loop {
+            if let Some(queued) = state.frames.pop_front() {
+                drop(state);
+                self.shared.not_full.notify_one();
+                return Ok(queued.frame);
+            }
+            if state.senders == 0 {
+                return Err(RecvTimeoutError::Disconnected);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(RecvTimeoutError::Timeout);
+            }
+            state = self
+                .shared
+                .not_empty
+                .wait_timeout(state, deadline - now)
+                .unwrap()
+                .0;
+        }
+    }
+
+    pub fn try_recv(&self) -> Result<EncodedFrame, TryRecvError> {
+        let mut state = self.shared.state.lock().unwrap();
+        match state.frames.pop_front() {
+            Some(queued) => {
+                drop(state);
+                self.shared.not_full.notify_one();
+                Ok(queued.frame)
+            }
+            None if state.senders == 0 => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+}
+
+impl Drop for FrameReceiver {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.receiver = false;
+        state.frames.clear();
+        drop(state);
+        self.shared.not_full.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::Codec;
+    use crate::latency::FrameTiming;
+
+    /// H.264 IDR (with parameter sets) or P frame, numbered by `sequence`.
+    fn frame(sequence: u64, keyframe: bool) -> EncodedFrame {
+        let data = if keyframe {
+            vec![
+                0, 0, 0, 1, 0x67, 0x42, 0, 0, 0, 1, 0x68, 0xce, 0, 0, 0, 1, 0x65, 0x88,
+            ]
+        } else {
+            vec![0, 0, 0, 1, 0x41, 0x9a]
+        };
+        EncodedFrame {
+            data: data.into(),
+            codec: Codec::H264,
+            raw: None,
+            encapsulation: None,
+            frame_id: String::new(),
+            key: "video".to_string(),
+            sequence: Some(sequence),
+            timing: FrameTiming::received(None, None),
+        }
+    }
+
+    /// Send frames numbered from `first`, keyframes where `true`.
+    fn send(tx: &FrameSender, first: u64, keyframes: &[bool]) {
+        for (sequence, &keyframe) in (first..).zip(keyframes) {
+            tx.send(frame(sequence, keyframe)).unwrap();
+        }
+    }
+
+    /// Sequence numbers of the queued frames, emptying the queue.
+    fn drain(rx: &FrameReceiver) -> Vec<u64> {
+        std::iter::from_fn(|| rx.try_recv().ok())
+            .map(|frame| frame.sequence.unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn drop_oldest_drains_to_the_next_keyframe() {
+        let (tx, rx) = bounded("drop-oldest", 4, DropPolicy::DropOldest);
+        send(&tx, 1, &[true, false, true, false, false]);
+        assert_eq!(drain(&rx), [3, 4, 5]);
+        assert_eq!(tx.drops().get(), 2);
+
+        // Everything before the next keyframe goes, queue head included.
+        send(&tx, 6, &[true, false, false, true, false]);
+        assert_eq!(drain(&rx), [9, 10]);
+        assert_eq!(tx.drops().get(), 5);
+    }
+
+    #[test]
+    fn drop_oldest_without_keyframe_skips_until_one() {
+        let (tx, rx) = bounded("drop-oldest-skip", 3, DropPolicy::DropOldest);
+        send(&tx, 1, &[false, false, false]);
+        // Nothing decodable is left: the queue and the new frame go.
+        send(&tx, 4, &[false, false, false]);
+        assert_eq!(tx.drops().get(), 6);
+        assert!(drain(&rx).is_empty());
+        send(&tx, 7, &[true, false]);
+        assert_eq!(drain(&rx), [7, 8]);
+        assert_eq!(tx.drops().get(), 6);
+
+        // A new keyframe replaces an undecodable queue.
+        send(&tx, 9, &[false, false, false, true, false]);
+        assert_eq!(drain(&rx), [12, 13]);
+        assert_eq!(tx.drops().get(), 9);
+    }
+
+    #[test]
+    fn drop_newest_resumes_at_a_keyframe() {
+        let (tx, rx) = bounded("drop-newest", 2, DropPolicy::DropNewest);
+        send(&tx, 1, &[true, false, false]);
+        assert_eq!(tx.drops().get(), 1);
+        // Room again, but frames 4-5 reference the dropped one.
+        assert_eq!(rx.recv().unwrap().sequence, Some(1));
+        send(&tx, 4, &[false, false]);
+        assert_eq!(tx.drops().get(), 3);
+        assert_eq!(drain(&rx), [2]);
+        send(&tx, 6, &[true, false]);
+        assert_eq!(drain(&rx), [6, 7]);
+        assert_eq!(tx.drops().get(), 3);
+    }
+
+    #[test]
+    fn drops_are_labelled_with_the_current_name() {
+        let queue_drops = |name: &str| METRICS.dropped.with_label_values(&[name, "queue"]).get();
+        let name = StreamName::new("relabel-before");
+        let (tx, _rx) = bounded(name.clone(), 1, DropPolicy::DropNewest);
+        send(&tx, 1, &[true, false]);
+        name.set("relabel-after");
+        send(&tx, 3, &[false]);
+        assert_eq!(queue_drops("relabel-before"), 1);
+        assert_eq!(queue_drops("relabel-after"), 1);
+    }
+
+    #[test]
+    fn block_waits_for_recv() {
+        let (tx, rx) = bounded("block", 1, DropPolicy::Block);
+        assert!(tx.may_block());
+        send(&tx, 1, &[true]);
+        let (done_tx, done_rx) = std::sync::mpsc::channel();
+        let sender = std::thread::spawn(move || {
+            let sent = tx.send(frame(2, false));
+            done_tx.send(()).unwrap();
+            (tx, sent)
+        });
+        assert!(done_rx.recv_timeout(Duration::from_millis(50)).is_err());
+        assert_eq!(rx.recv().unwrap().sequence, Some(1));
+        done_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        let (tx, sent) = sender.join().unwrap();
+        assert_eq!(sent, Ok(()));
+        assert_eq!(rx.recv().unwrap().sequence, Some(2));
+        assert_eq!(tx.drops().get(), 0);
+
+        // Queued frames are still delivered after the last sender leaves.
+        send(&tx, 3, &[false]);
+        drop(tx);
+        assert_eq!(rx.recv().unwrap().sequence, Some(3));
+        assert!(rx.recv().is_err());
+        assert_eq!(rx.try_recv().unwrap_err(), TryRecvError::Disconnected);
+    }
+
+    #[test]
+    fn block_fails_when_the_receiver_leaves() {
+        let (tx, rx) = bounded("block-disconnect", 1, DropPolicy::Block);
+        send(&tx, 1, &[true]);
+        let sender = std::thread::spawn(move || tx.send(frame(2, false)));
+        std::thread::sleep(Duration::from_millis(50));
+        drop(rx);
+        assert_eq!(sender.join().unwrap(), Err(Disconnected));
+
+        let (tx, rx) = bounded("block-disconnected", 1, DropPolicy::Block);
+        drop(rx);
+        assert_eq!(tx.send(frame(1, true)), Err(Disconnected));
+        assert_eq!(tx.drops().get(), 0);
+    }
+}
@@ -0,0 +1,238 @@
+use std::path::PathBuf;
+#[cfg(feature = "gstreamer")]
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+#[cfg(feature = "gstreamer")]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "gstreamer")]
+use anyhow::Context;
+#[cfg(feature = "gstreamer")]
+use gstreamer::prelude::*;
+
+#[cfg(feature = "gstreamer")]
+use crate::codec::Codec;
+#[cfg(feature = "gstreamer")]
+use crate::decoder;
+#[cfg(feature = "gstreamer")]
+use crate::nal;
+
+/// How long a recorder may take to write its index after EOS.
+const FINALIZE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Container format for recordings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Container {
+    Mp4,
+    Mkv,
+}
+
+#[cfg(feature = "gstreamer")]
+impl Container {
+    fn muxer(self) -> &'static str {
+        match self {
+            Self::Mp4 => "mp4mux",
+            Self::Mkv => "matroskamux",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Mp4 => "mp4",
+            Self::Mkv => "mkv",
+        }
+    }
+}
+
+/// Recording settings shared by the GUI toggle and every decoder thread.
+///
+/// Decoder threads start or finalize their `Recorder` to follow `enabled`.
+/// Recording needs the `gstreamer` feature; other backends switch it off.
+pub struct Recording {
+    enabled: AtomicBool,
+    pub dir: PathBuf,
+    pub container: Container,
+    /// Start a new file after this many bytes (0 = no limit).
+    pub max_size_bytes: u64,
+    /// Start a new file after this long (zero = no limit).
+    pub max_duration: Duration,
+    /// Recorders currently writing, so shutdown can wait for them.
+    open: AtomicUsize,
+}
+
+impl Recording {
+    pub fn new(
+        enabled: bool,
+        dir: PathBuf,
+        container: Container,
+        max_size_bytes: u64,
+        max_duration: Duration,
+    ) -> Self {
+        Self {
+            enabled: AtomicBool::new(enabled),
+            dir,
+            container,
+            max_size_bytes,
+            max_duration,
+            open: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Stop recording and wait (up to a few seconds) until every open file
+    /// has been finalized. Called on Ctrl+C and when the window closes.
+    pub fn shutdown(&self) {
+        self.set_enabled(false);
+        let deadline = Instant::now() + FINALIZE_TIMEOUT;
+        while self.open.load(Ordering::SeqCst) > 0 && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(20));
+        }
+    }
+}
+
+impl Default for Recording {
+    /// Disabled; MP4 files under `recordings/` without rotation.
+    fn default() -> Self {
+        Self::new(
+            false,
+            PathBuf::from("recordings"),
+            Container::Mp4,
+            0,
+            Duration::ZERO,
+        )
+    }
+}
+
+/// Writes one stream to disk without re-encoding:
+/// appsrc → parser → splitmuxsink (mp4mux / matroskamux).
+///
+/// Dropping the recorder sends EOS and waits for the muxer to finalize the
+/// current file.
+#[cfg(feature = "gstreamer")]
+pub struct Recorder {
+    pipeline: gstreamer::Pipeline,
+    appsrc: gstreamer_app::AppSrc,
+    codec: Codec,
+    /// PTS of the first recorded frame; files start at zero.
+    base_pts_ns: Option<u64>,
+    recording: Arc<Recording>,
+}
+
+#[cfg(feature = "gstreamer")]
+impl Recorder {
+    /// Start recording a `codec` stream; `name` (usually the topic) becomes
+    /// part of the file name.
+    pub fn start(recording: Arc<Recording>, codec: Codec, name: &str) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(&recording.dir)
+            .with_context(|| format!("creating {}", recording.dir.display()))?;
+
+        let stamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let stem: String = name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        let location = recording.dir.join(format!(
+            "{stem}_{stamp}_%05d.{}",
+            recording.container.extension()
+        ));
+
+        let pipeline = gstreamer::Pipeline::new();
+        let appsrc = gstreamer_app::AppSrc::builder()
+            .is_live(true)
+            .format(gstreamer::Format::Time)
+            .caps(&decoder::src_caps(codec))
+            .build();
+        let parse = decoder::make_parser(codec);
+
+        let mut sink = gstreamer::ElementFactory::make("splitmuxsink")
+            .property("location", location.to_string_lossy().to_string())
+            .property("muxer-factory", recording.container.muxer());
+        if recording.max_size_bytes > 0 {
+            sink = sink.property("max-size-bytes", recording.max_size_bytes);
+        }
+        if !recording.max_duration.is_zero() {
+            sink = sink.property("max-size-time", recording.max_duration.as_nanos() as u64);
+        }
+        let sink = sink.build().context("splitmuxsink unavailable")?;
+
+        pipeline.add_many([appsrc.upcast_ref(), &parse, &sink])?;
+        gstreamer::Element::link_many([appsrc.upcast_ref(), &parse, &sink])?;
+        pipeline
+            .set_state(gstreamer::State::Playing)
+            .context("starting recording pipeline")?;
+
+        recording.open.fetch_add(1, Ordering::SeqCst);
+        println!("  Recording {codec} to {}", location.display());
+
+        Ok(Self {
+            pipeline,
+            appsrc,
+            codec,
+            base_pts_ns: None,
+            recording,
+        })
+    }
+
+    /// Record one compressed frame. Frames before the first keyframe are
+    /// skipped so every file starts decodable.
+    pub fn push(&mut self, buffer: &gstreamer::Buffer) -> anyhow::Result<()> {
+        let pts_ns = buffer.pts().map_or(0, |t| t.nseconds());
+        let base_pts_ns = match self.base_pts_ns {
+            Some(base) => base,
+            None => {
+                let map = buffer.map_readable()?;
+                if !nal::is_keyframe(self.codec, &map) {
+                    return Ok(());
+                }
+                *self.base_pts_ns.insert(pts_ns)
+            }
+        };
+
+        // Shallow copy: shares the payload, only the metadata is rewritten.
+        let mut buffer = buffer.copy();
+        buffer.get_mut().expect("fresh copy is writable").set_pts(
+            gstreamer::ClockTime::from_nseconds(pts_ns.saturating_sub(base_pts_ns)),
+        );
+        self.appsrc.push_buffer(buffer)?;
+
+        // Surface muxer / filesink failures (disk full, permissions, ...).
+        let error = self
+            .pipeline
+            .bus()
+            .and_then(|bus| bus.pop_filtered(&[gstreamer::MessageType::Error]));
+        if let Some(msg) = error
+            && let gstreamer::MessageView::Error(err) = msg.view()
+        {
+            anyhow::bail!("{}", err.error());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "gstreamer")]
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        // EOS makes the muxer write its index (mp4 moov / mkv cues).
+        let _ = self.appsrc.end_of_stream();
+        if let Some(bus) = self.pipeline.bus() {
+            let _ = bus.timed_pop_filtered(
+                gstreamer::ClockTime::from_nseconds(FINALIZE_TIMEOUT.as_nanos() as u64),
+                &[gstreamer::MessageType::Eos, gstreamer::MessageType::Error],
+            );
+        }
+        let _ = self.pipeline.set_state(gstreamer::State::Null);
+        self.recording.open.fetch_sub(1, Ordering::SeqCst);
+        println!("  Recording finalized");
+    }
+}
@@ -0,0 +1,118 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Wait this long after the last window size change before renegotiating,
+/// so dragging a window edge does not rebuild the caps on every frame.
+const RESIZE_SETTLE: Duration = Duration::from_millis(200);
+
+/// How decoded frames are sized before they reach the display.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Scaling {
+    /// Keep the source resolution.
+    Native,
+    /// Scale down to fit a fixed maximum size.
+    Max,
+    /// Scale down to fit the area the video is shown in.
+    Window,
+}
+
+impl std::fmt::Display for Scaling {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Native => "Native",
+            Self::Max => "Max size",
+            Self::Window => "Fit window",
+        })
+    }
+}
+
+/// Output size of one decoder, adjustable while it runs.
+///
+/// The GUI sets the scaling mode and reports the size of the video area;
+/// the decoder polls [`limit`](Self::limit) and renegotiates its scaler
+/// when it changes. Frames are only ever scaled down, keeping their aspect
+/// ratio.
+#[derive(Debug, Clone)]
+pub struct OutputSize(Arc<Mutex<State>>);
+
+#[derive(Debug)]
+struct State {
+    scaling: Scaling,
+    max: (u32, u32),
+    /// Video area in physical pixels, once known.
+    window: Option<(u32, u32)>,
+    window_changed: Instant,
+    /// Last window size that stayed unchanged for [`RESIZE_SETTLE`].
+    settled_window: Option<(u32, u32)>,
+}
+
+impl OutputSize {
+    /// `max` is the bound for [`Scaling::Max`].
+    pub fn new(scaling: Scaling, max: (u32, u32)) -> Self {
+        Self(Arc::new(Mutex::new(State {
+            scaling,
+            max,
+            window: None,
+            window_changed: Instant::now(),
+            settled_window: None,
+        })))
+    }
+
+    pub fn scaling(&self) -> Scaling {
+        self.0.lock().unwrap().scaling
+    }
+
+    pub fn set_scaling(&self, scaling: Scaling) {
+        self.0.lock().unwrap().scaling = scaling;
+    }
+
+    /// Report the size of the area the video is shown in, in pixels.
+    pub fn set_window(&self, width: u32, height: u32) {
+        let mut state = self.0.lock().unwrap();
+        let size = Some((width.max(1), height.max(1)));
+        if state.window != size {
+            state.window = size;
+            state.window_changed = Instant::now();
+        }
+    }
+
+    /// Largest output size the decoder should produce, `None` for the
+    /// source size. A new window size is only used once it has settled;
+    /// until the first one is reported, frames keep their source size.
+    pub fn limit(&self) -> Option<(u32, u32)> {
+        let mut state = self.0.lock().unwrap();
+        if state.window_changed.elapsed() >= RESIZE_SETTLE {
+            state.settled_window = state.window;
+        }
+        match state.scaling {
+            Scaling::Native => None,
+            Scaling::Max => Some(state.max),
+            Scaling::Window => state.settled_window,
+        }
+    }
+
+    /// Size to scale a `width`×`height` frame to under [`limit`](Self::limit)
+    /// when the decoder scales in software.
+    pub fn fit(&self, width: u32, height: u32) -> (u32, u32) {
+        let Some((max_width, max_height)) = self.limit() else {
+            return (width, height);
+        };
+        let scale = f64::min(
+            max_width as f64 / width.max(1) as f64,
+            max_height as f64 / height.max(1) as f64,
+        );
+        if scale >= 1.0 {
+            return (width, height);
+        }
+        (
+            ((width as f64 * scale) as u32).max(1),
+            ((height as f64 * scale) as u32).max(1),
+        )
+    }
+}
+
+impl Default for OutputSize {
+    fn default() -> Self {
+        Self::new(Scaling::Native, (u32::MAX, u32::MAX))
+    }
+}
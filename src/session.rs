@@ -0,0 +1,255 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+/// Role of the player in the Zenoh network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Mode {
+    /// Talk to other peers directly, e.g. over a tether.
+    Peer,
+    /// Go through a router.
+    Client,
+}
+
+impl Mode {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Peer => "peer",
+            Self::Client => "client",
+        }
+    }
+}
+
+/// How the player joins the Zenoh network.
+///
+/// Starts from the Zenoh defaults, or from `file` if set; every other field
+/// that is set overrides the corresponding file value.
+#[derive(Debug, Clone, Default)]
+pub struct SessionConfig {
+    /// JSON5 Zenoh configuration file.
+    pub file: Option<PathBuf>,
+    pub mode: Option<Mode>,
+    /// Endpoints to connect to (e.g. `tcp/192.168.1.10:7447`).
+    pub connect: Vec<String>,
+    /// Endpoints to accept connections on.
+    pub listen: Vec<String>,
+    /// Enable or disable multicast scouting.
+    pub multicast_scouting: Option<bool>,
+    /// CA certificate (PEM) that `tls/` and `quic/` endpoints are verified
+    /// against.
+    pub tls_root_ca: Option<PathBuf>,
+    /// Client certificate and private key (PEM) for mutual TLS; both or
+    /// neither.
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+    /// User and password for Zenoh's user/password authentication; both or
+    /// neither.
+    pub user: Option<String>,
+    pub password: Option<String>,
+}
+
+impl SessionConfig {
+    /// Session connecting to a single endpoint, otherwise the Zenoh defaults.
+    pub fn endpoint(endpoint: impl Into<String>) -> Self {
+        Self {
+            connect: vec![endpoint.into()],
+            ..Self::default()
+        }
+    }
+
+    /// Build the Zenoh configuration.
+    pub fn to_config(&self) -> anyhow::Result<zenoh::Config> {
+        let mut config = match &self.file {
+            Some(path) => zenoh::Config::from_file(path)
+                .map_err(|e| anyhow::anyhow!("Cannot load {}: {e}", path.display()))?,
+            None => zenoh::Config::default(),
+        };
+        let mut set = |key: &str, value: String| {
+            config
+                .insert_json5(key, &value)
+                .map_err(|e| anyhow::anyhow!("Invalid {key} {value}: {e}"))
+        };
+        if let Some(mode) = self.mode {
+            set("mode", format!(r#""{}""#, mode.as_str()))?;
+        }
+        if !self.connect.is_empty() {
+            set("connect/endpoints", serde_json::to_string(&self.connect)?)?;
+        }
+        if !self.listen.is_empty() {
+            set("listen/endpoints", serde_json::to_string(&self.listen)?)?;
+        }
+        if let Some(enabled) = self.multicast_scouting {
+            set("scouting/multicast/enabled", enabled.to_string())?;
+        }
+
+        if let Some(ca) = &self.tls_root_ca {
+            set(
+                "transport/link/tls/root_ca_certificate",
+                pem_path(ca, "CA certificate")?,
+            )?;
+        }
+        match (&self.tls_cert, &self.tls_key) {
+            (Some(cert), Some(key)) => {
+                set("transport/link/tls/enable_mtls", "true".to_string())?;
+                set(
+                    "transport/link/tls/connect_certificate",
+                    pem_path(cert, "client certificate")?,
+                )?;
+                set(
+                    "transport/link/tls/connect_private_key",
+                    pem_path(key, "client key")?,
+                )?;
+            }
+            (None, None) => {}
+            _ => anyhow::bail!("Mutual TLS needs both a client certificate and a key"),
+        }
+        match (&self.user, &self.password) {
+            (Some(user), Some(password)) => {
+                set("transport/auth/usrpwd/user", serde_json::to_string(user)?)?;
+                set(
+                    "transport/auth/usrpwd/password",
+                    serde_json::to_string(password)?,
+                )?;
+            }
+            (None, None) => {}
+            _ => anyhow::bail!("Zenoh authentication needs both a user and a password"),
+        }
+        Ok(config)
+    }
+
+    /// Whether the session goes through a router, by `mode` or the
+    /// configuration file. False if the configuration cannot be loaded;
+    /// opening the session reports why.
+    pub fn is_client(&self) -> bool {
+        self.to_config()
+            .ok()
+            .and_then(|config| config.get_json("mode").ok())
+            .is_some_and(|mode| mode == r#""client""#)
+    }
+
+    /// Open a session with this configuration.
+    pub async fn open(&self) -> anyhow::Result<zenoh::Session> {
+        let config = self.to_config()?;
+        let tls = uses_tls(&config);
+        let session = zenoh::open(config)
+            .await
+            .map_err(|e| anyhow::anyhow!("{e}"));
+        if tls {
+            // Zenoh reports handshake failures as a generic connect error.
+            session.context(
+                "TLS connection failed; check the endpoint, CA, client certificate and key",
+            )
+        } else {
+            session
+        }
+    }
+}
+
+impl std::fmt::Display for SessionConfig {
+    /// Where the session connects, for log and status messages.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.connect.as_slice(), &self.file) {
+            ([], Some(path)) => write!(f, "{}", path.display()),
+            ([], None) => f.write_str("scouted peers"),
+            (endpoints, _) => f.write_str(&endpoints.join(", ")),
+        }
+    }
+}
+
+/// Whether any connect or listen endpoint of `config`, from the flags or the
+/// configuration file, goes over TLS (`tls/` or `quic/`).
+fn uses_tls(config: &zenoh::Config) -> bool {
+    // Endpoints are a list, or a list per mode.
+    fn any_tls(endpoints: &serde_json::Value) -> bool {
+        match endpoints {
+            serde_json::Value::String(e) => e.starts_with("tls/") || e.starts_with("quic/"),
+            serde_json::Value::Array(list) => list.iter().any(any_tls),
+            serde_json::Value::Object(modes) => modes.values().any(any_tls),
+            _ => false,
+        }
+    }
+    ["connect/endpoints", "listen/endpoints"]
+        .into_iter()
+        .filter_map(|key| config.get_json(key).ok())
+        .filter_map(|json| serde_json::from_str(&json).ok())
+        .any(|endpoints| any_tls(&endpoints))
+}
+
+/// `path` as a JSON string for the Zenoh config, after checking that it is a
+/// readable PEM file. Zenoh only reads it when the first TLS link opens, and
+/// then fails without naming the file.
+fn pem_path(path: &Path, what: &str) -> anyhow::Result<String> {
+    let pem = std::fs::read_to_string(path)
+        .with_context(|| format!("Cannot read {what} {}", path.display()))?;
+    if !pem.contains("-----BEGIN ") {
+        anyhow::bail!("{what} {} is not a PEM file", path.display());
+    }
+    let path = path
+        .to_str()
+        .context("Certificate path is not valid UTF-8")?;
+    Ok(serde_json::to_string(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn client_mode_from_flag_or_file() {
+        assert!(!SessionConfig::default().is_client());
+        let peer = SessionConfig {
+            mode: Some(Mode::Peer),
+            ..SessionConfig::default()
+        };
+        assert!(!peer.is_client());
+        let client = SessionConfig {
+            mode: Some(Mode::Client),
+            ..SessionConfig::default()
+        };
+        assert!(client.is_client());
+
+        let file = std::env::temp_dir().join(format!("zenoh-client-{}.json5", std::process::id()));
+        std::fs::write(&file, r#"{ mode: "client" }"#).unwrap();
+        let from_file = SessionConfig {
+            file: Some(file.clone()),
+            ..SessionConfig::default()
+        };
+        assert!(from_file.is_client());
+        let overridden = SessionConfig {
+            mode: Some(Mode::Peer),
+            ..from_file
+        };
+        assert!(!overridden.is_client());
+        std::fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn tls_from_any_connect_or_listen_endpoint() {
+        let tls = |session: SessionConfig| uses_tls(&session.to_config().unwrap());
+        assert!(!tls(SessionConfig::default()));
+        assert!(!tls(SessionConfig::endpoint("tcp/10.0.0.1:7447")));
+        assert!(tls(SessionConfig::endpoint("tls/router:7447")));
+        assert!(tls(SessionConfig {
+            listen: vec!["quic/0.0.0.0:7447".to_string()],
+            ..SessionConfig::default()
+        }));
+
+        let file = std::env::temp_dir().join(format!("zenoh-tls-{}.json5", std::process::id()));
+        std::fs::write(
+            &file,
+            r#"{ connect: { endpoints: { router: ["tcp/a:7447"], peer: ["tls/b:7447"] } } }"#,
+        )
+        .unwrap();
+        let from_file = SessionConfig {
+            file: Some(file.clone()),
+            ..SessionConfig::default()
+        };
+        assert!(tls(from_file.clone()));
+        let overridden = SessionConfig {
+            connect: vec!["tcp/c:7447".to_string()],
+            ..from_file
+        };
+        assert!(!tls(overridden));
+        std::fs::remove_file(file).unwrap();
+    }
+}
@@ -0,0 +1,181 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::cdr::DecodeErrors;
+use crate::frame::DecodedFrame;
+use crate::latency::Latency;
+use crate::metrics::METRICS;
+use crate::queue::Drops;
+
+/// Tick interval for updating FPS / speed metrics (250 ms → 4 updates/s).
+const TICK_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Number of samples kept for charts (240 × 250 ms = 60 s of history).
+pub const HISTORY_LEN: usize = 240;
+
+/// Frame counters, FPS / speed / latency metrics and their history for one
+/// stream. Shared by the GUI and headless mode so both report the same
+/// numbers.
+pub struct StreamStats {
+    pub frame_count: u64,
+    /// Frames the stream's ingest queue dropped.
+    pub queue_drops: Drops,
+    /// Payloads of the stream that were not a playable message.
+    pub decode_errors: DecodeErrors,
+    /// Size of the frames shown, after scaling.
+    pub video_width: u32,
+    pub video_height: u32,
+    /// Size the decoder produced, before scaling.
+    pub source_width: u32,
+    pub source_height: u32,
+    /// When the last frame was shown, `None` before the first.
+    pub last_frame_time: Option<Instant>,
+
+    // Metrics sampling
+    tick: Instant,
+    frames_since_tick: u64,
+    bytes_since_tick: u64,
+
+    // FPS
+    pub fps_current: f32,
+    pub fps_history: VecDeque<f32>,
+
+    // Speed (Mbps)
+    pub speed_current: f32,
+    pub speed_history: VecDeque<f32>,
+
+    // Latency of displayed frames (ms)
+    latency_sum_since_tick: Latency,
+    latency_frames_since_tick: u32,
+    pub latency_current: Option<Latency>,
+    pub latency_history: VecDeque<Latency>,
+}
+
+impl Default for StreamStats {
+    fn default() -> Self {
+        Self {
+            frame_count: 0,
+            queue_drops: Drops::default(),
+            decode_errors: DecodeErrors::default(),
+            video_width: 0,
+            video_height: 0,
+            source_width: 0,
+            source_height: 0,
+            last_frame_time: None,
+            tick: Instant::now(),
+            frames_since_tick: 0,
+            bytes_since_tick: 0,
+            fps_current: 0.0,
+            fps_history: VecDeque::with_capacity(HISTORY_LEN),
+            speed_current: 0.0,
+            speed_history: VecDeque::with_capacity(HISTORY_LEN),
+            latency_sum_since_tick: Latency::default(),
+            latency_frames_since_tick: 0,
+            latency_current: None,
+            latency_history: VecDeque::with_capacity(HISTORY_LEN),
+        }
+    }
+}
+
+impl StreamStats {
+    /// Stats of a stream whose ingest queue counts its drops in
+    /// `queue_drops`, and whose source counts undecodable payloads in
+    /// `decode_errors`.
+    pub fn new(queue_drops: Drops, decode_errors: DecodeErrors) -> Self {
+        Self {
+            queue_drops,
+            decode_errors,
+            ..Self::default()
+        }
+    }
+
+    /// Count one decoded frame of `compressed_size` input bytes.
+    pub fn frame_decoded(&mut self, compressed_size: usize) {
+        self.frame_count += 1;
+        self.frames_since_tick += 1;
+        self.bytes_since_tick += compressed_size as u64;
+    }
+
+    /// Record that `frame` was shown now.
+    pub fn frame_shown(&mut self, frame: &DecodedFrame) {
+        let now = Instant::now();
+        self.video_width = frame.width;
+        self.video_height = frame.height;
+        self.source_width = frame.source_width;
+        self.source_height = frame.source_height;
+        self.last_frame_time = Some(now);
+
+        if let Some(timing) = frame.timing {
+            let latency = timing.latency(now);
+            let sum = &mut self.latency_sum_since_tick;
+            sum.receive_ms = match (sum.receive_ms, latency.receive_ms) {
+                (Some(a), Some(b)) => Some(a + b),
+                (a, b) => a.or(b),
+            };
+            sum.decode_ms += latency.decode_ms;
+            sum.display_ms += latency.display_ms;
+            self.latency_frames_since_tick += 1;
+        }
+    }
+
+    /// Forget the resolution, e.g. after switching topics.
+    pub fn reset_video(&mut self) {
+        self.video_width = 0;
+        self.video_height = 0;
+        self.source_width = 0;
+        self.source_height = 0;
+    }
+
+    /// Update the metrics every `TICK_INTERVAL` (250 ms) and publish them
+    /// under `topic`.
+    pub fn update(&mut self, topic: &str) {
+        let elapsed = self.tick.elapsed();
+        if elapsed < TICK_INTERVAL {
+            return;
+        }
+        let secs = elapsed.as_secs_f32();
+
+        self.fps_current = self.frames_since_tick as f32 / secs;
+        self.speed_current = (self.bytes_since_tick as f32 * 8.0) / (secs * 1_000_000.0); // Mbps
+
+        self.frames_since_tick = 0;
+        self.bytes_since_tick = 0;
+        self.tick = Instant::now();
+
+        if self.fps_history.len() >= HISTORY_LEN {
+            self.fps_history.pop_front();
+        }
+        self.fps_history.push_back(self.fps_current);
+
+        if self.speed_history.len() >= HISTORY_LEN {
+            self.speed_history.pop_front();
+        }
+        self.speed_history.push_back(self.speed_current);
+
+        // Average latency of the frames displayed during this tick; keep
+        // the previous value if none were shown.
+        if self.latency_frames_since_tick > 0 {
+            let n = self.latency_frames_since_tick as f32;
+            let sum = self.latency_sum_since_tick;
+            self.latency_current = Some(Latency {
+                receive_ms: sum.receive_ms.map(|v| v / n),
+                decode_ms: sum.decode_ms / n,
+                display_ms: sum.display_ms / n,
+            });
+            self.latency_sum_since_tick = Latency::default();
+            self.latency_frames_since_tick = 0;
+        }
+        if self.latency_history.len() >= HISTORY_LEN {
+            self.latency_history.pop_front();
+        }
+        self.latency_history
+            .push_back(self.latency_current.unwrap_or_default());
+
+        METRICS.set_stream(
+            topic,
+            self.fps_current,
+            self.speed_current,
+            self.latency_current,
+        );
+    }
+}
@@ -1,60 +1,775 @@
-use std::sync::mpsc;
-
-use crate::cdr;
-
-/// Spawn a background thread that subscribes to a Zenoh topic and forwards
-/// decoded H.264 data through the provided channel.
-pub fn spawn(endpoint: String, topic: String, h264_tx: mpsc::Sender<Vec<u8>>) {
-    std::thread::spawn(move || {
-        let rt = tokio::runtime::Runtime::new().expect("Failed to create tokio runtime");
-        rt.block_on(async {
-            let mut config = zenoh::Config::default();
-            let endpoints_json = format!(r#"["{}"]"#, endpoint);
-            config
-                .insert_json5("connect/endpoints", &endpoints_json)
-                .unwrap();
-            let session = zenoh::open(config)
-                .await
-                .expect("Failed to open zenoh session");
-            let subscriber = session
-                .declare_subscriber(&topic)
-                .await
-                .expect("Failed to declare subscriber");
-            println!("Zenoh subscriber active on '{topic}'");
+use std::collections::{HashMap, HashSet};
+use std::io::BufWriter;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use zenoh::bytes::ZBytes;
+use zenoh::handlers::FifoChannelHandler;
+use zenoh::pubsub::Subscriber;
+use zenoh::sample::Sample;
+
+use crate::cdr::{self, DecodeError, DecodeErrors, Schema};
+use crate::codec::Codec;
+use crate::discovery::{self, Discovered};
+use crate::frame::{EncodedFrame, FrameData};
+use crate::inspect::Inspected;
+use crate::keyframe::{self, KeyframeRequester, RequestMode};
+use crate::latency::FrameTiming;
+use crate::mcap;
+use crate::metrics::METRICS;
+use crate::queue::FrameSender;
+use crate::session::SessionConfig;
+
+/// Give up opening a session after this long and retry.
+const OPEN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// First and longest wait between session attempts.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// How often the session's routes and the subscriber tasks are checked.
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Reopen the session after it has had no route to a router or peer for
+/// this long, in client mode or after a route was lost; Zenoh's own link
+/// retries did not bring it back.
+const REOPEN_AFTER: Duration = Duration::from_secs(15);
+
+/// A connected stream without samples for this long is reported as
+/// [`ConnectionState::NoData`].
+const NO_DATA_AFTER: Duration = Duration::from_secs(3);
+
+/// Minimum time between two keyframe requests for the same stream.
+const KEYFRAME_REQUEST_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Requests from the GUI and the decoders to the Zenoh thread.
+pub enum Command {
+    /// Point stream `index` at a different key expression.
+    Retarget { index: usize, topic: String },
+    /// Start discovery on a key expression, or stop it with `None`.
+    Discover(Option<String>),
+    /// Ask the publisher of stream `index` for a keyframe; see
+    /// [`KeyframeRequester`].
+    RequestKeyframe { index: usize, reason: &'static str },
+}
+
+/// Connection state of one stream.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// Opening the session, or waiting for Zenoh to re-establish a link.
+    #[default]
+    Connecting,
+    /// Connected and samples are arriving.
+    Connected,
+    /// Connected, but no samples on this stream for a few seconds.
+    NoData,
+    /// A peer session that has not found a router or peer yet.
+    WaitingForPeers,
+    /// The last attempt failed; retrying with backoff.
+    Error(String),
+}
+
+impl std::fmt::Display for ConnectionState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Connecting => f.write_str("Connecting..."),
+            Self::Connected => f.write_str("Connected"),
+            Self::NoData => f.write_str("Connected, no data"),
+            Self::WaitingForPeers => f.write_str("Waiting for peers..."),
+            Self::Error(reason) => write!(f, "Error: {reason}"),
+        }
+    }
+}
+
+/// Connection status written by the Zenoh thread and read by the GUI.
+#[derive(Debug, Default)]
+pub struct Connection(Mutex<ConnectionStatus>);
+
+#[derive(Debug, Default)]
+struct ConnectionStatus {
+    session: ConnectionState,
+    /// Arrival of the last sample, by stream index.
+    last_sample: HashMap<usize, Instant>,
+}
+
+impl Connection {
+    /// State of stream `index`: session problems first, then whether its
+    /// samples are arriving.
+    pub fn state(&self, index: usize) -> ConnectionState {
+        let status = self.0.lock().unwrap();
+        match &status.session {
+            ConnectionState::Connected => match status.last_sample.get(&index) {
+                Some(t) if t.elapsed() < NO_DATA_AFTER => ConnectionState::Connected,
+                _ => ConnectionState::NoData,
+            },
+            other => other.clone(),
+        }
+    }
+
+    fn set_session(&self, state: ConnectionState) {
+        self.0.lock().unwrap().session = state;
+    }
+
+    fn sample(&self, index: usize) {
+        self.0
+            .lock()
+            .unwrap()
+            .last_sample
+            .insert(index, Instant::now());
+    }
+
+    fn reset_stream(&self, index: usize) {
+        self.0.lock().unwrap().last_sample.remove(&index);
+    }
+}
+
+/// Builds the subscriber stage: one Zenoh session, one subscriber per topic,
+/// each forwarding [`EncodedFrame`]s through its own channel.
+///
+/// ```ignore
+/// let (frame_tx, frame_rx) = queue::bounded("video/front/stream", 8, DropPolicy::DropOldest);
+/// let zenoh_cmd = SubscriberBuilder::new(SessionConfig::endpoint("tcp/127.0.0.1:7447"))
+///     .topic("video/front/stream", frame_tx)
+///     .spawn();
+/// ```
+pub struct SubscriberBuilder {
+    session: SessionConfig,
+    subscriptions: Vec<(String, FrameSender)>,
+    discovered: Discovered,
+    connection: Arc<Connection>,
+    inspected: Inspected,
+    schema: Schema,
+    keyframe_mode: RequestMode,
+    /// Keyframe request key, with `{topic}` for the stream's key expression.
+    keyframe_key: String,
+    cmd_tx: tokio::sync::mpsc::UnboundedSender<Command>,
+    cmd_rx: tokio::sync::mpsc::UnboundedReceiver<Command>,
+}
+
+impl SubscriberBuilder {
+    pub fn new(session: SessionConfig) -> Self {
+        let (cmd_tx, cmd_rx) = tokio::sync::mpsc::unbounded_channel();
+        Self {
+            session,
+            subscriptions: Vec::new(),
+            discovered: Discovered::default(),
+            connection: Arc::default(),
+            inspected: Inspected::default(),
+            schema: Schema::Auto,
+            keyframe_mode: RequestMode::Query,
+            keyframe_key: keyframe::DEFAULT_KEY.to_string(),
+            cmd_tx,
+            cmd_rx,
+        }
+    }
+
+    /// Forward the samples of `topic` to `frame_tx`. Streams are numbered in
+    /// the order they are added, for [`Command::Retarget`].
+    pub fn topic(mut self, topic: impl Into<String>, frame_tx: FrameSender) -> Self {
+        self.subscriptions.push((topic.into(), frame_tx));
+        self
+    }
+
+    /// Table that [`Command::Discover`] fills in.
+    pub fn discovered(mut self, discovered: Discovered) -> Self {
+        self.discovered = discovered;
+        self
+    }
+
+    /// Status that the subscriber thread keeps up to date.
+    pub fn connection(mut self, connection: Arc<Connection>) -> Self {
+        self.connection = connection;
+        self
+    }
 
-            let mut count: u64 = 0;
-            while let Ok(sample) = subscriber.recv_async().await {
-                let payload = sample.payload().to_bytes();
-                count += 1;
+    /// Inspectors that the subscribers feed, see [`Inspected`].
+    pub fn inspected(mut self, inspected: Inspected) -> Self {
+        self.inspected = inspected;
+        self
+    }
+
+    /// Message layout of the samples (default: detected per message).
+    pub fn schema(mut self, schema: Schema) -> Self {
+        self.schema = schema;
+        self
+    }
+
+    /// How to ask publishers for keyframes, and on which key; `{topic}` in
+    /// `key` is replaced by the stream's key expression. Defaults to a query
+    /// on [`keyframe::DEFAULT_KEY`].
+    pub fn keyframe_requests(mut self, mode: RequestMode, key: impl Into<String>) -> Self {
+        self.keyframe_mode = mode;
+        self.keyframe_key = key.into();
+        self
+    }
+
+    /// Handle for the decoder of stream `index` to request keyframes with.
+    /// Requests are sent once the subscriber thread runs.
+    pub fn keyframe_requester(&self, index: usize) -> KeyframeRequester {
+        KeyframeRequester {
+            index,
+            cmd_tx: self.cmd_tx.clone(),
+        }
+    }
+
+    /// Start the subscribers on a background thread.
+    ///
+    /// All topics share one session; each subscriber is drained by its own
+    /// task. The session is supervised: failures to open it, lost routes
+    /// and closed subscribers are retried with backoff for as long as the
+    /// process runs. The returned sender controls retargeting and discovery.
+    pub fn spawn(self) -> tokio::sync::mpsc::UnboundedSender<Command> {
+        let cmd_tx = self.cmd_tx.clone();
+        std::thread::spawn(move || {
+            let rt = tokio::runtime::Runtime::new().expect("Failed to create tokio runtime");
+            rt.block_on(supervise(self));
+        });
+        cmd_tx
+    }
+}
 
-                if payload.is_empty() {
-                    continue;
+/// One subscribed topic and the task draining it, if subscribed.
+struct Stream {
+    topic: String,
+    frame_tx: FrameSender,
+    task: Option<tokio::task::JoinHandle<()>>,
+    last_keyframe_request: Option<Instant>,
+}
+
+impl Stream {
+    fn abort(&mut self) {
+        // Dropping the aborted task's subscriber undeclares it.
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+    }
+}
+
+/// Keep a session open and every stream subscribed until the process ends.
+async fn supervise(builder: SubscriberBuilder) {
+    let SubscriberBuilder {
+        session: session_config,
+        subscriptions,
+        discovered,
+        connection,
+        inspected,
+        schema,
+        keyframe_mode,
+        keyframe_key,
+        cmd_tx,
+        mut cmd_rx,
+    } = builder;
+    // Only the returned and the requester handles keep the channel open.
+    drop(cmd_tx);
+    let mut streams: Vec<Stream> = subscriptions
+        .into_iter()
+        .map(|(topic, frame_tx)| Stream {
+            topic,
+            frame_tx,
+            task: None,
+            last_keyframe_request: None,
+        })
+        .collect();
+    let mut discover_key: Option<String> = None;
+    let mut commands_open = true;
+    let mut backoff = INITIAL_BACKOFF;
+    // Without a router to connect to, having no route is normal until a peer
+    // shows up.
+    let client = session_config.is_client();
+
+    loop {
+        connection.set_session(ConnectionState::Connecting);
+        let opened = match tokio::time::timeout(OPEN_TIMEOUT, session_config.open()).await {
+            Ok(Ok(session)) => Ok(session),
+            Ok(Err(e)) => Err(format!("{e:#}")),
+            Err(_) => Err(format!("no answer from {session_config}")),
+        };
+        let session = match opened {
+            Ok(session) => session,
+            Err(reason) => {
+                eprintln!("Zenoh session failed ({reason}), retrying in {backoff:?}");
+                connection.set_session(ConnectionState::Error(reason));
+                // Remember retargets and discovery requests for the next session.
+                let retry = tokio::time::sleep(backoff);
+                tokio::pin!(retry);
+                loop {
+                    tokio::select! {
+                        _ = &mut retry => break,
+                        cmd = cmd_rx.recv(), if commands_open => match cmd {
+                            Some(Command::Retarget { index, topic }) => {
+                                if let Some(stream) = streams.get_mut(index) {
+                                    stream.topic = topic;
+                                    connection.reset_stream(index);
+                                }
+                            }
+                            Some(Command::Discover(key_expr)) => discover_key = key_expr,
+                            // Nobody to ask; the next subscribe requests one.
+                            Some(Command::RequestKeyframe { .. }) => {}
+                            None => commands_open = false,
+                        },
+                    }
                 }
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+                continue;
+            }
+        };
+        backoff = INITIAL_BACKOFF;
+        println!("Zenoh session open on {session_config}");
+        connection.set_session(ConnectionState::Connected);
+
+        let mut discovery: Option<tokio::task::JoinHandle<()>> = None;
+        let mut health = tokio::time::interval(HEALTH_CHECK_INTERVAL);
+        let mut unrouted_since: Option<Instant> = None;
+        let mut had_route = false;
 
-                // Decode CDR-encoded foxglove CompressedVideo
-                match cdr::decode_compressed_video(&payload) {
-                    Ok((data, format)) => {
-                        if count % 100 == 1 {
-                            println!(
-                                "Message #{count}: CDR CompressedVideo format={format}, data={} bytes",
-                                data.len()
-                            );
+        loop {
+            tokio::select! {
+                cmd = cmd_rx.recv(), if commands_open => match cmd {
+                    Some(Command::Retarget { index, topic }) => {
+                        let Some(stream) = streams.get_mut(index) else {
+                            continue;
+                        };
+                        stream.abort();
+                        stream.topic = topic;
+                        stream.last_keyframe_request = None;
+                        connection.reset_stream(index);
+                        if let Some(inspector) = inspected.lock().unwrap().get_mut(&index) {
+                            *inspector = Default::default();
+                        }
+                        stream.task =
+                            subscribe(&session, index, stream, &connection, &inspected, schema).await;
+                        if stream.task.is_some() {
+                            request_keyframe(&session, stream, keyframe_mode, &keyframe_key, "subscribe");
                         }
-                        if !data.is_empty() {
-                            let _ = h264_tx.send(data);
+                    }
+                    Some(Command::RequestKeyframe { index, reason }) => {
+                        if let Some(stream) = streams.get_mut(index) {
+                            request_keyframe(&session, stream, keyframe_mode, &keyframe_key, reason);
                         }
                     }
-                    Err(reason) => {
-                        if count % 100 == 1 {
-                            println!(
-                                "Message #{count}: CDR decode failed ({reason}), raw {} bytes",
-                                payload.len()
-                            );
+                    Some(Command::Discover(key_expr)) => {
+                        if let Some(task) = discovery.take() {
+                            task.abort();
+                        }
+                        discover_key = key_expr;
+                        if let Some(key_expr) = &discover_key {
+                            discovered.lock().unwrap().clear();
+                            let run =
+                                discovery::run(session.clone(), key_expr.clone(), discovered.clone());
+                            discovery = Some(tokio::spawn(run));
                         }
-                        let _ = h264_tx.send(payload.to_vec());
+                    }
+                    // The GUI is gone; keep forwarding.
+                    None => commands_open = false,
+                },
+                _ = health.tick() => {
+                    // (Re)subscribe streams that are not, or whose
+                    // subscriber closed.
+                    for (index, stream) in streams.iter_mut().enumerate() {
+                        if stream.task.as_ref().is_none_or(|t| t.is_finished()) {
+                            stream.task =
+                                subscribe(&session, index, stream, &connection, &inspected, schema).await;
+                            // Joining mid-GOP: don't wait for the next keyframe.
+                            if stream.task.is_some() {
+                                request_keyframe(&session, stream, keyframe_mode, &keyframe_key, "subscribe");
+                            }
+                        }
+                    }
+                    if discovery.is_none()
+                        && let Some(key_expr) = &discover_key
+                    {
+                        let run = discovery::run(session.clone(), key_expr.clone(), discovered.clone());
+                        discovery = Some(tokio::spawn(run));
+                    }
+
+                    let routes = session.info().routers_zid().await.count()
+                        + session.info().peers_zid().await.count();
+                    if routes > 0 {
+                        had_route = true;
+                        unrouted_since = None;
+                        connection.set_session(ConnectionState::Connected);
+                        continue;
+                    }
+                    if !client && !had_route {
+                        connection.set_session(ConnectionState::WaitingForPeers);
+                        continue;
+                    }
+                    let since = *unrouted_since.get_or_insert_with(Instant::now);
+                    connection.set_session(ConnectionState::Error(format!(
+                        "lost connection to {session_config}, reconnecting"
+                    )));
+                    if since.elapsed() >= REOPEN_AFTER {
+                        eprintln!("Zenoh: no route for {REOPEN_AFTER:?}, reopening session");
+                        break;
                     }
                 }
             }
-        });
+        }
+
+        for stream in &mut streams {
+            stream.abort();
+        }
+        if let Some(task) = discovery.take() {
+            task.abort();
+        }
+        let _ = session.close().await;
+    }
+}
+
+/// Send a keyframe request for `stream` in the background, unless one went
+/// out less than `KEYFRAME_REQUEST_INTERVAL` ago.
+fn request_keyframe(
+    session: &zenoh::Session,
+    stream: &mut Stream,
+    mode: RequestMode,
+    key_template: &str,
+    reason: &'static str,
+) {
+    if mode == RequestMode::Off
+        || stream
+            .last_keyframe_request
+            .is_some_and(|t| t.elapsed() < KEYFRAME_REQUEST_INTERVAL)
+    {
+        return;
+    }
+    stream.last_keyframe_request = Some(Instant::now());
+    let key = keyframe::request_key(key_template, &stream.topic);
+    tokio::spawn(keyframe::send(
+        session.clone(),
+        mode,
+        key,
+        stream.topic.clone(),
+        reason,
+    ));
+}
+
+/// Declare a subscriber for stream `index` and spawn the task that forwards
+/// its samples. `None` if the subscriber could not be declared; the next
+/// health check retries.
+async fn subscribe(
+    session: &zenoh::Session,
+    index: usize,
+    stream: &Stream,
+    connection: &Arc<Connection>,
+    inspected: &Inspected,
+    schema: Schema,
+) -> Option<tokio::task::JoinHandle<()>> {
+    let topic = stream.topic.clone();
+    match session.declare_subscriber(&topic).await {
+        Ok(subscriber) => {
+            println!("Zenoh subscriber active on '{topic}'");
+            Some(tokio::spawn(forward(
+                topic,
+                index,
+                subscriber,
+                stream.frame_tx.clone(),
+                connection.clone(),
+                inspected.clone(),
+                schema,
+            )))
+        }
+        Err(e) => {
+            eprintln!("Failed to subscribe to '{topic}': {e}");
+            None
+        }
+    }
+}
+
+/// Decode every sample of one subscriber and forward it until the
+/// subscriber closes.
+async fn forward(
+    topic: String,
+    index: usize,
+    subscriber: Subscriber<FifoChannelHandler<Sample>>,
+    frame_tx: FrameSender,
+    connection: Arc<Connection>,
+    inspected: Inspected,
+    schema: Schema,
+) {
+    let decode_errors = frame_tx.decode_errors();
+    let mut count: u64 = 0;
+    while let Ok(sample) = subscriber.recv_async().await {
+        connection.sample(index);
+        let hlc = sample.timestamp().map(|ts| ts.get_time().to_system_time());
+        count += 1;
+        METRICS.received.with_label_values(&[&topic]).inc();
+
+        let key = sample.key_expr().as_str();
+        if let Some(mut frame) = decode_payload(
+            &topic,
+            count,
+            key,
+            sample.payload(),
+            hlc,
+            schema,
+            &decode_errors,
+        ) {
+            frame.sequence = sample
+                .attachment()
+                .and_then(|a| frame_counter(&a.to_bytes()));
+            if let Some(inspector) = inspected.lock().unwrap().get_mut(&index) {
+                inspector.push(&frame);
+            }
+            if frame_tx.may_block() {
+                // Keep the runtime's other tasks running while the decoder
+                // catches up.
+                tokio::task::block_in_place(|| {
+                    let _ = frame_tx.send(frame);
+                });
+            } else {
+                let _ = frame_tx.send(frame);
+            }
+        }
+    }
+}
+
+/// Frame counter from a sample attachment: publishers that number their
+/// frames attach the counter as 8 bytes, little-endian. Other attachments
+/// are ignored.
+fn frame_counter(attachment: &[u8]) -> Option<u64> {
+    Some(u64::from_le_bytes(attachment.try_into().ok()?))
+}
+
+/// Turn one sample payload received on `key` into an [`EncodedFrame`],
+/// logging every 100th message of `topic`. `None` for empty payloads and for
+/// payloads that carry no playable video or image; those are counted in
+/// `errors` by class and never reach the decoder.
+///
+/// CompressedVideo, the bulk of the traffic, is read in place: the frame
+/// shares its data with `payload` instead of copying it.
+///
+/// Shared by live subscribers and MCAP playback.
+pub fn decode_payload(
+    topic: &str,
+    count: u64,
+    key: &str,
+    payload: &ZBytes,
+    hlc: Option<SystemTime>,
+    schema: Schema,
+    errors: &DecodeErrors,
+) -> Option<EncodedFrame> {
+    let bytes = payload.to_bytes();
+    if bytes.is_empty() {
+        return None;
+    }
+    let encapsulation = match schema {
+        Schema::AnnexB => None,
+        _ => cdr::Encapsulation::from_header(&bytes),
+    };
+    let log = |schema: Schema, format: &str, len: usize| {
+        if count % 100 == 1 {
+            let encoding = encapsulation.map_or(String::new(), |e| format!(" ({e})"));
+            println!(
+                "[{topic}] Message #{count}: {schema}{encoding} format={format}, data={len} bytes"
+            );
+        }
+    };
+    let frame = |data: FrameData, codec, raw, frame_id, timestamp: cdr::Timestamp| {
+        if data.is_empty() {
+            return None;
+        }
+        let timestamp = (!timestamp.is_zero()).then_some(timestamp);
+        Some(EncodedFrame {
+            data,
+            codec,
+            raw,
+            encapsulation,
+            frame_id,
+            key: key.to_string(),
+            sequence: None,
+            timing: FrameTiming::received(timestamp, hlc),
+        })
+    };
+
+    if matches!(
+        schema,
+        Schema::Auto | Schema::CompressedVideo | Schema::CompressedImage
+    ) && let Ok(view) = cdr::CompressedVideoView::parse(&bytes)
+        && let Some(codec) = Codec::from_format(view.format)
+    {
+        log(view.schema(), view.format, view.data.len());
+        let data = FrameData::from_payload(payload, view.data);
+        return frame(data, codec, None, view.frame_id.to_string(), view.timestamp);
+    }
+
+    // Any other Foxglove or ROS 2 video / image message
+    let decoded = cdr::decode_message(&bytes, schema).and_then(|message| {
+        let raw = message.raw_layout();
+        let codec = message
+            .codec()
+            .filter(|&codec| codec != Codec::Raw || raw.is_some())
+            .ok_or_else(|| DecodeError::UnknownFormat(message.format().to_string()))?;
+        Ok((message, codec, raw))
     });
+    let (message, codec, raw) = match decoded {
+        Ok(decoded) => decoded,
+        Err(e) => {
+            METRICS
+                .decode_failures
+                .with_label_values(&[topic, e.class()])
+                .inc();
+            errors.count(&e);
+            if count % 100 == 1 {
+                println!(
+                    "[{topic}] Message #{count}: {e} ({} bytes), dropped",
+                    bytes.len()
+                );
+            }
+            return None;
+        }
+    };
+    log(message.schema(), message.format(), message.data().len());
+    let timestamp = message.timestamp();
+    let (frame_id, data) = message.into_parts();
+    frame(data.into(), codec, raw, frame_id, timestamp)
+}
+
+/// Subscribe to `topics` and write every sample, undecoded, to an MCAP file
+/// at `output` until Ctrl+C.
+///
+/// Each Zenoh key gets its own channel, with the MCAP schema of the message
+/// layout `schema` finds in its first playable sample; samples before that
+/// are not recorded. Bare Annex B has no schema and is refused. The log time
+/// is the arrival time; the publish time is the sample's HLC timestamp when
+/// the session adds one, the arrival time otherwise.
+pub fn record_mcap(
+    session: &SessionConfig,
+    topics: &[String],
+    output: &Path,
+    schema: Schema,
+) -> anyhow::Result<()> {
+    if schema == Schema::AnnexB {
+        anyhow::bail!("MCAP channels need a CDR message schema, {schema} cannot be recorded");
+    }
+    let rt = tokio::runtime::Runtime::new().context("Failed to create tokio runtime")?;
+    let file =
+        std::fs::File::create(output).with_context(|| format!("creating {}", output.display()))?;
+    let mut writer = mcap::Writer::new(BufWriter::new(file))?;
+
+    let recorded = rt.block_on(async {
+        let session = session
+            .open()
+            .await
+            .context("Failed to open zenoh session")?;
+
+        // Samples of every subscriber funnel into the single writer.
+        let (sample_tx, mut sample_rx) = tokio::sync::mpsc::unbounded_channel::<Sample>();
+        for topic in topics {
+            let subscriber = session
+                .declare_subscriber(topic)
+                .await
+                .map_err(|e| anyhow::anyhow!("Failed to subscribe to '{topic}': {e}"))?;
+            println!("Recording '{topic}' to {}", output.display());
+            let sample_tx = sample_tx.clone();
+            tokio::spawn(async move {
+                while let Ok(sample) = subscriber.recv_async().await {
+                    if sample_tx.send(sample).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sample_tx);
+
+        let ctrl_c = tokio::signal::ctrl_c();
+        tokio::pin!(ctrl_c);
+        let mut count: u64 = 0;
+        // Message layout of each key, from its first playable sample.
+        let mut layouts: HashMap<String, Schema> = HashMap::new();
+        // Keys without one yet, logged once.
+        let mut unplayable: HashSet<String> = HashSet::new();
+        loop {
+            tokio::select! {
+                sample = sample_rx.recv() => {
+                    let Some(sample) = sample else { break };
+                    let log_time = unix_nanos(SystemTime::now());
+                    let publish_time = sample
+                        .timestamp()
+                        .map_or(log_time, |ts| unix_nanos(ts.get_time().to_system_time()));
+                    let key = sample.key_expr().as_str();
+                    let payload = sample.payload().to_bytes();
+                    let layout = match layouts.get(key) {
+                        Some(&layout) => layout,
+                        None => match cdr::decode_message(&payload, schema) {
+                            Ok(message) => {
+                                println!("[{key}] Recording {} messages", message.schema());
+                                *layouts.entry(key.to_string()).or_insert(message.schema())
+                            }
+                            Err(e) => {
+                                if unplayable.insert(key.to_string()) {
+                                    println!("[{key}] {e}, not recorded until a playable sample");
+                                }
+                                continue;
+                            }
+                        },
+                    };
+                    writer.write(key, layout, log_time, publish_time, &payload)?;
+                    count += 1;
+                    if count % 100 == 1 {
+                        println!("Recorded {count} messages");
+                    }
+                }
+                _ = &mut ctrl_c => break,
+            }
+        }
+        anyhow::Ok(count)
+    });
+
+    // Even when recording failed, end the file so what was written plays.
+    let finished = writer.finish();
+    let count = recorded?;
+    finished?;
+    println!("Wrote {count} messages to {}", output.display());
+    Ok(())
+}
+
+fn unix_nanos(t: SystemTime) -> u64 {
+    t.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const H264: &[u8] = &[0, 0, 0, 1, 0x65, 0x88, 0x84];
+
+    /// A little-endian CDR CompressedVideo message.
+    fn compressed_video(format: &str) -> ZBytes {
+        let mut buf = vec![0, 1, 0, 0, 7, 0, 0, 0, 9, 0, 0, 0];
+        for field in [&b"cam\0"[..], H264, format!("{format}\0").as_bytes()] {
+            buf.resize(4 + (buf.len() - 4).next_multiple_of(4), 0);
+            buf.extend_from_slice(&(field.len() as u32).to_le_bytes());
+            buf.extend_from_slice(field);
+        }
+        ZBytes::from(buf)
+    }
+
+    fn decode(payload: &ZBytes, schema: Schema, errors: &DecodeErrors) -> Option<EncodedFrame> {
+        decode_payload("test", 0, "test", payload, None, schema, errors)
+    }
+
+    #[test]
+    fn drops_unknown_formats() {
+        let errors = DecodeErrors::default();
+        assert!(decode(&compressed_video("theora"), Schema::Auto, &errors).is_none());
+        assert_eq!(errors.get()[3], ("format", 1));
+        assert_eq!(errors.total(), 1);
+    }
+
+    #[test]
+    fn takes_bare_h264_only_as_annex_b() {
+        let errors = DecodeErrors::default();
+        let payload = ZBytes::from(H264);
+        assert!(decode(&payload, Schema::Auto, &errors).is_none());
+        assert!(decode(&payload, Schema::CompressedVideo, &errors).is_none());
+        assert_eq!(errors.total(), 2);
+
+        let frame = decode(&payload, Schema::AnnexB, &errors).unwrap();
+        assert_eq!(frame.codec, Codec::H264);
+        assert_eq!(&*frame.data, H264);
+        assert_eq!(frame.encapsulation, None);
+        assert_eq!(errors.total(), 2);
+    }
 }